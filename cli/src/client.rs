@@ -0,0 +1,87 @@
+use anyhow::{Result, anyhow};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use xnet_core::{GenerationOptions, ModelInfo, NodeMetrics, PeerInfo, SignedEntry, TaskRecord, TaskResult};
+
+/// Talks to a running node over its HTTP API (the control routes only answer on loopback).
+pub struct ApiClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LedgerSummary {
+    pub balance: f64,
+    pub entries: Vec<SignedEntry>,
+}
+
+#[derive(Serialize)]
+struct SubmitTaskRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+    options: &'a GenerationOptions,
+    max_price: Option<f64>,
+}
+
+impl ApiClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self { client: reqwest::Client::new(), base_url: base_url.into().trim_end_matches('/').to_string() }
+    }
+
+    pub async fn peers(&self) -> Result<Vec<PeerInfo>> {
+        self.send(self.client.get(self.url("peers"))).await
+    }
+
+    pub async fn metrics(&self) -> Result<NodeMetrics> {
+        self.send(self.client.get(self.url("metrics"))).await
+    }
+
+    pub async fn ledger(&self) -> Result<LedgerSummary> {
+        self.send(self.client.get(self.url("ledger"))).await
+    }
+
+    pub async fn tasks(&self) -> Result<Vec<TaskRecord>> {
+        self.send(self.client.get(self.url("tasks"))).await
+    }
+
+    /// Publishes a task to the network and waits until it settles.
+    pub async fn submit(&self, model: &str, prompt: &str, options: &GenerationOptions, max_price: Option<f64>) -> Result<TaskResult> {
+        let request = SubmitTaskRequest { model, prompt, options, max_price };
+        self.send(self.client.post(self.url("tasks")).json(&request)).await
+    }
+
+    pub async fn cancel(&self, task_id: &str) -> Result<serde_json::Value> {
+        self.send(self.client.post(self.url(&format!("tasks/{}/cancel", task_id)))).await
+    }
+
+    pub async fn models(&self) -> Result<Vec<ModelInfo>> {
+        self.send(self.client.get(self.url("models"))).await
+    }
+
+    pub async fn set_providing(&self, providing: bool) -> Result<serde_json::Value> {
+        let url = self.url("provider");
+        let request = if providing { self.client.post(url) } else { self.client.delete(url) };
+        self.send(request).await
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/api/v1/{}", self.base_url, path)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let res = request.send().await
+            .map_err(|e| anyhow!("Cannot reach the node at {} (is the daemon running?): {}", self.base_url, e))?;
+        let status = res.status();
+        if !status.is_success() {
+            #[derive(Deserialize)]
+            struct ApiError {
+                error: String,
+            }
+            return match res.json::<ApiError>().await {
+                Ok(body) => Err(anyhow!("Node returned {}: {}", status, body.error)),
+                Err(_) => Err(anyhow!("Node returned {}", status)),
+            };
+        }
+        Ok(res.json().await?)
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{Context, Result, anyhow, bail};
+use clap::Subcommand;
+use libp2p::identity::Keypair;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use xnet_core::{KeyHandover, generate_keypair, is_encrypted, load_identity, load_keypair, save_keypair, write_private};
+
+/// Where the identity key lives and how to unlock it; shared by `daemon` and `key`.
+#[derive(clap::Args)]
+pub struct IdentityArgs {
+    /// Directory for the node store and, by default, the identity key
+    #[arg(long, env = "XNET_DATA_DIR", default_value = "xnet-data")]
+    pub data_dir: PathBuf,
+
+    /// Identity key file [default: <data-dir>/identity.key]
+    #[arg(long, env = "XNET_IDENTITY")]
+    pub identity: Option<PathBuf>,
+
+    /// File holding the key passphrase; XNET_KEY_PASSPHRASE is used if not set
+    #[arg(long, env = "XNET_KEY_PASSPHRASE_FILE")]
+    pub passphrase_file: Option<PathBuf>,
+}
+
+impl IdentityArgs {
+    pub fn key_path(&self) -> PathBuf {
+        self.identity.clone().unwrap_or_else(|| self.data_dir.join(xnet_core::IDENTITY_FILE))
+    }
+
+    pub fn passphrase(&self) -> Result<Option<String>> {
+        if let Some(path) = &self.passphrase_file {
+            let passphrase = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read passphrase file {}", path.display()))?;
+            return Ok(Some(passphrase.trim_end_matches(['\r', '\n']).to_string()));
+        }
+        Ok(std::env::var("XNET_KEY_PASSPHRASE").ok().filter(|p| !p.is_empty()))
+    }
+
+    /// Loads the identity key, creating it on first use.
+    pub fn load_or_create(&self) -> Result<Keypair> {
+        let passphrase = self.passphrase()?;
+        let keys = match &self.identity {
+            Some(path) => xnet_core::load_or_generate_keypair(path, passphrase.as_deref()),
+            None => {
+                std::fs::create_dir_all(&self.data_dir)
+                    .with_context(|| format!("Failed to create data dir {}", self.data_dir.display()))?;
+                load_identity(&self.data_dir, passphrase.as_deref())
+            }
+        };
+        keys.with_context(|| format!("Failed to load identity {}", self.key_path().display()))
+    }
+
+    fn load(&self) -> Result<Keypair> {
+        let path = self.key_path();
+        load_keypair(&path, self.passphrase()?.as_deref())
+            .with_context(|| format!("Failed to load identity {}", path.display()))
+    }
+}
+
+#[derive(Subcommand)]
+pub enum KeyCommand {
+    /// Create a new identity key
+    Generate {
+        #[command(flatten)]
+        identity: IdentityArgs,
+        /// Replace an existing key (its peer id and credits are lost; see `key rotate`)
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the peer id of the identity key
+    Show {
+        #[command(flatten)]
+        identity: IdentityArgs,
+    },
+    /// Install a key from a key file or a raw protobuf keypair as the identity key
+    Import {
+        file: PathBuf,
+        #[command(flatten)]
+        identity: IdentityArgs,
+        /// Passphrase file for the imported key, if it differs from the identity passphrase
+        #[arg(long)]
+        source_passphrase_file: Option<PathBuf>,
+        /// Replace an existing key
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write the identity key to a file, encrypted with the identity passphrase
+    Export {
+        file: PathBuf,
+        #[command(flatten)]
+        identity: IdentityArgs,
+        /// Export unencrypted (only when no passphrase is set)
+        #[arg(long)]
+        plaintext: bool,
+    },
+    /// Replace the identity key and hand the credit ledger over to the new one (node must be stopped)
+    Rotate {
+        #[command(flatten)]
+        identity: IdentityArgs,
+    },
+    /// Create a pre-shared key for a private network; give every node in it a copy (`psk_file`)
+    Psk {
+        file: PathBuf,
+        /// Replace an existing key
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Serialize)]
+struct KeyInfo {
+    peer_id: String,
+    path: PathBuf,
+    encrypted: bool,
+}
+
+pub fn run(command: KeyCommand, json: bool) -> Result<()> {
+    match command {
+        KeyCommand::Generate { identity, force } => {
+            let path = identity.key_path();
+            ensure_writable(&path, force)?;
+            let keys = generate_keypair();
+            save_keypair(&path, &keys, identity.passphrase()?.as_deref())?;
+            print_key(json, &keys, &path, "Generated")
+        }
+        KeyCommand::Show { identity } => {
+            let path = identity.key_path();
+            let keys = identity.load()?;
+            print_key(json, &keys, &path, "Identity")
+        }
+        KeyCommand::Import { file, identity, source_passphrase_file, force } => {
+            let path = identity.key_path();
+            ensure_writable(&path, force)?;
+            let source_passphrase = match &source_passphrase_file {
+                Some(p) => Some(std::fs::read_to_string(p)?.trim_end_matches(['\r', '\n']).to_string()),
+                None => identity.passphrase()?,
+            };
+            let keys = load_keypair(&file, source_passphrase.as_deref())
+                .with_context(|| format!("Failed to read key {}", file.display()))?;
+            save_keypair(&path, &keys, identity.passphrase()?.as_deref())?;
+            print_key(json, &keys, &path, "Imported")
+        }
+        KeyCommand::Export { file, identity, plaintext } => {
+            let keys = identity.load()?;
+            let passphrase = identity.passphrase()?;
+            if passphrase.is_none() && !plaintext {
+                bail!("Set a passphrase (--passphrase-file or XNET_KEY_PASSPHRASE) or pass --plaintext");
+            }
+            if passphrase.is_some() && plaintext {
+                bail!("--plaintext cannot be combined with a passphrase");
+            }
+            ensure_writable(&file, false)?;
+            save_keypair(&file, &keys, passphrase.as_deref())?;
+            print_key(json, &keys, &file, "Exported")
+        }
+        KeyCommand::Rotate { identity } => rotate(&identity, json),
+        KeyCommand::Psk { file, force } => {
+            ensure_writable(&file, force)?;
+            let psk = xnet_network::generate_psk();
+            write_private(&file, psk.to_string().as_bytes())?;
+            let fingerprint = psk.fingerprint().to_string();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "path": file, "fingerprint": fingerprint }))?);
+            } else {
+                println!("Generated pre-shared key {} (fingerprint {})", file.display(), fingerprint);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Signs a handover from the old key to a fresh one, closes the old key's ledger chain with it,
+/// then swaps the key files. The old key is kept next to the new one as a backup.
+fn rotate(identity: &IdentityArgs, json: bool) -> Result<()> {
+    let path = identity.key_path();
+    let passphrase = identity.passphrase()?;
+    let old = identity.load()?;
+    let new = generate_keypair();
+    let old_peer_id = old.public().to_peer_id();
+    let new_peer_id = new.public().to_peer_id();
+
+    let handover = KeyHandover::sign(&old, &new).map_err(|e| anyhow!(e))?;
+
+    // The new key is on disk before the ledger points at it, so a failure can't strand the credits
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or(xnet_core::IDENTITY_FILE);
+    let backup = path.with_file_name(format!("{}.{}.old", file_name, old_peer_id));
+    let pending = path.with_file_name(format!("{}.{}.new", file_name, new_peer_id));
+    save_keypair(&backup, &old, passphrase.as_deref())?;
+    save_keypair(&pending, &new, passphrase.as_deref())?;
+
+    let handed_over = if identity.data_dir.join(xnet_network::STORE_FILE).exists() {
+        xnet_network::hand_over_ledger(&identity.data_dir, &old, &handover).map(|_| ())
+    } else {
+        Ok(())
+    };
+    if let Err(e) = handed_over {
+        let _ = std::fs::remove_file(&pending);
+        bail!("Failed to hand over the ledger (is the node still running?): {}", e);
+    }
+    std::fs::rename(&pending, &path)?;
+
+    let handover_path = path.with_file_name(format!("handover-{}.json", new_peer_id));
+    std::fs::write(&handover_path, serde_json::to_vec_pretty(&handover)?)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&handover)?);
+    } else {
+        println!("Rotated identity {} -> {}", old_peer_id, new_peer_id);
+        println!("Old key backed up to {}", backup.display());
+        println!("Signed handover written to {}", handover_path.display());
+    }
+    Ok(())
+}
+
+fn ensure_writable(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!("{} already exists (use --force to replace it)", path.display());
+    }
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+fn print_key(json: bool, keys: &Keypair, path: &Path, action: &str) -> Result<()> {
+    let info = KeyInfo {
+        peer_id: keys.public().to_peer_id().to_string(),
+        path: path.to_path_buf(),
+        encrypted: is_encrypted(path)?,
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        println!("{} {} ({}{})", action, info.path.display(), info.peer_id, if info.encrypted { ", encrypted" } else { "" });
+    }
+    Ok(())
+}
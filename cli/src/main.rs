@@ -1,38 +1,303 @@
+mod client;
+mod keys;
+
 use anyhow::Result;
-use tokio::io::{self, AsyncBufReadExt};
-use xnet_core::{InferenceTask, NetworkInterface};
-use xnet_network::P2PNode;
-use xnet_runtime::OllamaRuntime;
+use clap::{Parser, Subcommand};
+use client::ApiClient;
+use keys::{IdentityArgs, KeyCommand};
+use libp2p::Multiaddr;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use xnet_core::{ConnectionState, GenerationOptions, NetworkInterface, TaskStatus};
+use tracing::info;
+use xnet_network::{LogConfig, LogFormat, NetworkEvent, NodeConfig, P2PNode};
+
+#[derive(Parser)]
+#[command(name = "xnet", version, about = "xNet node agent")]
+struct Cli {
+    /// HTTP API of the node to manage
+    #[arg(long, global = true, env = "XNET_API", default_value = "http://127.0.0.1:3030")]
+    api: String,
+
+    /// Print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a full worker node without the desktop app
+    Daemon(DaemonArgs),
+    /// Manage the node's identity key
+    Key {
+        #[command(subcommand)]
+        command: KeyCommand,
+    },
+    /// List connected peers
+    Peers,
+    /// Show uptime, task counters and credits
+    Metrics,
+    /// Show the credit balance and ledger entries
+    Credits,
+    /// Submit a task to the network and wait for the result
+    Submit {
+        prompt: String,
+        #[arg(long, default_value = "llama3")]
+        model: String,
+        /// Most credits you are willing to pay the worker
+        #[arg(long)]
+        max_price: Option<f64>,
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        #[arg(long)]
+        temperature: Option<f32>,
+    },
+    /// List tasks this node published or worked on
+    Tasks,
+    /// Cancel a task this node published
+    Cancel {
+        task_id: String,
+    },
+    /// List the models the node can serve
+    Models,
+    /// Announce this node as a provider
+    Provide,
+    /// Withdraw the provider announcement and stop taking tasks
+    StopProviding,
+}
+
+#[derive(clap::Args)]
+struct DaemonArgs {
+    #[command(flatten)]
+    identity: IdentityArgs,
+
+    /// TOML node config; XNET_* variables and the flags below override it [default: <data-dir>/config.toml]
+    #[arg(long, env = "XNET_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Bootnode multiaddr including /p2p/<peer id>; may be repeated
+    #[arg(long = "bootnode", value_delimiter = ',')]
+    bootnodes: Vec<Multiaddr>,
+
+    /// Address to listen on for peers; may be repeated [default: all interfaces, random TCP and QUIC ports]
+    #[arg(long = "listen", value_delimiter = ',')]
+    listen_addrs: Vec<Multiaddr>,
+
+    /// Ollama server that runs the inference [default: http://localhost:11434]
+    #[arg(long)]
+    ollama_url: Option<String>,
+
+    /// Address for the HTTP API [default: 127.0.0.1:3030]
+    #[arg(long)]
+    api_addr: Option<SocketAddr>,
+
+    /// Private network name, namespaces the gossip topics
+    #[arg(long)]
+    network_id: Option<String>,
+
+    /// Pre-shared key file of a private network (see `xnet key psk`); turns off QUIC
+    #[arg(long)]
+    psk_file: Option<PathBuf>,
+}
+
+impl DaemonArgs {
+    /// The config file, then the environment, then command line flags.
+    fn node_config(&self) -> Result<NodeConfig> {
+        let path = self.config.clone().unwrap_or_else(|| self.identity.data_dir.join(xnet_network::CONFIG_FILE));
+        let mut config = NodeConfig::load(Some(&path))?;
+        if !self.bootnodes.is_empty() {
+            config.bootnodes = self.bootnodes.clone();
+        }
+        if !self.listen_addrs.is_empty() {
+            config.listen_addrs = self.listen_addrs.clone();
+        }
+        if let Some(url) = &self.ollama_url {
+            config.ollama_url = url.clone();
+        }
+        if let Some(addr) = self.api_addr {
+            config.api_addr = addr;
+        }
+        if let Some(network_id) = &self.network_id {
+            config.network_id = Some(network_id.clone());
+        }
+        if let Some(path) = &self.psk_file {
+            config.psk_file = Some(path.clone());
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    println!("Starting xNet Agent (PoC)...");
-    
-    // 1. Start P2P Node
-    let node = P2PNode::new().await?;
-    println!("P2P Node started!");
-
-    // 2. Start Runtime (Lazy init, just checking connection or defining it)
-    let _runtime = OllamaRuntime::new("http://localhost:11434");
-    println!("Runtime initialized (connected to Ollama at localhost:11434)");
-
-    println!("commands: [publish <prompt>, exit]");
-
-    // 3. Command Loop
-    let stdin = io::stdin();
-    let mut reader = io::BufReader::new(stdin).lines();
-
-    while let Some(line) = reader.next_line().await? {
-        let line = line.trim();
-        if line == "exit" {
-            break;
-        } else if line.starts_with("publish ") {
-            let prompt = line.trim_start_matches("publish ").to_string();
-            let task = InferenceTask::new("task-id-1", "llama3", prompt);
-            node.publish_task(task).await.map_err(|e| anyhow::anyhow!(e))?;
-            println!("Task published!");
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    let api = ApiClient::new(&cli.api);
+    let json = cli.json;
+    match cli.command {
+        Command::Daemon(args) => run_daemon(args).await,
+        Command::Key { command } => keys::run(command, json),
+        Command::Peers => {
+            let peers = api.peers().await?;
+            output(json, &peers, |peers| {
+                let connected = peers.iter().filter(|p| p.state == ConnectionState::Connected).count();
+                println!("{} connected peers, {} known", connected, peers.len());
+                for peer in peers {
+                    let rtt = peer.rtt_ms.map(|ms| format!("{}ms", ms)).unwrap_or_else(|| "-".to_string());
+                    let version = peer.xnet_version().map(|v| format!("xnet {}", v))
+                        .unwrap_or_else(|| peer.protocol_version.clone().unwrap_or_else(|| "unidentified".to_string()));
+                    println!("  {} {:?} rtt {} {} ({})", peer.peer_id, peer.state, rtt, version, peer.agent_version.as_deref().unwrap_or("-"));
+                    for addr in &peer.addresses {
+                        println!("      {}", addr);
+                    }
+                }
+            })
+        }
+        Command::Metrics => {
+            let metrics = api.metrics().await?;
+            output(json, &metrics, |m| {
+                println!("Uptime:          {}s", m.uptime_seconds);
+                println!("Tasks processed: {}", m.tasks_processed);
+                println!("Tasks relayed:   {}", m.tasks_relayed);
+                println!("Credits:         {:.2}", m.credits);
+                println!("Inference:       {} running, {} queued", m.running_tasks, m.queued_tasks);
+            })
+        }
+        Command::Credits => {
+            let ledger = api.ledger().await?;
+            output(json, &ledger, |ledger| {
+                println!("Balance: {:.2} credits ({} ledger entries)", ledger.balance, ledger.entries.len());
+                for signed in ledger.entries.iter().rev().take(20) {
+                    let e = &signed.entry;
+                    println!("  #{:<5} {:?} {:>10.2}  {:?}", e.seq, e.kind, e.amount, e.event);
+                }
+            })
+        }
+        Command::Submit { prompt, model, max_price, max_tokens, temperature } => {
+            let options = GenerationOptions { max_tokens, temperature, ..GenerationOptions::default() };
+            let result = api.submit(&model, &prompt, &options, max_price).await?;
+            output(json, &result, |result| {
+                println!("{}", result.output);
+                eprintln!("(task {} by {}, {:.2} credits)", result.task_id, result.worker, result.receipt.receipt.price);
+            })
         }
+        Command::Tasks => {
+            let tasks = api.tasks().await?;
+            output(json, &tasks, |tasks| {
+                for record in tasks {
+                    let status = match &record.status {
+                        TaskStatus::Failed(reason) => format!("Failed ({})", reason),
+                        status => format!("{:?}", status),
+                    };
+                    println!("{}  {:?}  {}  {}", record.task.id, record.role, record.task.model_name, status);
+                }
+            })
+        }
+        Command::Cancel { task_id } => {
+            let result = api.cancel(&task_id).await?;
+            output(json, &result, |_| println!("Cancelled {}", task_id))
+        }
+        Command::Models => {
+            let models = api.models().await?;
+            output(json, &models, |models| {
+                for model in models {
+                    println!("{}  {:?}  ctx {}", model.name, model.capabilities, model.details.context_length.map(|c| c.to_string()).unwrap_or_else(|| "?".to_string()));
+                }
+            })
+        }
+        Command::Provide => {
+            let reply = api.set_providing(true).await?;
+            output(json, &reply, |_| println!("Now providing compute"))
+        }
+        Command::StopProviding => {
+            let reply = api.set_providing(false).await?;
+            output(json, &reply, |_| println!("Stopped providing compute"))
+        }
+    }
+}
+
+fn output<T: Serialize>(json: bool, value: &T, human: impl FnOnce(&T)) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    } else {
+        human(value);
     }
+    Ok(())
+}
+
+async fn run_daemon(args: DaemonArgs) -> Result<()> {
+    let config = args.node_config()?;
+    init_tracing(&config.log)?;
+    info!("Starting xNet daemon...");
 
+    let keys = args.identity.load_or_create()?;
+    info!("Identity: {}", keys.public().to_peer_id());
+    let keypair_bytes = keys.to_protobuf_encoding()?;
+
+    let (runtime, api_addr) = (config.ollama_runtime(), config.api_addr);
+    let node = P2PNode::new(Some(keypair_bytes), Some(args.identity.data_dir), config).await?;
+    info!("P2P Node started!");
+
+    // Log network activity, there is no UI to show it
+    let mut events = node.subscribe();
+    tokio::spawn(async move {
+        while let Ok(event) = events.recv().await {
+            match event {
+                NetworkEvent::PeerConnected(id) => info!("Peer connected: {}", id),
+                NetworkEvent::PeerDisconnected(id) => info!("Peer disconnected: {}", id),
+                NetworkEvent::Message(msg) => info!("{}", msg),
+                NetworkEvent::TaskCompleted(result) => info!(task_id = %result.task_id, "Task completed by {}", result.worker),
+                _ => {}
+            }
+        }
+    });
+
+    // A daemon is always a worker
+    node.announce_provider().await.map_err(|e| anyhow::anyhow!(e))?;
+
+    let (stop_api, api_stopped) = tokio::sync::oneshot::channel::<()>();
+    let api = tokio::spawn(xnet_network::api::serve(node.clone(), runtime, api_addr, async move {
+        let _ = api_stopped.await;
+    }));
+
+    shutdown_signal().await?;
+    info!("Shutting down...");
+
+    // Let in-flight API requests finish, then the node drains its running tasks and disconnects
+    let _ = stop_api.send(());
+    api.await?;
+    node.shutdown().await.map_err(|e| anyhow::anyhow!(e))?;
+    info!("Stopped");
+    Ok(())
+}
+
+/// Sends the daemon's logs to stdout.
+fn init_tracing(log: &LogConfig) -> Result<()> {
+    let filter = tracing_subscriber::EnvFilter::try_new(&log.level)
+        .map_err(|e| anyhow::anyhow!("Invalid log level {:?}: {}", log.level, e))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match log.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+    Ok(())
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix (as sent by systemd and container runtimes).
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
     Ok(())
 }
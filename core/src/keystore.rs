@@ -0,0 +1,361 @@
+use crate::ledger::{peer_public_key, LedgerError};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use libp2p_identity::{Keypair, PeerId};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::Path;
+
+// Identity Keystore
+//
+// A node's identity key is stored as a small JSON file. With a passphrase the protobuf-encoded
+// keypair is sealed with ChaCha20-Poly1305 under a key derived by Argon2id; the KDF parameters
+// travel with the file. Files are written with 0600 permissions. Plain protobuf files from
+// earlier versions are still read.
+
+const KEYSTORE_VERSION: u32 = 1;
+
+/// Identity key file name inside a node's data dir.
+pub const IDENTITY_FILE: &str = "identity.key";
+const LEGACY_IDENTITY_FILE: &str = "identity.bin";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyFile {
+    version: u32,
+    peer_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encryption: Option<KeyEncryption>,
+    key: String, // Hex: the protobuf keypair, or its ciphertext when encrypted
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyEncryption {
+    kdf: String, // "argon2id"
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    cipher: String, // "chacha20poly1305"
+    nonce: String,
+}
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Io(std::io::Error),
+    Format(String),
+    PassphraseRequired,
+    WrongPassphrase,
+    Crypto(String),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io(e) => write!(f, "Key file I/O failed: {}", e),
+            KeystoreError::Format(e) => write!(f, "Invalid key file: {}", e),
+            KeystoreError::PassphraseRequired => write!(f, "Key file is encrypted, a passphrase is required"),
+            KeystoreError::WrongPassphrase => write!(f, "Wrong passphrase or corrupted key file"),
+            KeystoreError::Crypto(e) => write!(f, "Key encryption failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<std::io::Error> for KeystoreError {
+    fn from(e: std::io::Error) -> Self {
+        KeystoreError::Io(e)
+    }
+}
+
+pub fn generate_keypair() -> Keypair {
+    Keypair::generate_ed25519()
+}
+
+/// Writes `keypair` to `path`, encrypted when a passphrase is given. Replaces any existing file.
+pub fn save_keypair(path: &Path, keypair: &Keypair, passphrase: Option<&str>) -> Result<(), KeystoreError> {
+    let encoded = keypair.to_protobuf_encoding().map_err(|e| KeystoreError::Format(e.to_string()))?;
+    let peer_id = keypair.public().to_peer_id().to_string();
+
+    let (encryption, key) = match passphrase {
+        None => (None, hex::encode(&encoded)),
+        Some(passphrase) => {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            let params = Params::default();
+            let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, &params)?);
+            let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let ciphertext = cipher.encrypt(&nonce, Payload { msg: &encoded, aad: peer_id.as_bytes() })
+                .map_err(|e| KeystoreError::Crypto(e.to_string()))?;
+            let encryption = KeyEncryption {
+                kdf: "argon2id".to_string(),
+                salt: hex::encode(salt),
+                m_cost: params.m_cost(),
+                t_cost: params.t_cost(),
+                p_cost: params.p_cost(),
+                cipher: "chacha20poly1305".to_string(),
+                nonce: hex::encode(nonce),
+            };
+            (Some(encryption), hex::encode(ciphertext))
+        }
+    };
+
+    let file = KeyFile { version: KEYSTORE_VERSION, peer_id, encryption, key };
+    let data = serde_json::to_vec_pretty(&file).map_err(|e| KeystoreError::Format(e.to_string()))?;
+    write_private(path, &data)
+}
+
+/// Reads a key file written by `save_keypair`, or a legacy raw protobuf `identity.bin`.
+pub fn load_keypair(path: &Path, passphrase: Option<&str>) -> Result<Keypair, KeystoreError> {
+    let data = std::fs::read(path)?;
+    let Ok(file) = serde_json::from_slice::<KeyFile>(&data) else {
+        return Keypair::from_protobuf_encoding(&data).map_err(|e| KeystoreError::Format(e.to_string()));
+    };
+    if file.version > KEYSTORE_VERSION {
+        return Err(KeystoreError::Format(format!("unsupported version {}", file.version)));
+    }
+
+    let key = hex::decode(&file.key).map_err(|e| KeystoreError::Format(e.to_string()))?;
+    let encoded = match &file.encryption {
+        None => key,
+        Some(encryption) => {
+            if encryption.kdf != "argon2id" || encryption.cipher != "chacha20poly1305" {
+                return Err(KeystoreError::Format(format!("unsupported scheme {}/{}", encryption.kdf, encryption.cipher)));
+            }
+            let passphrase = passphrase.ok_or(KeystoreError::PassphraseRequired)?;
+            let salt = hex::decode(&encryption.salt).map_err(|e| KeystoreError::Format(e.to_string()))?;
+            let nonce = hex::decode(&encryption.nonce).map_err(|e| KeystoreError::Format(e.to_string()))?;
+            if nonce.len() != 12 {
+                return Err(KeystoreError::Format("bad nonce length".to_string()));
+            }
+            let params = Params::new(encryption.m_cost, encryption.t_cost, encryption.p_cost, Some(32))
+                .map_err(|e| KeystoreError::Format(e.to_string()))?;
+            let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt, &params)?);
+            cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: &key, aad: file.peer_id.as_bytes() })
+                .map_err(|_| KeystoreError::WrongPassphrase)?
+        }
+    };
+
+    let keypair = Keypair::from_protobuf_encoding(&encoded).map_err(|e| KeystoreError::Format(e.to_string()))?;
+    if keypair.public().to_peer_id().to_string() != file.peer_id {
+        return Err(KeystoreError::Format("key does not match its peer id".to_string()));
+    }
+    Ok(keypair)
+}
+
+/// Loads the key at `path`, or generates and saves a new one if there is none yet.
+pub fn load_or_generate_keypair(path: &Path, passphrase: Option<&str>) -> Result<Keypair, KeystoreError> {
+    if path.exists() {
+        return load_keypair(path, passphrase);
+    }
+    let keypair = generate_keypair();
+    save_keypair(path, &keypair, passphrase)?;
+    Ok(keypair)
+}
+
+/// Loads or creates the identity key in a data dir. A legacy plaintext `identity.bin` is moved
+/// into the keystore format (encrypted if a passphrase is given) and removed.
+pub fn load_identity(data_dir: &Path, passphrase: Option<&str>) -> Result<Keypair, KeystoreError> {
+    let path = data_dir.join(IDENTITY_FILE);
+    let legacy = data_dir.join(LEGACY_IDENTITY_FILE);
+    if !path.exists() && legacy.exists() {
+        let keypair = load_keypair(&legacy, None)?;
+        save_keypair(&path, &keypair, passphrase)?;
+        std::fs::remove_file(&legacy)?;
+        return Ok(keypair);
+    }
+    load_or_generate_keypair(&path, passphrase)
+}
+
+/// Whether the key file needs a passphrase, without decrypting it.
+pub fn is_encrypted(path: &Path) -> Result<bool, KeystoreError> {
+    let data = std::fs::read(path)?;
+    Ok(serde_json::from_slice::<KeyFile>(&data).is_ok_and(|f| f.encryption.is_some()))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: &Params) -> Result<Key, KeystoreError> {
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| KeystoreError::Crypto(e.to_string()))?;
+    Ok(key)
+}
+
+/// Writes via a temp file created owner-only, so the key is never readable by others, even briefly.
+pub fn write_private(path: &Path, data: &[u8]) -> Result<(), KeystoreError> {
+    use std::io::Write;
+    let tmp = path.with_extension("tmp");
+    // A leftover temp file may have been created by someone else with looser permissions
+    match std::fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Statement by an old identity key that a new key takes over its identity, signed by both keys:
+/// the old key hands over, the new key accepts. Anyone can check it with nothing but the two
+/// peer ids and the signatures.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyHandover {
+    pub old_peer_id: String,
+    pub new_peer_id: String,
+    pub issued_at: u64, // Unix seconds
+    #[serde(default)]
+    pub signature: Vec<u8>, // By the old key, over `hash()`
+    #[serde(default)]
+    pub new_signature: Vec<u8>, // By the new key, over `hash()`
+}
+
+impl KeyHandover {
+    pub fn sign(old: &Keypair, new: &Keypair) -> Result<Self, LedgerError> {
+        let mut handover = Self {
+            old_peer_id: old.public().to_peer_id().to_string(),
+            new_peer_id: new.public().to_peer_id().to_string(),
+            issued_at: crate::types::unix_timestamp(),
+            signature: Vec::new(),
+            new_signature: Vec::new(),
+        };
+        let hash = handover.hash();
+        handover.signature = old.sign(hash.as_bytes()).map_err(|e| LedgerError::Signing(e.to_string()))?;
+        handover.new_signature = new.sign(hash.as_bytes()).map_err(|e| LedgerError::Signing(e.to_string()))?;
+        Ok(handover)
+    }
+
+    /// Hex SHA-256 of the statement without its signature.
+    pub fn hash(&self) -> String {
+        let bytes = serde_json::to_vec(&(&self.old_peer_id, &self.new_peer_id, self.issued_at)).expect("handover serializes");
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    /// Checks that both keys signed the statement.
+    pub fn verify(&self) -> Result<(), LedgerError> {
+        let hash = self.hash();
+        for (peer, signature) in [(&self.old_peer_id, &self.signature), (&self.new_peer_id, &self.new_signature)] {
+            let peer_id: PeerId = peer.parse().map_err(|_| LedgerError::UnknownKey(peer.clone()))?;
+            if !peer_public_key(&peer_id)?.verify(hash.as_bytes(), signature) {
+                return Err(LedgerError::BadHandoverSignature(peer.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("xnet-keystore-{}", generate_keypair().public().to_peer_id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn round_trips_plain_and_encrypted_keys() {
+        let dir = TempDir::new();
+        let keypair = generate_keypair();
+
+        let plain = dir.0.join("plain.key");
+        save_keypair(&plain, &keypair, None).unwrap();
+        assert!(!is_encrypted(&plain).unwrap());
+        assert_eq!(load_keypair(&plain, None).unwrap().public(), keypair.public());
+
+        let sealed = dir.0.join("sealed.key");
+        save_keypair(&sealed, &keypair, Some("correct horse")).unwrap();
+        assert!(is_encrypted(&sealed).unwrap());
+        assert!(!std::fs::read_to_string(&sealed).unwrap().contains(&hex::encode(keypair.to_protobuf_encoding().unwrap())));
+        assert_eq!(load_keypair(&sealed, Some("correct horse")).unwrap().public(), keypair.public());
+    }
+
+    #[test]
+    fn rejects_wrong_or_missing_passphrase() {
+        let dir = TempDir::new();
+        let path = dir.0.join(IDENTITY_FILE);
+        save_keypair(&path, &generate_keypair(), Some("correct horse")).unwrap();
+
+        assert!(matches!(load_keypair(&path, Some("battery staple")), Err(KeystoreError::WrongPassphrase)));
+        assert!(matches!(load_keypair(&path, None), Err(KeystoreError::PassphraseRequired)));
+    }
+
+    #[test]
+    fn rejects_key_swapped_under_another_peer_id() {
+        let dir = TempDir::new();
+        let path = dir.0.join(IDENTITY_FILE);
+        save_keypair(&path, &generate_keypair(), None).unwrap();
+
+        let mut file: KeyFile = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        file.key = hex::encode(generate_keypair().to_protobuf_encoding().unwrap());
+        std::fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+        assert!(matches!(load_keypair(&path, None), Err(KeystoreError::Format(_))));
+    }
+
+    #[test]
+    fn migrates_legacy_identity() {
+        let dir = TempDir::new();
+        let keypair = generate_keypair();
+        std::fs::write(dir.0.join(LEGACY_IDENTITY_FILE), keypair.to_protobuf_encoding().unwrap()).unwrap();
+
+        let loaded = load_identity(&dir.0, Some("pass")).unwrap();
+        assert_eq!(loaded.public(), keypair.public());
+        assert!(!dir.0.join(LEGACY_IDENTITY_FILE).exists());
+        assert!(is_encrypted(&dir.0.join(IDENTITY_FILE)).unwrap());
+        assert_eq!(load_identity(&dir.0, Some("pass")).unwrap().public(), keypair.public());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new();
+        let path = dir.0.join(IDENTITY_FILE);
+        // A stale temp file others can read is replaced, not reused
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, b"stale").unwrap();
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o644)).unwrap();
+        save_keypair(&path, &generate_keypair(), None).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!tmp.exists());
+    }
+
+    #[test]
+    fn handover_needs_both_keys() {
+        let (old, new) = (generate_keypair(), generate_keypair());
+        let handover = KeyHandover::sign(&old, &new).unwrap();
+        assert!(handover.verify().is_ok());
+
+        let mut unaccepted = handover.clone();
+        unaccepted.new_signature = old.sign(handover.hash().as_bytes()).unwrap();
+        assert!(matches!(unaccepted.verify(), Err(LedgerError::BadHandoverSignature(peer)) if peer == handover.new_peer_id));
+
+        let mut redirected = handover.clone();
+        redirected.new_peer_id = generate_keypair().public().to_peer_id().to_string();
+        assert!(redirected.verify().is_err());
+    }
+}
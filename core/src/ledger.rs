@@ -0,0 +1,341 @@
+use crate::keystore::KeyHandover;
+use libp2p_identity::{Keypair, PeerId, PublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+// Credit Ledger
+//
+// Every earn/spend event is an entry signed by the node's identity key and chained to the
+// previous one by hash, so the balance can be recomputed (and checked) by anyone who holds
+// the entries and the owner's public key. Editing, dropping or reordering entries breaks the chain.
+
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EntryKind {
+    Earn,
+    Spend,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LedgerEvent {
+    /// Balance carried over from the legacy self-reported wallet. Peers may choose to discount it.
+    Opening,
+    Uptime { minutes: u64 },
+    TaskExecuted { task_id: String, requester: String },
+    PaymentReceived { task_id: String, from: String, receipt_hash: String },
+    PaymentSent { task_id: String, to: String, receipt_hash: String },
+    TasksRelayed { count: u64 },
+    VerificationPerformed { target: String },
+    /// The last entry signed by the handover's old key; later entries are signed by its new key.
+    KeyRotated { handover: KeyHandover },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub seq: u64,
+    pub prev_hash: String,
+    pub timestamp: u64, // Unix seconds
+    pub kind: EntryKind,
+    pub amount: f64,
+    pub event: LedgerEvent,
+}
+
+impl LedgerEntry {
+    /// Hex SHA-256 of the canonical JSON encoding; this is what gets signed and chained.
+    pub fn hash(&self) -> String {
+        let bytes = serde_json::to_vec(self).expect("ledger entry serializes");
+        hex::encode(Sha256::digest(bytes))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedEntry {
+    pub entry: LedgerEntry,
+    pub hash: String,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum LedgerError {
+    BrokenChain { seq: u64 },
+    HashMismatch { seq: u64 },
+    BadSignature { seq: u64 },
+    InvalidAmount { seq: u64 },
+    UnknownKey(String),
+    Signing(String),
+    InsufficientCredits { balance: f64, required: f64 },
+    MissingCountersignature(String),
+    BadReceiptSignature(String),
+    ReceiptMismatch { task_id: String, field: String },
+    BadHandoverSignature(String),
+    WrongOwner,
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::BrokenChain { seq } => write!(f, "Ledger chain broken at entry {}", seq),
+            LedgerError::HashMismatch { seq } => write!(f, "Ledger entry {} does not match its hash", seq),
+            LedgerError::BadSignature { seq } => write!(f, "Ledger entry {} has an invalid signature", seq),
+            LedgerError::InvalidAmount { seq } => write!(f, "Ledger entry {} has an invalid amount", seq),
+            LedgerError::UnknownKey(peer) => write!(f, "Cannot derive a public key from peer id {}", peer),
+            LedgerError::Signing(e) => write!(f, "Failed to sign ledger entry: {}", e),
+            LedgerError::InsufficientCredits { balance, required } => {
+                write!(f, "Insufficient credits: balance {:.2}, required {:.2}", balance, required)
+            }
+            LedgerError::MissingCountersignature(task_id) => write!(f, "Receipt for task {} is not countersigned", task_id),
+            LedgerError::BadReceiptSignature(peer) => write!(f, "Receipt signature from {} is invalid", peer),
+            LedgerError::ReceiptMismatch { task_id, field } => write!(f, "Receipt for task {} does not match its {}", task_id, field),
+            LedgerError::BadHandoverSignature(peer) => write!(f, "Key handover from {} has an invalid signature", peer),
+            LedgerError::WrongOwner => write!(f, "Ledger does not end with the expected owner key"),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    entries: Vec<SignedEntry>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuilds a ledger from stored or received entries, rejecting it unless the whole chain checks out.
+    pub fn from_entries(entries: Vec<SignedEntry>, owner: &PublicKey) -> Result<Self, LedgerError> {
+        verify_chain(&entries, owner)?;
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[SignedEntry] {
+        &self.entries
+    }
+
+    pub fn head_hash(&self) -> &str {
+        self.entries.last().map(|e| e.hash.as_str()).unwrap_or(GENESIS_HASH)
+    }
+
+    pub fn balance(&self) -> f64 {
+        self.entries.iter().fold(0.0, |balance, e| match e.entry.kind {
+            EntryKind::Earn => balance + e.entry.amount,
+            EntryKind::Spend => balance - e.entry.amount,
+        })
+    }
+
+    /// Appends a new entry signed by `keypair`, which must be the ledger owner's key.
+    pub fn record(&mut self, keypair: &Keypair, kind: EntryKind, amount: f64, event: LedgerEvent) -> Result<&SignedEntry, LedgerError> {
+        let seq = self.entries.len() as u64;
+        if !amount.is_finite() || amount < 0.0 {
+            return Err(LedgerError::InvalidAmount { seq });
+        }
+        if kind == EntryKind::Spend && amount > self.balance() {
+            return Err(LedgerError::InsufficientCredits { balance: self.balance(), required: amount });
+        }
+
+        let entry = LedgerEntry {
+            seq,
+            prev_hash: self.head_hash().to_string(),
+            timestamp: crate::types::unix_timestamp(),
+            kind,
+            amount,
+            event,
+        };
+        let hash = entry.hash();
+        let signature = keypair.sign(hash.as_bytes()).map_err(|e| LedgerError::Signing(e.to_string()))?;
+
+        self.entries.push(SignedEntry { entry, hash, signature });
+        Ok(self.entries.last().expect("just pushed"))
+    }
+}
+
+/// Checks sequence numbers, hash links, entry hashes and signatures of a full chain.
+/// A chain that went through key rotations is signed by each key in turn and must end with `owner`.
+pub fn verify_chain(entries: &[SignedEntry], owner: &PublicKey) -> Result<(), LedgerError> {
+    // Trust starts at `owner` and goes back one key per rotation, each one accepted by its successor
+    let mut signer = owner.clone();
+    for signed in entries.iter().rev() {
+        if let LedgerEvent::KeyRotated { handover } = &signed.entry.event {
+            handover.verify()?;
+            if peer_key(&handover.new_peer_id)? != signer {
+                return Err(LedgerError::WrongOwner);
+            }
+            signer = peer_key(&handover.old_peer_id)?;
+        }
+    }
+
+    let mut prev_hash = GENESIS_HASH;
+    for (index, signed) in entries.iter().enumerate() {
+        let seq = signed.entry.seq;
+        if seq != index as u64 || signed.entry.prev_hash != prev_hash {
+            return Err(LedgerError::BrokenChain { seq });
+        }
+        if !signed.entry.amount.is_finite() || signed.entry.amount < 0.0 {
+            return Err(LedgerError::InvalidAmount { seq });
+        }
+        if signed.entry.hash() != signed.hash {
+            return Err(LedgerError::HashMismatch { seq });
+        }
+        if !signer.verify(signed.hash.as_bytes(), &signed.signature) {
+            return Err(LedgerError::BadSignature { seq });
+        }
+        if let LedgerEvent::KeyRotated { handover } = &signed.entry.event {
+            signer = peer_key(&handover.new_peer_id)?;
+        }
+        prev_hash = &signed.hash;
+    }
+    Ok(())
+}
+
+fn peer_key(peer: &str) -> Result<PublicKey, LedgerError> {
+    let peer_id: PeerId = peer.parse().map_err(|_| LedgerError::UnknownKey(peer.to_string()))?;
+    peer_public_key(&peer_id)
+}
+
+/// Ed25519 peer ids embed the public key (identity multihash), so signatures
+/// from a peer can be checked knowing only its id.
+pub fn peer_public_key(peer_id: &PeerId) -> Result<PublicKey, LedgerError> {
+    let multihash = peer_id.as_ref();
+    if multihash.code() != 0x00 {
+        return Err(LedgerError::UnknownKey(peer_id.to_string()));
+    }
+    PublicKey::try_decode_protobuf(multihash.digest())
+        .map_err(|_| LedgerError::UnknownKey(peer_id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ledger_with(keys: &Keypair, amounts: &[f64]) -> Ledger {
+        let mut ledger = Ledger::new();
+        for amount in amounts {
+            ledger.record(keys, EntryKind::Earn, *amount, LedgerEvent::Uptime { minutes: 1 }).unwrap();
+        }
+        ledger
+    }
+
+    fn rotate(ledger: &mut Ledger, handover: KeyHandover, signer: &Keypair) {
+        ledger.record(signer, EntryKind::Earn, 0.0, LedgerEvent::KeyRotated { handover }).unwrap();
+    }
+
+    #[test]
+    fn verifies_own_chain() {
+        let keys = Keypair::generate_ed25519();
+        let mut ledger = ledger_with(&keys, &[5.0, 2.5]);
+        ledger.record(&keys, EntryKind::Spend, 3.0, LedgerEvent::TasksRelayed { count: 1 }).unwrap();
+
+        let rebuilt = Ledger::from_entries(ledger.entries().to_vec(), &keys.public()).unwrap();
+        assert_eq!(rebuilt.balance(), 4.5);
+        assert!(Ledger::from_entries(Vec::new(), &keys.public()).is_ok());
+    }
+
+    #[test]
+    fn rejects_overspending_and_bad_amounts() {
+        let keys = Keypair::generate_ed25519();
+        let mut ledger = ledger_with(&keys, &[1.0]);
+        assert!(matches!(
+            ledger.record(&keys, EntryKind::Spend, 2.0, LedgerEvent::Opening),
+            Err(LedgerError::InsufficientCredits { .. })
+        ));
+        assert!(matches!(
+            ledger.record(&keys, EntryKind::Earn, -1.0, LedgerEvent::Opening),
+            Err(LedgerError::InvalidAmount { seq: 1 })
+        ));
+        assert!(matches!(
+            ledger.record(&keys, EntryKind::Earn, f64::NAN, LedgerEvent::Opening),
+            Err(LedgerError::InvalidAmount { seq: 1 })
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_signer() {
+        let keys = Keypair::generate_ed25519();
+        let other = Keypair::generate_ed25519();
+        let ledger = ledger_with(&other, &[1.0]);
+        assert!(matches!(
+            verify_chain(ledger.entries(), &keys.public()),
+            Err(LedgerError::BadSignature { seq: 0 })
+        ));
+    }
+
+    #[test]
+    fn rejects_tampered_entries() {
+        let keys = Keypair::generate_ed25519();
+        let entries = ledger_with(&keys, &[1.0, 2.0, 3.0]).entries().to_vec();
+
+        let mut relinked = entries.clone();
+        relinked[1].entry.prev_hash = GENESIS_HASH.to_string();
+        assert!(matches!(verify_chain(&relinked, &keys.public()), Err(LedgerError::BrokenChain { seq: 1 })));
+
+        let mut edited = entries.clone();
+        edited[2].entry.amount = 300.0;
+        assert!(matches!(verify_chain(&edited, &keys.public()), Err(LedgerError::HashMismatch { seq: 2 })));
+
+        // Re-hashing an edit doesn't help without the key
+        edited[2].hash = edited[2].entry.hash();
+        assert!(matches!(verify_chain(&edited, &keys.public()), Err(LedgerError::BadSignature { seq: 2 })));
+
+        let mut dropped = entries.clone();
+        dropped.remove(1);
+        assert!(matches!(verify_chain(&dropped, &keys.public()), Err(LedgerError::BrokenChain { seq: 2 })));
+    }
+
+    #[test]
+    fn follows_key_rotation() {
+        let old = Keypair::generate_ed25519();
+        let new = Keypair::generate_ed25519();
+        let mut ledger = ledger_with(&old, &[4.0]);
+        rotate(&mut ledger, KeyHandover::sign(&old, &new).unwrap(), &old);
+        ledger.record(&new, EntryKind::Earn, 1.0, LedgerEvent::Uptime { minutes: 1 }).unwrap();
+
+        assert!(verify_chain(ledger.entries(), &new.public()).is_ok());
+        // The old key no longer owns it
+        assert!(verify_chain(ledger.entries(), &old.public()).is_err());
+    }
+
+    #[test]
+    fn rejects_forged_rotation() {
+        let attacker = Keypair::generate_ed25519();
+        let victim = Keypair::generate_ed25519();
+        let mut ledger = ledger_with(&attacker, &[1000.0]);
+
+        // A handover the victim never signed
+        let mut handover = KeyHandover::sign(&attacker, &attacker).unwrap();
+        handover.new_peer_id = victim.public().to_peer_id().to_string();
+        handover.signature = attacker.sign(handover.hash().as_bytes()).unwrap();
+        handover.new_signature = attacker.sign(handover.hash().as_bytes()).unwrap();
+        rotate(&mut ledger, handover, &attacker);
+
+        assert!(matches!(
+            Ledger::from_entries(ledger.entries().to_vec(), &victim.public()),
+            Err(LedgerError::BadHandoverSignature(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_rotation_to_someone_else() {
+        let old = Keypair::generate_ed25519();
+        let new = Keypair::generate_ed25519();
+        let owner = Keypair::generate_ed25519();
+        let mut ledger = ledger_with(&old, &[1.0]);
+        rotate(&mut ledger, KeyHandover::sign(&old, &new).unwrap(), &old);
+        ledger.record(&new, EntryKind::Earn, 1.0, LedgerEvent::Uptime { minutes: 1 }).unwrap();
+
+        assert!(matches!(verify_chain(ledger.entries(), &owner.public()), Err(LedgerError::WrongOwner)));
+    }
+
+    #[test]
+    fn rejects_rotation_entry_signed_by_the_new_key() {
+        let old = Keypair::generate_ed25519();
+        let new = Keypair::generate_ed25519();
+        let mut ledger = ledger_with(&old, &[1.0]);
+        rotate(&mut ledger, KeyHandover::sign(&old, &new).unwrap(), &new);
+
+        assert!(matches!(verify_chain(ledger.entries(), &new.public()), Err(LedgerError::BadSignature { seq: 1 })));
+    }
+}
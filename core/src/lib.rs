@@ -1,7 +1,12 @@
 pub mod types;
 pub mod traits;
+pub mod ledger;
+pub mod receipt;
+pub mod keystore;
 
 pub use types::*;
 pub use traits::*;
-
+pub use ledger::*;
+pub use receipt::*;
+pub use keystore::*;
 
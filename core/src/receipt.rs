@@ -0,0 +1,191 @@
+use crate::ledger::{peer_public_key, LedgerError};
+use crate::types::{GenerationOptions, InferenceTask, TaskOutput};
+use libp2p_identity::{Keypair, PeerId};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Task Receipts (Proof of Inference)
+//
+// The worker signs a receipt when it completes a task, committing to what it ran (task, input,
+// model digest, options), what it produced and when. The requester countersigns it when it
+// accepts the result and pays. Anyone holding the receipt can check the signatures offline, and
+// anyone holding the task and output can check the hashes.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskReceipt {
+    pub task_id: String,
+    pub worker: String,    // Peer id
+    pub requester: String, // Peer id
+    pub price: f64,
+    pub task_hash: String,
+    pub prompt_hash: String,
+    pub output_hash: String,
+    pub model: String,
+    pub model_digest: String,
+    pub options: GenerationOptions, // As applied by the worker, after its limits
+    pub started_at: u64,  // Unix milliseconds
+    pub finished_at: u64, // Unix milliseconds
+}
+
+impl TaskReceipt {
+    pub fn hash(&self) -> String {
+        let bytes = serde_json::to_vec(self).expect("receipt serializes");
+        hex::encode(Sha256::digest(bytes))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedReceipt {
+    pub receipt: TaskReceipt,
+    pub worker_signature: Vec<u8>,
+    #[serde(default)]
+    pub requester_signature: Option<Vec<u8>>,
+}
+
+impl SignedReceipt {
+    /// Signs a receipt as the worker. `keypair` must belong to `receipt.worker`.
+    pub fn issue(receipt: TaskReceipt, keypair: &Keypair) -> Result<Self, LedgerError> {
+        let worker_signature = keypair.sign(receipt.hash().as_bytes())
+            .map_err(|e| LedgerError::Signing(e.to_string()))?;
+        Ok(Self { receipt, worker_signature, requester_signature: None })
+    }
+
+    /// Accepts the receipt as the requester. `keypair` must belong to `receipt.requester`.
+    pub fn countersign(&mut self, keypair: &Keypair) -> Result<(), LedgerError> {
+        let signature = keypair.sign(self.receipt.hash().as_bytes())
+            .map_err(|e| LedgerError::Signing(e.to_string()))?;
+        self.requester_signature = Some(signature);
+        Ok(())
+    }
+
+    pub fn hash(&self) -> String {
+        self.receipt.hash()
+    }
+
+    pub fn verify_worker(&self) -> Result<(), LedgerError> {
+        verify_signature(&self.receipt.worker, &self.receipt.hash(), &self.worker_signature)
+    }
+
+    /// Checks both signatures; only a countersigned receipt settles a payment.
+    pub fn verify(&self) -> Result<(), LedgerError> {
+        self.verify_worker()?;
+        let signature = self.requester_signature.as_ref()
+            .ok_or_else(|| LedgerError::MissingCountersignature(self.receipt.task_id.clone()))?;
+        verify_signature(&self.receipt.requester, &self.receipt.hash(), signature)
+    }
+
+    /// Checks that the receipt commits to this exact task and output, and that the worker signed it.
+    pub fn verify_against(&self, task: &InferenceTask, output: &TaskOutput) -> Result<(), LedgerError> {
+        self.verify_worker()?;
+        let receipt = &self.receipt;
+        let mismatch = if receipt.task_id != task.id {
+            Some("task id")
+        } else if receipt.task_hash != task.hash() {
+            Some("task hash")
+        } else if receipt.prompt_hash != task.input_hash() {
+            Some("prompt hash")
+        } else if receipt.model != task.model_name {
+            Some("model")
+        } else if receipt.output_hash != output.hash() {
+            Some("output hash")
+        } else if receipt.finished_at < receipt.started_at {
+            Some("timing")
+        } else {
+            None
+        };
+        match mismatch {
+            Some(field) => Err(LedgerError::ReceiptMismatch { task_id: receipt.task_id.clone(), field: field.to_string() }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn verify_signature(peer: &str, hash: &str, signature: &[u8]) -> Result<(), LedgerError> {
+    let peer_id: PeerId = peer.parse().map_err(|_| LedgerError::UnknownKey(peer.to_string()))?;
+    if peer_public_key(&peer_id)?.verify(hash.as_bytes(), signature) {
+        Ok(())
+    } else {
+        Err(LedgerError::BadReceiptSignature(peer.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt_for(task: &InferenceTask, output: &TaskOutput, worker: &Keypair, requester: &Keypair) -> TaskReceipt {
+        TaskReceipt {
+            task_id: task.id.clone(),
+            worker: worker.public().to_peer_id().to_string(),
+            requester: requester.public().to_peer_id().to_string(),
+            price: 1.5,
+            task_hash: task.hash(),
+            prompt_hash: task.input_hash(),
+            output_hash: output.hash(),
+            model: task.model_name.clone(),
+            model_digest: "sha256:abc".to_string(),
+            options: task.options.clone(),
+            started_at: 1_000,
+            finished_at: 2_000,
+        }
+    }
+
+    #[test]
+    fn settles_only_when_countersigned() {
+        let (worker, requester) = (Keypair::generate_ed25519(), Keypair::generate_ed25519());
+        let task = InferenceTask::new("t1", "llama3", "Hello");
+        let output = TaskOutput::Text("Hi".to_string());
+        let mut signed = SignedReceipt::issue(receipt_for(&task, &output, &worker, &requester), &worker).unwrap();
+
+        assert!(signed.verify_worker().is_ok());
+        assert!(matches!(signed.verify(), Err(LedgerError::MissingCountersignature(_))));
+        signed.countersign(&requester).unwrap();
+        assert!(signed.verify().is_ok());
+    }
+
+    #[test]
+    fn rejects_wrong_signers() {
+        let (worker, requester, other) = (Keypair::generate_ed25519(), Keypair::generate_ed25519(), Keypair::generate_ed25519());
+        let task = InferenceTask::new("t1", "llama3", "Hello");
+        let output = TaskOutput::Text("Hi".to_string());
+
+        let forged = SignedReceipt::issue(receipt_for(&task, &output, &worker, &requester), &other).unwrap();
+        assert!(matches!(forged.verify_worker(), Err(LedgerError::BadReceiptSignature(_))));
+
+        let mut signed = SignedReceipt::issue(receipt_for(&task, &output, &worker, &requester), &worker).unwrap();
+        signed.countersign(&other).unwrap();
+        assert!(matches!(signed.verify(), Err(LedgerError::BadReceiptSignature(_))));
+    }
+
+    #[test]
+    fn rejects_edited_receipt() {
+        let (worker, requester) = (Keypair::generate_ed25519(), Keypair::generate_ed25519());
+        let task = InferenceTask::new("t1", "llama3", "Hello");
+        let output = TaskOutput::Text("Hi".to_string());
+        let mut signed = SignedReceipt::issue(receipt_for(&task, &output, &worker, &requester), &worker).unwrap();
+        signed.countersign(&requester).unwrap();
+
+        signed.receipt.price = 100.0;
+        assert!(signed.verify().is_err());
+    }
+
+    #[test]
+    fn checks_task_and_output() {
+        let (worker, requester) = (Keypair::generate_ed25519(), Keypair::generate_ed25519());
+        let task = InferenceTask::new("t1", "llama3", "Hello");
+        let output = TaskOutput::Text("Hi".to_string());
+        let signed = SignedReceipt::issue(receipt_for(&task, &output, &worker, &requester), &worker).unwrap();
+        assert!(signed.verify_against(&task, &output).is_ok());
+
+        let other_output = TaskOutput::Text("Bye".to_string());
+        assert!(matches!(
+            signed.verify_against(&task, &other_output),
+            Err(LedgerError::ReceiptMismatch { field, .. }) if field == "output hash"
+        ));
+        let other_prompt = InferenceTask::new("t1", "llama3", "Goodbye");
+        assert!(matches!(
+            signed.verify_against(&other_prompt, &output),
+            Err(LedgerError::ReceiptMismatch { field, .. }) if field == "task hash"
+        ));
+    }
+}
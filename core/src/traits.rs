@@ -1,13 +1,17 @@
 use async_trait::async_trait;
-use crate::types::{InferenceTask, PipelineEvent};
+use crate::types::{ChatMessage, ChatRequest, GenerationOptions, InferenceTask, TaskKind, TaskOutput, TaskResult};
 use std::error::Error;
 
-
+/// Error type of the traits below. Implementations box their crate's own enum (`NetworkError`,
+/// `RuntimeError`, `LedgerError`, ...), so callers can `downcast_ref` to tell failures apart.
 pub type DynError = Box<dyn Error + Send + Sync>;
 
 #[async_trait]
 pub trait NetworkInterface: Send + Sync {
+    /// Broadcasts a task. Fails with `LedgerError::InsufficientCredits` if the bid isn't covered.
     async fn publish_task(&self, task: InferenceTask) -> Result<(), DynError>;
+    /// Publishes a task and waits for the first result, paying the worker from the bid.
+    async fn submit_task(&self, task: InferenceTask) -> Result<TaskResult, DynError>;
     async fn announce_provider(&self) -> Result<(), DynError>;
     async fn publish_pipeline_event(&self, event: crate::types::PipelineEvent) -> Result<(), DynError>;
     async fn publish_verification_event(&self, event: crate::types::VerificationEvent) -> Result<(), DynError>;
@@ -16,5 +20,17 @@ pub trait NetworkInterface: Send + Sync {
 
 #[async_trait]
 pub trait RuntimeInterface: Send + Sync {
-    async fn generate(&self, model: &str, prompt: &str) -> Result<String, DynError>;
+    /// `images` are raw image bytes for vision models; pass an empty slice for text-only prompts.
+    async fn generate(&self, model: &str, prompt: &str, images: &[Vec<u8>], options: &GenerationOptions) -> Result<String, DynError>;
+    async fn chat(&self, model: &str, request: &ChatRequest, images: &[Vec<u8>], options: &GenerationOptions) -> Result<ChatMessage, DynError>;
+    async fn embed(&self, model: &str, input: &[String], options: &GenerationOptions) -> Result<Vec<Vec<f32>>, DynError>;
+
+    /// Runs a task according to its kind. `images` holds the resolved bytes of `task.images`, in order.
+    async fn run_task(&self, task: &InferenceTask, images: &[Vec<u8>]) -> Result<TaskOutput, DynError> {
+        match &task.kind {
+            TaskKind::Generate => Ok(TaskOutput::Text(self.generate(&task.model_name, &task.prompt, images, &task.options).await?)),
+            TaskKind::Chat(request) => Ok(TaskOutput::Text(self.chat(&task.model_name, request, images, &task.options).await?.content)),
+            TaskKind::Embed { input } => Ok(TaskOutput::Embeddings(self.embed(&task.model_name, input, &task.options).await?)),
+        }
+    }
 }
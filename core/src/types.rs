@@ -1,4 +1,16 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub fn unix_timestamp() -> u64 {
+    unix_timestamp_millis() / 1000
+}
+
+pub fn unix_timestamp_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct NodeId(pub String);
@@ -17,12 +29,167 @@ pub enum TaskStatus {
     Failed(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self { role, content: content.into() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ChatRequest {
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+}
+
+/// Sampling parameters for a single generation. Unset fields fall back to the model defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct GenerationOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+}
+
+/// Upper bounds a worker enforces on requested options, regardless of what the task asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct GenerationLimits {
+    pub max_tokens: Option<u32>,
+    pub max_context_window: Option<u32>,
+}
+
+impl GenerationOptions {
+    /// Applies worker limits. An unset value is treated as "unbounded" and gets the limit itself.
+    pub fn clamp(&self, limits: &GenerationLimits) -> Self {
+        let cap = |requested: Option<u32>, limit: Option<u32>| match (requested, limit) {
+            (Some(r), Some(l)) => Some(r.min(l)),
+            (None, Some(l)) => Some(l),
+            (r, None) => r,
+        };
+        Self {
+            max_tokens: cap(self.max_tokens, limits.max_tokens),
+            context_window: cap(self.context_window, limits.max_context_window),
+            ..self.clone()
+        }
+    }
+}
+
+/// Content-addressed reference to a blob (e.g. an image). Only the reference travels
+/// in gossip; workers fetch the bytes from the task author and check them against the hash.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlobRef {
+    pub hash: String, // Hex-encoded SHA-256 of the content
+    pub size: u64,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+}
+
+impl BlobRef {
+    pub fn for_bytes(data: &[u8], mime_type: Option<String>) -> Self {
+        Self {
+            hash: hex::encode(Sha256::digest(data)),
+            size: data.len() as u64,
+            mime_type,
+        }
+    }
+
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() as u64 == self.size && hex::encode(Sha256::digest(data)) == self.hash
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub enum TaskKind {
+    #[default]
+    Generate,
+    Chat(ChatRequest),
+    Embed { input: Vec<String> },
+}
+
+impl TaskKind {
+    /// The model capability a worker needs to run this kind of task.
+    pub fn required_capability(&self) -> ModelCapability {
+        match self {
+            TaskKind::Generate | TaskKind::Chat(_) => ModelCapability::Completion,
+            TaskKind::Embed { .. } => ModelCapability::Embedding,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TaskOutput {
+    Text(String),
+    Embeddings(Vec<Vec<f32>>),
+}
+
+impl TaskOutput {
+    /// Hex SHA-256 of the JSON encoding, committed to in receipts.
+    pub fn hash(&self) -> String {
+        let bytes = serde_json::to_vec(self).expect("task output serializes");
+        hex::encode(Sha256::digest(bytes))
+    }
+}
+
+impl std::fmt::Display for TaskOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskOutput::Text(text) => write!(f, "{}", text),
+            TaskOutput::Embeddings(vectors) => {
+                let dims = vectors.first().map(|v| v.len()).unwrap_or(0);
+                write!(f, "[{} embeddings x {} dims]", vectors.len(), dims)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InferenceTask {
     pub id: String,
     pub model_name: String,
-    pub prompt: String,
+    pub prompt: String, // Unused for chat and embed tasks, their input lives in `kind`
     pub status: TaskStatus,
+    #[serde(default)]
+    pub kind: TaskKind,
+    #[serde(default)]
+    pub options: GenerationOptions,
+    #[serde(default)]
+    pub images: Vec<BlobRef>, // Attached to the prompt, or to the last user message for chat tasks
+    #[serde(default)]
+    pub max_price: Option<f64>, // Most the requester will pay in credits; None means unpaid
+    #[serde(default)]
+    pub requester: Option<String>, // Set by the publishing node to its peer id
+    #[serde(default)]
+    pub created_at: u64, // Unix seconds, reset when published
+    #[serde(default)]
+    pub stamp: Option<u64>, // Proof-of-work nonce, see `mint_stamp`
+    #[serde(default)]
+    pub deadline: Option<u64>, // Unix seconds; workers drop the task after this
 }
 
 impl InferenceTask {
@@ -32,7 +199,159 @@ impl InferenceTask {
             model_name: model.into(),
             prompt: prompt.into(),
             status: TaskStatus::Pending,
+            kind: TaskKind::Generate,
+            options: GenerationOptions::default(),
+            images: Vec::new(),
+            max_price: None,
+            requester: None,
+            created_at: unix_timestamp(),
+            stamp: None,
+            deadline: None,
+        }
+    }
+
+    pub fn chat(id: impl Into<String>, model: impl Into<String>, request: ChatRequest) -> Self {
+        Self {
+            id: id.into(),
+            model_name: model.into(),
+            prompt: String::new(),
+            status: TaskStatus::Pending,
+            kind: TaskKind::Chat(request),
+            options: GenerationOptions::default(),
+            images: Vec::new(),
+            max_price: None,
+            requester: None,
+            created_at: unix_timestamp(),
+            stamp: None,
+            deadline: None,
+        }
+    }
+
+    pub fn embed(id: impl Into<String>, model: impl Into<String>, input: Vec<String>) -> Self {
+        Self {
+            id: id.into(),
+            model_name: model.into(),
+            prompt: String::new(),
+            status: TaskStatus::Pending,
+            kind: TaskKind::Embed { input },
+            options: GenerationOptions::default(),
+            images: Vec::new(),
+            max_price: None,
+            requester: None,
+            created_at: unix_timestamp(),
+            stamp: None,
+            deadline: None,
+        }
+    }
+
+    pub fn with_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_images(mut self, images: Vec<BlobRef>) -> Self {
+        self.images = images;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: u64) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_max_price(mut self, max_price: f64) -> Self {
+        self.max_price = Some(max_price);
+        self
+    }
+
+    /// Hex SHA-256 of the whole task as published.
+    pub fn hash(&self) -> String {
+        let bytes = serde_json::to_vec(self).expect("task serializes");
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    /// Hex SHA-256 of just the model input: prompt, chat messages or embed input, plus image hashes.
+    pub fn input_hash(&self) -> String {
+        let bytes = serde_json::to_vec(&(&self.prompt, &self.kind, &self.images)).expect("task input serializes");
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    /// Proof of work against spam: finds a `stamp` so that the SHA-256 of the task id, input hash
+    /// and stamp starts with `bits` zero bits. Each extra bit doubles the expected work.
+    pub fn mint_stamp(&mut self, bits: u32) {
+        let prefix = self.stamp_prefix();
+        let nonce = (0..).find(|n| stamp_work(&prefix, *n) >= bits).expect("a stamp exists");
+        self.stamp = Some(nonce);
+    }
+
+    pub fn has_stamp(&self, bits: u32) -> bool {
+        self.stamp.is_some_and(|n| stamp_work(&self.stamp_prefix(), n) >= bits)
+    }
+
+    fn stamp_prefix(&self) -> String {
+        format!("{}:{}:", self.id, self.input_hash())
+    }
+
+    /// Capabilities a worker's model must have to run this task.
+    pub fn required_capabilities(&self) -> Vec<ModelCapability> {
+        let mut required = vec![self.kind.required_capability()];
+        if !self.images.is_empty() {
+            required.push(ModelCapability::Vision);
         }
+        required
+    }
+}
+
+/// Leading zero bits of the stamp hash.
+fn stamp_work(prefix: &str, nonce: u64) -> u32 {
+    let digest = Sha256::new().chain_update(prefix).chain_update(nonce.to_string()).finalize();
+    let mut bits = 0;
+    for byte in digest {
+        bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Sent back by a worker on `xnet/results/v1`; the receipt is what the requester countersigns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub task_id: String,
+    pub worker: String,
+    pub output: TaskOutput,
+    pub receipt: crate::receipt::SignedReceipt,
+}
+
+/// Published by a requester on `xnet/cancel/v1` when it no longer wants a task's result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskCancel {
+    pub task_id: String,
+    pub requester: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskRole {
+    Requester,
+    Worker,
+}
+
+/// What a node remembers about a task it published or worked on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub task: InferenceTask,
+    pub role: TaskRole,
+    pub status: TaskStatus,
+    pub output: Option<TaskOutput>,
+    pub receipt: Option<crate::receipt::SignedReceipt>,
+    pub updated_at: u64, // Unix seconds
+}
+
+impl TaskRecord {
+    pub fn new(task: InferenceTask, role: TaskRole) -> Self {
+        let status = task.status.clone();
+        Self { task, role, status, output: None, receipt: None, updated_at: unix_timestamp() }
     }
 }
 
@@ -42,6 +361,10 @@ pub struct NodeMetrics {
     pub tasks_processed: u64,
     pub tasks_relayed: u64,
     pub credits: f64,
+    #[serde(default)]
+    pub running_tasks: u64,
+    #[serde(default)]
+    pub queued_tasks: u64, // Waiting for an inference slot
 }
 
 impl NodeMetrics {
@@ -50,6 +373,82 @@ impl NodeMetrics {
     }
 }
 
+// Peer Table Types
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionState {
+    Connected,
+    Disconnected,
+}
+
+/// What a node knows about a peer it has been connected to, from the connection itself,
+/// identify and ping.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+    pub agent_version: Option<String>,
+    pub protocol_version: Option<String>, // Identify protocol version, "/xnet/<version>" for xNet nodes
+    pub protocols: Vec<String>,
+    pub rtt_ms: Option<u64>,
+    pub state: ConnectionState,
+    pub connections: u32,
+    pub last_seen: u64, // Unix seconds
+}
+
+impl PeerInfo {
+    /// xNet protocol version the peer speaks, if it is an xNet node.
+    pub fn xnet_version(&self) -> Option<&str> {
+        self.protocol_version.as_deref()?.strip_prefix("/xnet/")
+    }
+}
+
+// Model Metadata Types
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ModelDetails {
+    pub format: Option<String>,
+    pub family: Option<String>,
+    pub parameter_size: Option<String>,
+    pub quantization_level: Option<String>,
+    pub context_length: Option<u64>,
+    pub parameters: Option<String>, // Raw Modelfile parameters, e.g. "stop \"<|im_end|>\""
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelCapability {
+    Completion,
+    Embedding,
+    Vision,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub name: String,
+    pub digest: String,
+    pub size: u64, // Bytes on disk
+    pub details: ModelDetails,
+    #[serde(default)]
+    pub capabilities: Vec<ModelCapability>,
+}
+
+impl ModelInfo {
+    pub fn supports(&self, capability: ModelCapability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// Gossiped by providers so requesters know which peers can serve which models. Providers
+/// repeat it periodically; an empty list withdraws the peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapabilityAdvertisement {
+    pub peer_id: String,
+    pub models: Vec<ModelInfo>,
+    #[serde(default)]
+    pub issued_at: u64, // Unix milliseconds; also keeps repeats from being dropped as duplicates
+}
+
 // Distributed Inference Types
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,12 +465,38 @@ pub enum PipelineEvent {
     Error { session_id: String, error: String },
 }
 
+impl PipelineEvent {
+    pub fn session_id(&self) -> &str {
+        match self {
+            PipelineEvent::InitSession { session_id, .. }
+            | PipelineEvent::ForwardPass { session_id, .. }
+            | PipelineEvent::Result { session_id, .. }
+            | PipelineEvent::Error { session_id, .. } => session_id,
+        }
+    }
+}
+
 // Verification System Types
 
+/// What a challenge disputes: a pipeline layer, or a specific signed inference receipt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ChallengeTarget {
+    Session { session_id: String, layer: usize },
+    Receipt { task_id: String, receipt_hash: String },
+}
+
+impl std::fmt::Display for ChallengeTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChallengeTarget::Session { session_id, layer } => write!(f, "session {} layer {}", session_id, layer),
+            ChallengeTarget::Receipt { task_id, receipt_hash } => write!(f, "task {} receipt {}", task_id, receipt_hash),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Challenge {
-    pub target_session_id: String,
-    pub target_layer: usize,
+    pub target: ChallengeTarget,
     pub challenger_id: String,
 }
 
@@ -83,7 +508,7 @@ pub enum VoteType {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vote {
-    pub session_id: String,
+    pub target: ChallengeTarget,
     pub voter_id: String,
     pub vote: VoteType,
 }
@@ -119,3 +544,24 @@ pub enum FLEvent {
     GlobalModelUpdate(FLTask),
     LocalUpdate(FLUpdate),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_applies_worker_limits() {
+        let limits = GenerationLimits { max_tokens: Some(1024), max_context_window: Some(4096) };
+        let over = GenerationOptions { max_tokens: Some(5000), context_window: Some(8192), temperature: Some(0.7), ..Default::default() };
+        let clamped = over.clamp(&limits);
+        assert_eq!((clamped.max_tokens, clamped.context_window), (Some(1024), Some(4096)));
+        assert_eq!(clamped.temperature, Some(0.7));
+
+        let unset = GenerationOptions::default().clamp(&limits);
+        assert_eq!((unset.max_tokens, unset.context_window), (Some(1024), Some(4096)));
+
+        let under = GenerationOptions { max_tokens: Some(256), context_window: Some(2048), ..Default::default() };
+        assert_eq!(under.clamp(&limits), under);
+        assert_eq!(GenerationOptions::default().clamp(&GenerationLimits::default()), GenerationOptions::default());
+    }
+}
@@ -2,21 +2,25 @@ import requests
 import json
 import sys
 
-API_URL = "http://localhost:3030/api/v1/task"
+API_URL = "http://localhost:3030/api/v1/chat"
 
 def chat_with_xnet():
     print("=== xNet Chat Demo (Service API) ===")
     print(f"Connecting to Local Node at {API_URL}...")
     
+    # Conversation history is sent with every turn so the model keeps context
+    history = []
+    
     while True:
         try:
             prompt = input("\nYou: ")
             if prompt.lower() in ['exit', 'quit']:
                 break
             
+            history.append({"role": "user", "content": prompt})
             payload = {
                 "model": "tinyllama",
-                "prompt": prompt
+                "messages": history
             }
             
             print("Sending task to xNet...")
@@ -25,12 +29,14 @@ def chat_with_xnet():
             if response.status_code == 200:
                 data = response.json()
                 if data.get("status") == "completed":
-                    result = data.get("result", "No result")
-                    print(f"\nxNet AI: {result}\n")
+                    message = data.get("message", {})
+                    history.append(message)
+                    print(f"\nxNet AI: {message.get('content', 'No result')}\n")
                 else:
                     print(f"xNet: Task Queued! (ID: {data.get('task_id', 'unknown')})")
                     print("(Note: Task sent to network)")
             else:
+                history.pop()
                 print(f"Error: {response.status_code} - {response.text}")
                 
         except requests.exceptions.ConnectionError:
@@ -0,0 +1,89 @@
+use std::fmt;
+use xnet_core::{DynError, KeystoreError};
+use xnet_network::{ConfigError, NetworkError};
+use xnet_runtime::RuntimeError;
+
+/// What a command returns when it fails. The frontend gets the message as a string.
+#[derive(Debug)]
+pub enum CommandError {
+    NodeNotRunning,
+    InvalidBootnode(String),
+    /// The node didn't start
+    Startup(NetworkError),
+    /// A call to the running node failed
+    Node(NetworkError),
+    /// Publishing through the node failed
+    Publish(DynError),
+    Runtime(RuntimeError),
+    Config(ConfigError),
+    Identity(KeystoreError),
+    Io(std::io::Error),
+    App(tauri::Error),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::NodeNotRunning => write!(f, "Node not running"),
+            CommandError::InvalidBootnode(addr) => write!(f, "Invalid bootnode address: {}", addr),
+            CommandError::Startup(e) => write!(f, "Failed to start node: {}", e),
+            CommandError::Node(e) => write!(f, "{}", e),
+            CommandError::Publish(e) => write!(f, "{}", e),
+            CommandError::Runtime(e) => write!(f, "{}", e),
+            CommandError::Config(e) => write!(f, "{}", e),
+            CommandError::Identity(e) => write!(f, "{}", e),
+            CommandError::Io(e) => write!(f, "{}", e),
+            CommandError::App(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl serde::Serialize for CommandError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl From<NetworkError> for CommandError {
+    fn from(e: NetworkError) -> Self {
+        CommandError::Node(e)
+    }
+}
+
+impl From<DynError> for CommandError {
+    fn from(e: DynError) -> Self {
+        CommandError::Publish(e)
+    }
+}
+
+impl From<RuntimeError> for CommandError {
+    fn from(e: RuntimeError) -> Self {
+        CommandError::Runtime(e)
+    }
+}
+
+impl From<ConfigError> for CommandError {
+    fn from(e: ConfigError) -> Self {
+        CommandError::Config(e)
+    }
+}
+
+impl From<KeystoreError> for CommandError {
+    fn from(e: KeystoreError) -> Self {
+        CommandError::Identity(e)
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(e: std::io::Error) -> Self {
+        CommandError::Io(e)
+    }
+}
+
+impl From<tauri::Error> for CommandError {
+    fn from(e: tauri::Error) -> Self {
+        CommandError::App(e)
+    }
+}
@@ -1,10 +1,34 @@
+mod error;
+mod logs;
+
+use crate::error::CommandError;
 use tauri::{Emitter, Manager};
-use xnet_network::{P2PNode, NetworkEvent, NetworkInterface};
+use xnet_network::{P2PNode, NetworkError, NetworkEvent, NetworkInterface, NodeConfig};
 use tokio::sync::Mutex;
 use std::sync::Arc;
 
 struct AppState {
     node: Arc<Mutex<Option<P2PNode>>>,
+    services: Arc<Mutex<Option<NodeServices>>>,
+    config: Arc<Mutex<NodeConfig>>,
+    /// Flushes the log file when the app exits
+    _log_guard: Option<tracing_appender::non_blocking::WorkerGuard>,
+}
+
+/// Background work that belongs to a running node and is stopped with it.
+struct NodeServices {
+    stop_api: tokio::sync::oneshot::Sender<()>,
+    api: tauri::async_runtime::JoinHandle<()>,
+    tasks: Vec<tauri::async_runtime::JoinHandle<()>>, // Event forwarding and idle detection
+}
+
+fn config_path(app: &tauri::AppHandle) -> Result<std::path::PathBuf, CommandError> {
+    let data_dir = app.path().app_data_dir()?;
+    Ok(data_dir.join(xnet_network::CONFIG_FILE))
+}
+
+async fn ollama_runtime(state: &AppState) -> xnet_runtime::OllamaRuntime {
+    state.config.lock().await.ollama_runtime()
 }
 
 #[derive(serde::Serialize)]
@@ -41,67 +65,65 @@ fn get_system_specs() -> SystemSpecs {
 }
 
 #[tauri::command]
-async fn start_node(state: tauri::State<'_, AppState>, app: tauri::AppHandle, bootnode: Option<String>) -> Result<String, String> {
+async fn start_node(state: tauri::State<'_, AppState>, app: tauri::AppHandle, bootnode: Option<String>, passphrase: Option<String>) -> Result<String, CommandError> {
+    launch_node(&state, app, bootnode, passphrase).await
+}
+
+#[tauri::command]
+async fn stop_node(state: tauri::State<'_, AppState>) -> Result<String, CommandError> {
+    if shutdown_node(&state).await? {
+        Ok("Node stopped".to_string())
+    } else {
+        Ok("Node not running".to_string())
+    }
+}
+
+/// Stops the node if it runs and starts it again, picking up changed settings.
+#[tauri::command]
+async fn restart_node(state: tauri::State<'_, AppState>, app: tauri::AppHandle, bootnode: Option<String>, passphrase: Option<String>) -> Result<String, CommandError> {
+    shutdown_node(&state).await?;
+    launch_node(&state, app, bootnode, passphrase).await
+}
+
+async fn launch_node(state: &AppState, app: tauri::AppHandle, bootnode: Option<String>, passphrase: Option<String>) -> Result<String, CommandError> {
     let mut node_guard = state.node.lock().await;
 
     if node_guard.is_some() {
         return Ok("Node already running".to_string());
     }
 
-    let mut bootnodes = Vec::new();
+    let mut config = state.config.lock().await.clone();
     if let Some(addr_str) = bootnode {
         if !addr_str.is_empty() {
              use std::str::FromStr;
              if let Ok(addr) = libp2p::Multiaddr::from_str(&addr_str) {
-                 bootnodes.push(addr);
+                 config.bootnodes.push(addr);
              } else {
-                 return Err("Invalid bootnode address".to_string());
+                 return Err(CommandError::InvalidBootnode(addr_str));
              }
         }
     }
+    let (idle_threshold, api_addr, runtime) = (config.idle_threshold_secs, config.api_addr, config.ollama_runtime());
 
     // Identity Persistence
     let app_handle = app.clone();
-    let data_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?;
+    let data_dir = app_handle.path().app_data_dir()?;
     if !data_dir.exists() {
-        std::fs::create_dir_all(&data_dir).map_err(|e| e.to_string())?;
-    }
-    let keypath = data_dir.join("identity.bin");
-    
-    let keypair_bytes = if keypath.exists() {
-        std::fs::read(&keypath).map_err(|e| e.to_string())?
-    } else {
-        use libp2p::identity;
-        let keys = identity::Keypair::generate_ed25519();
-        let bytes = keys.to_protobuf_encoding().map_err(|e| e.to_string())?;
-        std::fs::write(&keypath, &bytes).map_err(|e| e.to_string())?;
-        bytes
-    };
-
-    // Wallet Persistence
-    let wallet_path = data_dir.join("wallet.json");
-    #[derive(serde::Serialize, serde::Deserialize)]
-    struct Wallet {
-        balance: f64,
+        std::fs::create_dir_all(&data_dir)?;
     }
+    let keys = xnet_core::load_identity(&data_dir, passphrase.as_deref().filter(|p| !p.is_empty()))?;
+    let keypair_bytes = keys.to_protobuf_encoding().map_err(|e| CommandError::Startup(NetworkError::Identity(e.to_string())))?;
 
-    let initial_balance = if wallet_path.exists() {
-        let data = std::fs::read_to_string(&wallet_path).map_err(|e| e.to_string())?;
-        serde_json::from_str::<Wallet>(&data).map_err(|e| e.to_string())?.balance
-    } else {
-        0.0
-    };
-
-    let node = P2PNode::new(bootnodes, Some(keypair_bytes), initial_balance).await.map_err(|e| e.to_string())?;
+    // Credits, task history and known peers live in the node store (node.redb in the data dir)
+    let node = P2PNode::new(Some(keypair_bytes), Some(data_dir.clone()), config).await.map_err(CommandError::Startup)?;
     *node_guard = Some(node.clone());
 
     let mut rx = node.subscribe();
     
     // Spawn event listener
     let app_event = app.clone();
-    let wallet_path_clone = wallet_path.clone();
 
-    tauri::async_runtime::spawn(async move {
+    let events = tauri::async_runtime::spawn(async move {
         while let Ok(event) = rx.recv().await {
             match event {
                 NetworkEvent::PeerConnected(id) => {
@@ -110,6 +132,9 @@ async fn start_node(state: tauri::State<'_, AppState>, app: tauri::AppHandle, bo
                 NetworkEvent::PeerDisconnected(id) => {
                     let _ = app_event.emit("peer-disconnected", id);
                 }
+                NetworkEvent::PeerUpdated(peer) => {
+                    let _ = app_event.emit("peer-updated", peer);
+                }
                 NetworkEvent::Message(msg) => {
                     let _ = app_event.emit("log-message", msg);
                 }
@@ -120,11 +145,6 @@ async fn start_node(state: tauri::State<'_, AppState>, app: tauri::AppHandle, bo
                     let _ = app_event.emit("dht-event", msg);
                 }
                 NetworkEvent::MetricsUpdated(metrics) => {
-                    // Save wallet balance
-                    let wallet = Wallet { balance: metrics.credits };
-                    if let Ok(json) = serde_json::to_string(&wallet) {
-                        let _ = std::fs::write(&wallet_path_clone, json);
-                    }
                     let _ = app_event.emit("metrics-updated", metrics);
                 }
                 NetworkEvent::PipelineEvent(event) => {
@@ -136,6 +156,15 @@ async fn start_node(state: tauri::State<'_, AppState>, app: tauri::AppHandle, bo
                 NetworkEvent::FLEvent(event) => {
                     let _ = app_event.emit("fl-event", event);
                 }
+                NetworkEvent::CapabilitiesAdvertised(advert) => {
+                    let _ = app_event.emit("capabilities-advertised", advert);
+                }
+                NetworkEvent::LedgerEntryAdded(entry) => {
+                    let _ = app_event.emit("ledger-entry", entry);
+                }
+                NetworkEvent::TaskCompleted(result) => {
+                    let _ = app_event.emit("task-completed", result);
+                }
             }
         }
     });
@@ -144,7 +173,7 @@ async fn start_node(state: tauri::State<'_, AppState>, app: tauri::AppHandle, bo
     let app_idle = app.clone();
     let node_idle = node.clone();
     
-    tauri::async_runtime::spawn(async move {
+    let idle_watcher = tauri::async_runtime::spawn(async move {
         use user_idle::UserIdle;
         use std::time::Duration;
         
@@ -155,13 +184,13 @@ async fn start_node(state: tauri::State<'_, AppState>, app: tauri::AppHandle, bo
             // Check idle time
             let idle_seconds = UserIdle::get_time().map(|i| i.as_seconds()).unwrap_or(0);
             
-            if idle_seconds > 60 && !is_muscle_mode {
+            if idle_seconds > idle_threshold && !is_muscle_mode {
                 is_muscle_mode = true;
                 let _ = app_idle.emit("mode-change", "Muscle (Idle)");
                 
                 // Announce Provider Capability
                 if let Err(e) = node_idle.announce_provider().await {
-                   println!("Failed to announce provider: {:?}", e);
+                   tracing::warn!("Failed to announce provider: {}", e);
                 }
                 
             } else if idle_seconds < 5 && is_muscle_mode {
@@ -173,101 +202,150 @@ async fn start_node(state: tauri::State<'_, AppState>, app: tauri::AppHandle, bo
 
     // Start HTTP API Server
     let api_node = node.clone();
-    tauri::async_runtime::spawn(async move {
-        use warp::Filter;
-        
-        // POST /api/v1/task
-        let task_route = warp::post()
-            .and(warp::path("api"))
-            .and(warp::path("v1"))
-            .and(warp::path("task"))
-            .and(warp::body::json())
-            .then(move |req: CreateTaskRequest| {
-                async move {
-                    let task_id = uuid::Uuid::new_v4().to_string();
-                    println!("API received task: {} - {}", task_id, req.prompt);
-                    
-                    // Process locally (single node mode)
-                    use xnet_core::RuntimeInterface;
-                    let runtime = xnet_runtime::OllamaRuntime::new("http://localhost:11434");
-                    
-                    match runtime.generate(&req.model, &req.prompt).await {
-                        Ok(response) => {
-                            let preview: String = response.chars().take(50).collect();
-                            println!("[LOCAL AI] Task {} completed: {}...", task_id, preview);
-                            let escaped = response.replace("\"", "\\\"").replace("\n", "\\n");
-                            warp::reply::with_status(
-                                format!("{{\"status\": \"completed\", \"task_id\": \"{}\", \"result\": \"{}\"}}", task_id, escaped),
-                                warp::http::StatusCode::OK,
-                            )
-                        },
-                        Err(e) => {
-                            println!("[LOCAL AI] Task {} failed: {}", task_id, e);
-                            warp::reply::with_status(
-                                format!("{{\"error\": \"{}\"}}", e),
-                                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
-                            )
-                        }
-                    }
-                }
-            });
-        
-        // Fix for "AsRef not general enough" - ensure filter is boxed
-        let task_route = task_route.boxed();
-
-        println!("Starting API Server on 0.0.0.0:3030");
-        warp::serve(task_route).run(([0, 0, 0, 0], 3030)).await;
+    let (stop_api, api_stopped) = tokio::sync::oneshot::channel::<()>();
+    let api = tauri::async_runtime::spawn(async move {
+        xnet_network::api::serve(api_node, runtime, api_addr, async move {
+            let _ = api_stopped.await;
+        }).await;
     });
 
+    *state.services.lock().await = Some(NodeServices { stop_api, api, tasks: vec![events, idle_watcher] });
     Ok("Node started successfully".to_string())
 }
 
-#[derive(serde::Deserialize)]
-struct CreateTaskRequest {
-    model: String,
-    prompt: String,
+/// Stops the background tasks and the API server, then shuts the node down. Returns false if no node was running.
+async fn shutdown_node(state: &AppState) -> Result<bool, CommandError> {
+    let mut node_guard = state.node.lock().await;
+    let Some(node) = node_guard.take() else {
+        return Ok(false);
+    };
+    if let Some(services) = state.services.lock().await.take() {
+        for task in services.tasks {
+            task.abort();
+        }
+        let _ = services.stop_api.send(());
+        let _ = services.api.await;
+    }
+    node.shutdown().await?;
+    Ok(true)
 }
 
 #[tauri::command]
-async fn test_pipeline_event(state: tauri::State<'_, AppState>) -> Result<(), String> {
+async fn test_pipeline_event(state: tauri::State<'_, AppState>) -> Result<(), CommandError> {
     let node_guard = state.node.lock().await;
     if let Some(node) = node_guard.as_ref() {
         let event = xnet_core::PipelineEvent::InitSession {
             session_id: "test-session-123".to_string(),
             model: "llama-3-8b".to_string(),
         };
-        node.publish_pipeline_event(event).await.map_err(|e| e.to_string())?;
+        node.publish_pipeline_event(event).await?;
         Ok(())
     } else {
-        Err("Node not running".to_string())
+        Err(CommandError::NodeNotRunning)
     }
 }
 
 #[tauri::command]
-async fn test_verification_event(state: tauri::State<'_, AppState>) -> Result<(), String> {
+async fn test_verification_event(state: tauri::State<'_, AppState>) -> Result<(), CommandError> {
     let node_guard = state.node.lock().await;
     if let Some(node) = node_guard.as_ref() {
         let event = xnet_core::VerificationEvent::ChallengeIssued(xnet_core::Challenge {
-            target_session_id: "test-session-123".to_string(),
-            target_layer: 10,
-            challenger_id: "node-challenger-xyz".to_string(),
+            target: xnet_core::ChallengeTarget::Session { session_id: "test-session-123".to_string(), layer: 10 },
+            challenger_id: node.peer_id().to_string(),
         });
-        node.publish_verification_event(event).await.map_err(|e| e.to_string())?;
+        node.publish_verification_event(event).await?;
         Ok(())
     } else {
-        Err("Node not running".to_string())
+        Err(CommandError::NodeNotRunning)
     }
 }
 
 #[tauri::command]
-async fn list_models() -> Result<Vec<String>, String> {
-    use xnet_runtime::OllamaRuntime;
-    let runtime = OllamaRuntime::new("http://localhost:11434");
-    runtime.list_models().await.map_err(|e| e.to_string())
+async fn get_ledger(state: tauri::State<'_, AppState>) -> Result<Vec<xnet_core::SignedEntry>, CommandError> {
+    let node_guard = state.node.lock().await;
+    if let Some(node) = node_guard.as_ref() {
+        Ok(node.ledger().await?)
+    } else {
+        Err(CommandError::NodeNotRunning)
+    }
 }
 
 #[tauri::command]
-async fn test_fl_event(state: tauri::State<'_, AppState>) -> Result<(), String> {
+async fn get_peers(state: tauri::State<'_, AppState>) -> Result<Vec<xnet_core::PeerInfo>, CommandError> {
+    let node_guard = state.node.lock().await;
+    if let Some(node) = node_guard.as_ref() {
+        Ok(node.peers().await?)
+    } else {
+        Err(CommandError::NodeNotRunning)
+    }
+}
+
+#[tauri::command]
+async fn get_tasks(state: tauri::State<'_, AppState>) -> Result<Vec<xnet_core::TaskRecord>, CommandError> {
+    let node_guard = state.node.lock().await;
+    if let Some(node) = node_guard.as_ref() {
+        Ok(node.tasks().await?)
+    } else {
+        Err(CommandError::NodeNotRunning)
+    }
+}
+
+#[tauri::command]
+async fn list_models(state: tauri::State<'_, AppState>) -> Result<Vec<String>, CommandError> {
+    let runtime = ollama_runtime(&state).await;
+    Ok(runtime.list_models().await?)
+}
+
+#[tauri::command]
+async fn pull_model(state: tauri::State<'_, AppState>, app: tauri::AppHandle, name: String) -> Result<(), CommandError> {
+    use futures::StreamExt;
+    let runtime = ollama_runtime(&state).await;
+    let mut progress = Box::pin(runtime.pull_model(&name).await?);
+
+    while let Some(update) = progress.next().await {
+        let update = update?;
+        let _ = app.emit("model-pull-progress", serde_json::json!({ "model": &name, "progress": update }));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_model(state: tauri::State<'_, AppState>, name: String) -> Result<(), CommandError> {
+    let runtime = ollama_runtime(&state).await;
+    Ok(runtime.delete_model(&name).await?)
+}
+
+#[tauri::command]
+async fn show_model(state: tauri::State<'_, AppState>, name: String) -> Result<xnet_core::ModelInfo, CommandError> {
+    let runtime = ollama_runtime(&state).await;
+    Ok(runtime.show_model(&name).await?)
+}
+
+#[tauri::command]
+async fn running_models(state: tauri::State<'_, AppState>) -> Result<Vec<xnet_runtime::RunningModel>, CommandError> {
+    let runtime = ollama_runtime(&state).await;
+    Ok(runtime.running_models().await?)
+}
+
+#[tauri::command]
+async fn get_settings(state: tauri::State<'_, AppState>) -> Result<NodeConfig, CommandError> {
+    Ok(state.config.lock().await.clone())
+}
+
+/// Validates and saves the settings. A running node keeps its current settings until it is restarted.
+#[tauri::command]
+async fn update_settings(state: tauri::State<'_, AppState>, app: tauri::AppHandle, settings: NodeConfig) -> Result<NodeConfig, CommandError> {
+    let path = config_path(&app)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    settings.save(&path)?;
+    *state.config.lock().await = settings.clone();
+    Ok(settings)
+}
+
+#[tauri::command]
+async fn test_fl_event(state: tauri::State<'_, AppState>) -> Result<(), CommandError> {
     let node_guard = state.node.lock().await;
     if let Some(node) = node_guard.as_ref() {
         let event = xnet_core::FLEvent::LocalUpdate(xnet_core::FLUpdate {
@@ -277,20 +355,45 @@ async fn test_fl_event(state: tauri::State<'_, AppState>) -> Result<(), String>
             gradients: vec![0.01; 10],
             metrics: "loss: 0.042".to_string(),
         });
-        node.publish_fl_event(event).await.map_err(|e| e.to_string())?;
+        node.publish_fl_event(event).await?;
         Ok(())
     } else {
-        Err("Node not running".to_string())
+        Err(CommandError::NodeNotRunning)
     }
 }
 
+/// Copies the recent log files into one file in the downloads folder and returns its path.
+#[tauri::command]
+fn export_logs(app: tauri::AppHandle) -> Result<String, CommandError> {
+    let path = logs::export(&app.path().app_log_dir()?, &app.path().download_dir()?)?;
+    Ok(path.display().to_string())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
-        .manage(AppState { node: Arc::new(Mutex::new(None)) })
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![start_node, get_system_specs, test_pipeline_event, test_verification_event, test_fl_event, list_models])
+        .setup(|app| {
+            // Settings come from config.toml in the app data dir, with XNET_* environment overrides
+            let loaded = config_path(app.handle()).and_then(|path| NodeConfig::load(Some(&path)).map_err(CommandError::from));
+            let log_config = loaded.as_ref().map(|config| config.log.clone()).unwrap_or_default();
+            // Logging needs the settings, so a settings error is only reported once it is up
+            let log_guard = match app.path().app_log_dir().map_err(xnet_core::DynError::from).and_then(|dir| logs::init(&dir, &log_config)) {
+                Ok(guard) => Some(guard),
+                Err(e) => {
+                    eprintln!("Failed to set up logging: {}", e);
+                    None
+                }
+            };
+            let config = loaded.unwrap_or_else(|e| {
+                tracing::warn!("Failed to load settings, using defaults: {}", e);
+                NodeConfig::default()
+            });
+            app.manage(AppState { node: Arc::new(Mutex::new(None)), services: Arc::new(Mutex::new(None)), config: Arc::new(Mutex::new(config)), _log_guard: log_guard });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![start_node, stop_node, restart_node, get_system_specs, test_pipeline_event, test_verification_event, test_fl_event, list_models, get_ledger, get_peers, get_tasks, pull_model, delete_model, show_model, running_models, get_settings, update_settings, export_logs])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,58 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer, Registry};
+use xnet_core::DynError;
+use xnet_network::{LogConfig, LogFormat};
+
+const LOG_PREFIX: &str = "xnet";
+const LOG_SUFFIX: &str = "log";
+/// Days of log files kept in the log dir
+const MAX_LOG_FILES: usize = 7;
+
+/// Logs to stdout and to a daily log file in `dir`. The file stops being written when the guard is dropped.
+pub fn init(dir: &Path, log: &LogConfig) -> Result<WorkerGuard, DynError> {
+    let filter = EnvFilter::try_new(&log.level)?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_PREFIX)
+        .filename_suffix(LOG_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(dir)?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let file: Box<dyn Layer<Registry> + Send + Sync> = match log.format {
+        LogFormat::Text => fmt::layer().with_writer(writer).with_ansi(false).boxed(),
+        LogFormat::Json => fmt::layer().json().with_writer(writer).boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(file)
+        .with(fmt::layer())
+        .with(filter)
+        .try_init()?;
+    Ok(guard)
+}
+
+/// Joins the log files in `dir`, oldest first, into one file in `dest`.
+pub fn export(dir: &Path, dest: &Path) -> std::io::Result<PathBuf> {
+    // File names carry the date, so they sort oldest first
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name().and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(LOG_PREFIX) && n.ends_with(LOG_SUFFIX))
+        })
+        .collect();
+    files.sort();
+
+    let path = dest.join(format!("xnet-logs-{}.log", xnet_core::unix_timestamp()));
+    let mut out = std::fs::File::create(&path)?;
+    for file in files {
+        writeln!(out, "==> {} <==", file.display())?;
+        out.write_all(&std::fs::read(&file)?)?;
+    }
+    Ok(path)
+}
@@ -13,6 +13,17 @@ interface NodeMetrics {
   tasks_processed: number;
   tasks_relayed: number;
   credits: number;
+  running_tasks: number;
+  queued_tasks: number;
+}
+
+interface PeerInfo {
+  peer_id: string;
+  addresses: string[];
+  agent_version: string | null;
+  protocol_version: string | null;
+  rtt_ms: number | null;
+  state: "Connected" | "Disconnected";
 }
 
 function App() {
@@ -21,15 +32,19 @@ function App() {
   const [status, setStatus] = useState("Offline");
   const [logs, setLogs] = useState<string[]>([]);
   const [peers, setPeers] = useState<string[]>([]);
+  const [peerTable, setPeerTable] = useState<Record<string, PeerInfo>>({});
   const [currentMode, setCurrentMode] = useState("Nerve");
   const [models, setModels] = useState<string[]>([]);
-  const [metrics, setMetrics] = useState({
+  const [metrics, setMetrics] = useState<NodeMetrics>({
     uptime_seconds: 0,
     tasks_processed: 0,
     tasks_relayed: 0,
     credits: 0.0,
+    running_tasks: 0,
+    queued_tasks: 0,
   });
   const [bootnodeInput, setBootnodeInput] = useState("");
+  const [passphraseInput, setPassphraseInput] = useState("");
 
   const fetchModels = async () => {
     try {
@@ -54,6 +69,10 @@ function App() {
       addLog(`Peer Disconnected: ${event.payload}`);
     });
 
+    const unlistenPeerUpdate = listen<PeerInfo>("peer-updated", (event) => {
+      setPeerTable((prev) => ({ ...prev, [event.payload.peer_id]: event.payload }));
+    });
+
     const unlistenLog = listen<string>("log-message", (event) => {
       addLog(`Msg: ${event.payload}`);
     });
@@ -90,7 +109,9 @@ function App() {
       let msg = "";
       if (payload.ChallengeIssued) {
         const c = payload.ChallengeIssued;
-        msg = `[Verification] Challenge Issued: Layer ${c.target_layer} for session ${c.target_session_id}`;
+        msg = c.target.Receipt
+          ? `[Verification] Challenge Issued: receipt ${c.target.Receipt.receipt_hash} for task ${c.target.Receipt.task_id}`
+          : `[Verification] Challenge Issued: Layer ${c.target.Session.layer} for session ${c.target.Session.session_id}`;
       }
       else if (payload.ProofSubmitted) msg = `[Verification] Proof Submitted for challenge`;
       else if (payload.ChallengeResolved) {
@@ -115,6 +136,7 @@ function App() {
     return () => {
       unlistenPeerConnect.then((f) => f());
       unlistenPeerDisconnect.then((f) => f());
+      unlistenPeerUpdate.then((f) => f());
       unlistenLog.then((f) => f());
       unlistenDht.then((f) => f());
       unlistenMode.then((f) => f());
@@ -132,7 +154,7 @@ function App() {
   const startNode = async () => {
     try {
       setStatus("Starting...");
-      const res = await invoke("start_node", { bootnode: bootnodeInput });
+      const res = await invoke("start_node", { bootnode: bootnodeInput, passphrase: passphraseInput });
       setStatus("Online (" + res + ")");
       addLog("Node service started.");
       fetchModels();
@@ -142,6 +164,27 @@ function App() {
     }
   };
 
+  const stopNode = async () => {
+    try {
+      setStatus("Stopping...");
+      const res = await invoke("stop_node");
+      setStatus("Offline");
+      addLog(String(res));
+    } catch (e) {
+      setStatus("Error: " + e);
+      addLog("Failed to stop node: " + e);
+    }
+  };
+
+  const exportLogs = async () => {
+    try {
+      const path = await invoke<string>("export_logs");
+      addLog("Logs exported to " + path);
+    } catch (e) {
+      addLog("Failed to export logs: " + e);
+    }
+  };
+
   const formatUptime = (seconds: number) => {
     const h = Math.floor(seconds / 3600);
     const m = Math.floor((seconds % 3600) / 60);
@@ -221,6 +264,7 @@ function App() {
           <div className="glass rounded-xl p-6 hover:glow transition-all transform hover:scale-105">
             <div className="text-gray-400 text-sm mb-2">Tasks Processed</div>
             <div className="text-3xl font-bold text-xnet-success">{metrics.tasks_processed}</div>
+            <div className="text-gray-500 text-xs mt-1">{metrics.running_tasks} running · {metrics.queued_tasks} queued</div>
           </div>
           <div className="glass rounded-xl p-6 hover:glow transition-all transform hover:scale-105 bg-gradient-to-br from-xnet-purple/20 to-purple-600/20">
             <div className="text-gray-400 text-sm mb-2">Credits</div>
@@ -240,6 +284,13 @@ function App() {
                 onChange={(e) => setBootnodeInput(e.target.value)}
                 className="flex-1 bg-xnet-darker/50 border border-white/10 rounded-lg px-4 py-2 text-white placeholder-gray-500 focus:outline-none focus:border-xnet-purple"
               />
+              <input
+                type="password"
+                placeholder="Key Passphrase (Optional)"
+                value={passphraseInput}
+                onChange={(e) => setPassphraseInput(e.target.value)}
+                className="w-56 bg-xnet-darker/50 border border-white/10 rounded-lg px-4 py-2 text-white placeholder-gray-500 focus:outline-none focus:border-xnet-purple"
+              />
             </div>
             <div className="grid grid-cols-2 md:grid-cols-5 gap-3">
               <button
@@ -249,6 +300,13 @@ function App() {
               >
                 Start Node
               </button>
+              <button
+                onClick={stopNode}
+                disabled={!status.startsWith("Online")}
+                className="bg-gray-700 hover:bg-gray-600 disabled:bg-gray-600 text-white font-semibold py-2 px-4 rounded-lg transition-all"
+              >
+                Stop Node
+              </button>
               <button
                 onClick={() => invoke("test_pipeline_event")}
                 disabled={!status.startsWith("Online")}
@@ -274,6 +332,26 @@ function App() {
           </div>
         </div>
 
+        {/* Peers */}
+        {peers.length > 0 && (
+          <div className="glass rounded-xl p-6">
+            <h2 className="text-xl font-semibold text-white mb-4">Peers</h2>
+            <div className="space-y-2 font-mono text-sm">
+              {peers.map((id) => {
+                const info = peerTable[id];
+                return (
+                  <div key={id} className="flex justify-between gap-4 text-gray-300">
+                    <span className="truncate">{id}</span>
+                    <span className="text-gray-500 whitespace-nowrap">
+                      {info?.protocol_version ?? "identifying..."} · {info?.agent_version ?? "-"} · {info?.rtt_ms != null ? `${info.rtt_ms}ms` : "-"}
+                    </span>
+                  </div>
+                );
+              })}
+            </div>
+          </div>
+        )}
+
         {/* Available Models */}
         {models.length > 0 && (
           <div className="glass rounded-xl p-6">
@@ -293,7 +371,15 @@ function App() {
 
         {/* Logs */}
         <div className="glass rounded-xl p-6">
-          <h2 className="text-xl font-semibold text-white mb-4">Network & Pipeline Logs</h2>
+          <div className="flex justify-between items-center mb-4">
+            <h2 className="text-xl font-semibold text-white">Network & Pipeline Logs</h2>
+            <button
+              onClick={exportLogs}
+              className="bg-gray-700 hover:bg-gray-600 text-white text-sm font-semibold py-1 px-3 rounded-lg transition-all"
+            >
+              Export Logs
+            </button>
+          </div>
           <div className="bg-xnet-darker/50 rounded-lg p-4 h-64 overflow-y-auto font-mono text-sm space-y-1">
             {logs.length === 0 ? (
               <div className="text-gray-500 text-center py-8">No activity yet...</div>
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+
+/// Credits paid out per unit of contribution (Proof of Contribution).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewardWeights {
+    pub uptime_per_minute: f64,
+    pub per_task: f64,
+    pub per_relay: f64,
+    pub per_verification: f64,
+}
+
+impl Default for RewardWeights {
+    fn default() -> Self {
+        Self {
+            uptime_per_minute: 0.1,
+            per_task: 5.0,
+            per_relay: 1.0,
+            per_verification: 2.0,
+        }
+    }
+}
+
+/// Parameters every node on a network should agree on.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkParams {
+    pub rewards: RewardWeights,
+    /// Proof-of-work bits requesters put on their tasks; workers insist on them from requesters
+    /// they have no history with. 0 turns stamps off
+    pub task_stamp_bits: u32,
+}
+
+// Uptime is booked in batches to keep the ledger small
+const UPTIME_CHECKPOINT_MINUTES: u64 = 10;
+
+// How many task ids we remember for de-duplication
+const DEDUP_CAPACITY: usize = 10_000;
+
+/// Decides what contribution is creditable. Each task id is credited at most once per kind,
+/// however many times (or in how many variants) it reaches us.
+pub struct ContributionAccountant {
+    weights: RewardWeights,
+    completed: RecentIds,
+    relayed: RecentIds,
+    unbooked_relays: u64,
+    uptime_minutes_booked: u64,
+}
+
+impl ContributionAccountant {
+    pub fn new(weights: RewardWeights) -> Self {
+        Self {
+            weights,
+            completed: RecentIds::new(DEDUP_CAPACITY),
+            relayed: RecentIds::new(DEDUP_CAPACITY),
+            unbooked_relays: 0,
+            uptime_minutes_booked: 0,
+        }
+    }
+
+    /// A task we actually forwarded to other mesh peers. Returns false for duplicates.
+    pub fn record_relay(&mut self, task_id: &str) -> bool {
+        if !self.relayed.insert(task_id) {
+            return false;
+        }
+        self.unbooked_relays += 1;
+        true
+    }
+
+    /// Credits for a completion the requester has countersigned, or `None` if already credited.
+    pub fn verified_completion(&mut self, task_id: &str) -> Option<f64> {
+        self.completed.insert(task_id).then_some(self.weights.per_task)
+    }
+
+    pub fn verification(&self) -> f64 {
+        self.weights.per_verification
+    }
+
+    /// Relays not yet booked, as (count, credits).
+    pub fn take_relays(&mut self) -> Option<(u64, f64)> {
+        if self.unbooked_relays == 0 {
+            return None;
+        }
+        let count = std::mem::take(&mut self.unbooked_relays);
+        Some((count, count as f64 * self.weights.per_relay))
+    }
+
+    /// Uptime not yet booked once a full checkpoint has accrued, as (minutes, credits).
+    pub fn take_uptime(&mut self, uptime_seconds: u64) -> Option<(u64, f64)> {
+        let minutes = uptime_seconds / 60 - self.uptime_minutes_booked;
+        if minutes < UPTIME_CHECKPOINT_MINUTES {
+            return None;
+        }
+        self.uptime_minutes_booked += minutes;
+        Some((minutes, minutes as f64 * self.weights.uptime_per_minute))
+    }
+}
+
+/// Bounded set of ids, forgetting the oldest first.
+struct RecentIds {
+    order: VecDeque<String>,
+    ids: HashSet<String>,
+    capacity: usize,
+}
+
+impl RecentIds {
+    fn new(capacity: usize) -> Self {
+        Self { order: VecDeque::new(), ids: HashSet::new(), capacity }
+    }
+
+    fn insert(&mut self, id: &str) -> bool {
+        if !self.ids.insert(id.to_string()) {
+            return false;
+        }
+        self.order.push_back(id.to_string());
+        if self.order.len() > self.capacity
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.ids.remove(&oldest);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credits_each_task_once() {
+        let mut accountant = ContributionAccountant::new(RewardWeights::default());
+        assert_eq!(accountant.verified_completion("t1"), Some(5.0));
+        assert_eq!(accountant.verified_completion("t1"), None);
+        assert!(accountant.record_relay("t1"));
+        assert!(!accountant.record_relay("t1"));
+        assert!(accountant.record_relay("t2"));
+        assert_eq!(accountant.take_relays(), Some((2, 2.0)));
+    }
+
+    #[test]
+    fn forgets_the_oldest_ids_past_capacity() {
+        let mut ids = RecentIds::new(2);
+        assert!(ids.insert("a") && ids.insert("b") && ids.insert("c"));
+        assert!(!ids.insert("c"));
+        assert!(ids.insert("a"));
+    }
+
+    #[test]
+    fn take_relays_drains() {
+        let mut accountant = ContributionAccountant::new(RewardWeights::default());
+        assert_eq!(accountant.take_relays(), None);
+        accountant.record_relay("t1");
+        assert_eq!(accountant.take_relays(), Some((1, 1.0)));
+        assert_eq!(accountant.take_relays(), None);
+    }
+
+    #[test]
+    fn books_uptime_in_checkpoints() {
+        let mut accountant = ContributionAccountant::new(RewardWeights::default());
+        assert_eq!(accountant.take_uptime(9 * 60 + 59), None);
+        let (minutes, credits) = accountant.take_uptime(12 * 60).unwrap();
+        assert_eq!(minutes, 12);
+        assert!((credits - 1.2).abs() < 1e-9);
+        // Only what accrued since the last booking counts towards the next checkpoint
+        assert_eq!(accountant.take_uptime(21 * 60), None);
+        assert_eq!(accountant.take_uptime(22 * 60).map(|(minutes, _)| minutes), Some(10));
+    }
+
+    #[test]
+    fn uses_the_network_reward_weights() {
+        let params: NetworkParams = toml::from_str("[rewards]\nper_task = 7.5\nper_relay = 0.5").unwrap();
+        assert_eq!(params.rewards.per_verification, RewardWeights::default().per_verification);
+        let mut accountant = ContributionAccountant::new(params.rewards);
+        assert_eq!(accountant.verified_completion("t1"), Some(7.5));
+        accountant.record_relay("t1");
+        accountant.record_relay("t2");
+        assert_eq!(accountant.take_relays(), Some((2, 1.0)));
+        assert_eq!(accountant.verification(), 2.0);
+    }
+}
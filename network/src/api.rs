@@ -0,0 +1,350 @@
+use crate::validation::MAX_TASK_BYTES;
+use crate::{NetworkError, P2PNode};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::net::SocketAddr;
+use tracing::{debug, info, info_span, warn, Instrument};
+use warp::Filter;
+use xnet_core::{ChatMessage, ChatRequest, ChatRole, DynError, GenerationOptions, InferenceTask, LedgerError, NetworkInterface, TaskOutput};
+use xnet_runtime::{OllamaRuntime, RuntimeError};
+
+// HTTP API
+//
+// Served by both the desktop app and the headless daemon. The inference routes (`task`, `chat`,
+// `embeddings`) run on this node's own Ollama runtime (single node mode), through the node's
+// work queue ahead of network tasks. The control routes manage the node itself and only answer
+// requests from the loopback interface.
+
+/// Serves the API on `addr` until `shutdown` resolves; in-flight requests are allowed to finish.
+pub async fn serve(node: P2PNode, runtime: OllamaRuntime, addr: SocketAddr, shutdown: impl Future<Output = ()> + Send + 'static) {
+    // POST /api/v1/task
+    let task_node = node.clone();
+    let task_route = warp::post()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("task"))
+        .and(warp::body::content_length_limit(MAX_TASK_BYTES as u64))
+        .and(warp::body::json())
+        .then(move |req: CreateTaskRequest| {
+            let node = task_node.clone();
+            let task_id = uuid::Uuid::new_v4().to_string();
+            let span = info_span!("api", route = "task", task_id = %task_id);
+            async move {
+                info!(model = %req.model, "API received task");
+                debug!(prompt = %req.prompt);
+
+                let images = match decode_images(&req.images) {
+                    Ok(images) => images,
+                    Err(e) => return warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({ "error": e })),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    ),
+                };
+
+                let task = InferenceTask::new(task_id.clone(), req.model, req.prompt).with_options(req.options);
+                match node.run_local(task, images).await {
+                    Ok(output) => {
+                        let response = output.to_string();
+                        let preview: String = response.chars().take(50).collect();
+                        info!("Task completed: {}...", preview);
+                        warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({ "status": "completed", "task_id": task_id, "result": response })),
+                            warp::http::StatusCode::OK,
+                        )
+                    },
+                    Err(e) => {
+                        warn!("Task failed: {}", e);
+                        warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({ "error": e.to_string() })),
+                            error_status(&e),
+                        )
+                    }
+                }
+            }.instrument(span)
+        });
+
+    // POST /api/v1/chat
+    let chat_node = node.clone();
+    let chat_route = warp::post()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("chat"))
+        .and(warp::body::content_length_limit(MAX_TASK_BYTES as u64))
+        .and(warp::body::json())
+        .then(move |req: ChatTaskRequest| {
+            let node = chat_node.clone();
+            let task_id = uuid::Uuid::new_v4().to_string();
+            let span = info_span!("api", route = "chat", task_id = %task_id);
+            async move {
+                info!(model = %req.model, "API received chat task with {} messages", req.messages.len());
+
+                let request = ChatRequest {
+                    messages: req.messages,
+                    system: req.system,
+                    stop: req.stop,
+                };
+
+                let images = match decode_images(&req.images) {
+                    Ok(images) => images,
+                    Err(e) => return warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({ "error": e })),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    ),
+                };
+
+                let task = InferenceTask::chat(task_id.clone(), req.model, request).with_options(req.options);
+                match node.run_local(task, images).await {
+                    Ok(output) => {
+                        let message = ChatMessage::new(ChatRole::Assistant, output.to_string());
+                        let preview: String = message.content.chars().take(50).collect();
+                        info!("Chat completed: {}...", preview);
+                        warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({ "status": "completed", "task_id": task_id, "message": message })),
+                            warp::http::StatusCode::OK,
+                        )
+                    },
+                    Err(e) => {
+                        warn!("Chat failed: {}", e);
+                        warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({ "error": e.to_string() })),
+                            error_status(&e),
+                        )
+                    }
+                }
+            }.instrument(span)
+        });
+
+    // POST /api/v1/embeddings
+    let embeddings_node = node.clone();
+    let embeddings_route = warp::post()
+        .and(warp::path("api"))
+        .and(warp::path("v1"))
+        .and(warp::path("embeddings"))
+        .and(warp::body::content_length_limit(MAX_TASK_BYTES as u64))
+        .and(warp::body::json())
+        .then(move |req: EmbeddingsRequest| {
+            let node = embeddings_node.clone();
+            let task_id = uuid::Uuid::new_v4().to_string();
+            let span = info_span!("api", route = "embeddings", task_id = %task_id);
+            async move {
+                let input = req.input.into_vec();
+                info!(model = %req.model, "API received embeddings request with {} inputs", input.len());
+
+                let task = InferenceTask::embed(task_id, req.model.clone(), input).with_options(req.options);
+                let embeddings = node.run_local(task, Vec::new()).await.and_then(|output| match output {
+                    TaskOutput::Embeddings(embeddings) => Ok(embeddings),
+                    TaskOutput::Text(_) => Err(NetworkError::Runtime(RuntimeError::BadResponse("model returned text instead of embeddings".to_string()).into())),
+                });
+                match embeddings {
+                    Ok(embeddings) => warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({ "model": req.model, "embeddings": embeddings })),
+                        warp::http::StatusCode::OK,
+                    ),
+                    Err(e) => {
+                        warn!("Embeddings failed: {}", e);
+                        warp::reply::with_status(
+                            warp::reply::json(&serde_json::json!({ "error": e.to_string() })),
+                            error_status(&e),
+                        )
+                    }
+                }
+            }.instrument(span)
+        });
+
+    // Fix for "AsRef not general enough" - ensure filter is boxed
+    let task_route = task_route.boxed();
+    let chat_route = chat_route.boxed();
+    let embeddings_route = embeddings_route.boxed();
+    let control_routes = control_routes(node, runtime).boxed();
+
+    info!("Starting API Server on {}", addr);
+    warp::serve(task_route.or(chat_route).or(embeddings_route).or(control_routes))
+        .bind(addr)
+        .await
+        .graceful(shutdown)
+        .run()
+        .await;
+    info!("API Server stopped");
+}
+
+#[derive(Deserialize)]
+struct CreateTaskRequest {
+    model: String,
+    prompt: String,
+    #[serde(default)]
+    options: GenerationOptions,
+    #[serde(default)]
+    images: Vec<String>, // Base64-encoded, for vision models
+}
+
+#[derive(Deserialize)]
+struct ChatTaskRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    #[serde(default)]
+    system: Option<String>,
+    #[serde(default)]
+    stop: Vec<String>,
+    #[serde(default)]
+    options: GenerationOptions,
+    #[serde(default)]
+    images: Vec<String>, // Base64-encoded, attached to the last user message
+}
+
+fn decode_images(images: &[String]) -> Result<Vec<Vec<u8>>, String> {
+    use base64::Engine;
+    images.iter()
+        .map(|img| base64::engine::general_purpose::STANDARD.decode(img).map_err(|e| format!("Invalid image data: {}", e)))
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsRequest {
+    model: String,
+    input: EmbeddingsInput,
+    #[serde(default)]
+    options: GenerationOptions,
+}
+
+/// Accepts either a single string or a batch, like Ollama's `/api/embed`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmbeddingsInput {
+    Single(String),
+    Batch(Vec<String>),
+}
+
+impl EmbeddingsInput {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            EmbeddingsInput::Single(text) => vec![text],
+            EmbeddingsInput::Batch(texts) => texts,
+        }
+    }
+}
+
+// Control API
+
+fn control_routes(node: P2PNode, runtime: OllamaRuntime) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let with_node = warp::any().map(move || node.clone());
+    let api = warp::path("api").and(warp::path("v1")).and(local_only());
+
+    // GET /api/v1/peers
+    let peers = api.clone().and(warp::path("peers")).and(warp::path::end()).and(warp::get()).and(with_node.clone())
+        .then(|node: P2PNode| async move { json_result(node.peers().await) });
+
+    // GET /api/v1/metrics
+    let metrics = api.clone().and(warp::path("metrics")).and(warp::path::end()).and(warp::get()).and(with_node.clone())
+        .then(|node: P2PNode| async move { json_result(node.metrics().await) });
+
+    // GET /api/v1/ledger
+    let ledger = api.clone().and(warp::path("ledger")).and(warp::path::end()).and(warp::get()).and(with_node.clone())
+        .then(|node: P2PNode| async move {
+            let summary: Result<LedgerSummary, DynError> = async {
+                Ok(LedgerSummary { balance: node.metrics().await?.credits, entries: node.ledger().await? })
+            }.await;
+            json_result(summary)
+        });
+
+    // GET /api/v1/tasks
+    let tasks = api.clone().and(warp::path("tasks")).and(warp::path::end()).and(warp::get()).and(with_node.clone())
+        .then(|node: P2PNode| async move { json_result(node.tasks().await) });
+
+    // POST /api/v1/tasks: publish to the network and wait for a settled result
+    let submit = api.clone().and(warp::path("tasks")).and(warp::path::end()).and(warp::post()).and(with_node.clone())
+        .and(warp::body::content_length_limit(MAX_TASK_BYTES as u64)).and(warp::body::json())
+        .then(|node: P2PNode, req: SubmitTaskRequest| async move {
+            let mut task = InferenceTask::new(uuid::Uuid::new_v4().to_string(), req.model, req.prompt)
+                .with_options(req.options);
+            if let Some(max_price) = req.max_price {
+                task = task.with_max_price(max_price);
+            }
+            info!(task_id = %task.id, "API submitting network task");
+            json_result(node.submit_task(task).await)
+        });
+
+    // POST /api/v1/tasks/<id>/cancel: stop waiting for a published task, or drop a local request
+    let cancel = api.clone().and(warp::path("tasks")).and(warp::path::param::<String>()).and(warp::path("cancel")).and(warp::path::end())
+        .and(warp::post()).and(with_node.clone())
+        .then(|task_id: String, node: P2PNode| async move {
+            json_result(node.cancel_task(&task_id).await.map(|_| serde_json::json!({ "task_id": task_id, "cancelled": true })))
+        });
+
+    // GET /api/v1/models
+    let models = api.clone().and(warp::path("models")).and(warp::path::end()).and(warp::get())
+        .then(move || {
+            let runtime = runtime.clone();
+            async move { json_result(runtime.describe_models().await.map_err(DynError::from)) }
+        });
+
+    // POST /api/v1/provider announces, DELETE /api/v1/provider withdraws
+    let provide = api.clone().and(warp::path("provider")).and(warp::path::end()).and(warp::post()).and(with_node.clone())
+        .then(|node: P2PNode| async move { json_result(node.announce_provider().await.map(|_| serde_json::json!({ "providing": true }))) });
+    let unprovide = api.and(warp::path("provider")).and(warp::path::end()).and(warp::delete()).and(with_node)
+        .then(|node: P2PNode| async move { json_result(node.stop_providing().await.map(|_| serde_json::json!({ "providing": false }))) });
+
+    peers.or(metrics).or(ledger).or(tasks).or(submit).or(cancel).or(models).or(provide).or(unprovide)
+}
+
+/// Rejects requests that don't come from this machine.
+fn local_only() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::addr::remote()
+        .and_then(|addr: Option<SocketAddr>| async move {
+            if addr.is_some_and(|a| a.ip().is_loopback()) {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+fn json_result<T: Serialize, E: Into<DynError>>(result: Result<T, E>) -> warp::reply::WithStatus<warp::reply::Json> {
+    match result.map_err(Into::into) {
+        Ok(value) => warp::reply::with_status(warp::reply::json(&value), warp::http::StatusCode::OK),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "error": e.to_string() })),
+            error_status(e.as_ref()),
+        ),
+    }
+}
+
+/// Node and runtime failures map to the status a client can act on; anything else is a 500.
+fn error_status(e: &(dyn std::error::Error + 'static)) -> warp::http::StatusCode {
+    use warp::http::StatusCode;
+    if let Some(e) = e.downcast_ref::<NetworkError>() {
+        return match e {
+            NetworkError::Runtime(e) => error_status(e.as_ref()),
+            NetworkError::Stopped | NetworkError::Cancelled | NetworkError::BlobEvicted(_) => StatusCode::SERVICE_UNAVAILABLE,
+            NetworkError::NoProvider { .. } | NetworkError::NoOpenTask(_) => StatusCode::NOT_FOUND,
+            NetworkError::BlobMissing(_) | NetworkError::BlobTooLarge { .. } | NetworkError::InvalidPrice(_) | NetworkError::DeadlinePassed => StatusCode::BAD_REQUEST,
+            NetworkError::Ledger(LedgerError::InsufficientCredits { .. }) => StatusCode::PAYMENT_REQUIRED,
+            NetworkError::LedgerFetch { .. } | NetworkError::Publish(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+    }
+    match e.downcast_ref::<RuntimeError>() {
+        Some(RuntimeError::ModelNotFound(_)) => StatusCode::NOT_FOUND,
+        Some(RuntimeError::InvalidRequest(_)) => StatusCode::BAD_REQUEST,
+        Some(RuntimeError::Overloaded(_)) => StatusCode::SERVICE_UNAVAILABLE,
+        Some(RuntimeError::Timeout(_)) => StatusCode::GATEWAY_TIMEOUT,
+        Some(RuntimeError::Unavailable(_) | RuntimeError::Request(_) | RuntimeError::BadResponse(_) | RuntimeError::Api { .. }) => StatusCode::BAD_GATEWAY,
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[derive(Deserialize)]
+struct SubmitTaskRequest {
+    model: String,
+    prompt: String,
+    #[serde(default)]
+    options: GenerationOptions,
+    #[serde(default)]
+    max_price: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct LedgerSummary {
+    balance: f64,
+    entries: Vec<xnet_core::SignedEntry>,
+}
@@ -1,10 +1,25 @@
 use libp2p::{
-    gossipsub, kad, mdns, swarm::NetworkBehaviour,
+    autonat, dcutr, gossipsub, identify, kad, mdns, ping, relay,
+    swarm::{behaviour::toggle::Toggle, NetworkBehaviour},
 };
+use crate::blobs::BlobBehaviour;
+use crate::dht::PersistentStore;
+use crate::ledger::LedgerBehaviour;
 
 #[derive(NetworkBehaviour)]
 pub struct RhizomeBehaviour {
     pub gossipsub: gossipsub::Behaviour,
     pub mdns: mdns::tokio::Behaviour,
-    pub kad: kad::Behaviour<kad::store::MemoryStore>,
+    pub kad: kad::Behaviour<PersistentStore>,
+    pub blobs: BlobBehaviour,
+    pub ledger: LedgerBehaviour,
+    // Round trip times for the peer table
+    pub ping: ping::Behaviour,
+    // NAT traversal: identify shares observed addresses, AutoNAT checks if they are reachable,
+    // unreachable nodes listen through a relay, and DCUtR upgrades relayed connections to direct ones
+    pub identify: identify::Behaviour,
+    pub autonat: autonat::Behaviour,
+    pub relay: Toggle<relay::Behaviour>,
+    pub relay_client: relay::client::Behaviour,
+    pub dcutr: dcutr::Behaviour,
 }
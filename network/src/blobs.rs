@@ -0,0 +1,169 @@
+use crate::NetworkError;
+use libp2p::{request_response, PeerId, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use xnet_core::{BlobRef, InferenceTask};
+
+// Blobs (task images) are exchanged peer-to-peer over request/response instead of gossip,
+// so large attachments aren't flooded to every subscriber.
+pub const BLOB_PROTOCOL: StreamProtocol = StreamProtocol::new("/xnet/blob/1.0.0");
+
+// The cbor codec caps responses at 10 MiB, leave room for framing
+pub const MAX_BLOB_SIZE: usize = 8 * 1024 * 1024;
+
+// Bytes kept in memory across all blobs; the least recently used go first
+pub const BLOB_STORE_BUDGET: usize = 128 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobRequest {
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobResponse {
+    #[serde(with = "serde_bytes")]
+    pub data: Option<Vec<u8>>,
+}
+
+pub type BlobBehaviour = request_response::cbor::Behaviour<BlobRequest, BlobResponse>;
+
+pub fn new_behaviour() -> BlobBehaviour {
+    request_response::cbor::Behaviour::new(
+        [(BLOB_PROTOCOL, request_response::ProtocolSupport::Full)],
+        request_response::Config::default(),
+    )
+}
+
+/// Task images held in memory, within `BLOB_STORE_BUDGET` bytes.
+pub struct BlobStore {
+    blobs: HashMap<String, (Vec<u8>, u64)>, // Data and when it was last used
+    size: usize,
+    budget: usize,
+    clock: u64,
+    evicted: Vec<String>,
+}
+
+impl Default for BlobStore {
+    fn default() -> Self {
+        Self::with_budget(BLOB_STORE_BUDGET)
+    }
+}
+
+impl BlobStore {
+    pub fn with_budget(budget: usize) -> Self {
+        Self { blobs: HashMap::new(), size: 0, budget, clock: 0, evicted: Vec::new() }
+    }
+
+    pub fn insert(&mut self, data: Vec<u8>, mime_type: Option<String>) -> Result<BlobRef, NetworkError> {
+        if data.len() > MAX_BLOB_SIZE {
+            return Err(NetworkError::BlobTooLarge { size: data.len(), max: MAX_BLOB_SIZE });
+        }
+        let blob = BlobRef::for_bytes(&data, mime_type);
+        self.store(blob.hash.clone(), data);
+        Ok(blob)
+    }
+
+    /// Stores bytes fetched from a peer, only if they match the reference.
+    pub fn insert_verified(&mut self, hash: &str, data: Vec<u8>) -> bool {
+        let expected = BlobRef { hash: hash.to_string(), size: data.len() as u64, mime_type: None };
+        if !expected.matches(&data) {
+            return false;
+        }
+        self.store(hash.to_string(), data);
+        true
+    }
+
+    pub fn get(&mut self, hash: &str) -> Option<&Vec<u8>> {
+        self.clock += 1;
+        let (data, used) = self.blobs.get_mut(hash)?;
+        *used = self.clock;
+        Some(data)
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blobs.contains_key(hash)
+    }
+
+    /// Resolves all images of a task, in order, or fails with the first that isn't held,
+    /// e.g. because it was evicted while the task waited on another.
+    pub fn resolve(&mut self, images: &[BlobRef]) -> Result<Vec<Vec<u8>>, NetworkError> {
+        images.iter()
+            .map(|b| self.get(&b.hash).cloned().ok_or_else(|| NetworkError::BlobEvicted(b.hash.clone())))
+            .collect()
+    }
+
+    /// Hashes evicted since the last call, so the node can stop providing them.
+    pub fn take_evicted(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    fn store(&mut self, hash: String, data: Vec<u8>) {
+        self.clock += 1;
+        self.size += data.len();
+        if let Some((old, _)) = self.blobs.insert(hash.clone(), (data, self.clock)) {
+            self.size -= old.len();
+        }
+        while self.size > self.budget {
+            let Some(oldest) = self.blobs.iter()
+                .filter(|(h, _)| **h != hash)
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(h, _)| h.clone()) else { break };
+            if let Some((data, _)) = self.blobs.remove(&oldest) {
+                self.size -= data.len();
+            }
+            self.evicted.push(oldest);
+        }
+    }
+}
+
+/// A received task waiting for its images to be fetched.
+pub struct PendingTask {
+    pub task: InferenceTask,
+    pub author: PeerId,
+    pub missing: HashSet<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_over_budget() {
+        let mut store = BlobStore::with_budget(10);
+        let a = store.insert(vec![1; 4], None).unwrap();
+        let b = store.insert(vec![2; 4], None).unwrap();
+        assert!(store.get(&a.hash).is_some()); // `b` is now the oldest
+        let c = store.insert(vec![3; 4], None).unwrap();
+
+        assert!(store.contains(&a.hash) && store.contains(&c.hash));
+        assert!(!store.contains(&b.hash));
+        assert_eq!(store.take_evicted(), [b.hash]);
+        assert!(store.take_evicted().is_empty());
+    }
+
+    #[test]
+    fn keeps_the_newest_blob_and_counts_duplicates_once() {
+        let mut store = BlobStore::with_budget(4);
+        let a = store.insert(vec![1; 4], None).unwrap();
+        store.insert(vec![1; 4], None).unwrap();
+        assert!(store.contains(&a.hash) && store.take_evicted().is_empty());
+
+        let big = store.insert(vec![2; 6], None).unwrap();
+        assert!(store.contains(&big.hash));
+        assert!(!store.contains(&a.hash));
+        assert!(store.insert(vec![0; MAX_BLOB_SIZE + 1], None).is_err());
+    }
+
+    #[test]
+    fn resolve_fails_once_an_image_is_evicted() {
+        let mut store = BlobStore::with_budget(8);
+        let a = store.insert(vec![1; 4], None).unwrap();
+        assert_eq!(store.resolve(std::slice::from_ref(&a)).unwrap(), [vec![1; 4]]);
+
+        // A task waiting on `a` and `b` gets `b` only after `a` was pushed out
+        let b = store.insert(vec![2; 6], None).unwrap();
+        let images = [a.clone(), b];
+        assert!(matches!(store.resolve(&images), Err(NetworkError::BlobEvicted(hash)) if hash == a.hash));
+        assert!(store.resolve(&[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,545 @@
+use crate::accounting::NetworkParams;
+use libp2p::multiaddr::Protocol;
+use libp2p::pnet::PreSharedKey;
+use libp2p::Multiaddr;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use xnet_core::GenerationLimits;
+use xnet_runtime::{OllamaRuntime, RetryPolicy};
+
+// Node Configuration
+//
+// One `NodeConfig` drives the daemon and the desktop app. It is read from a TOML file (every
+// field optional, defaults below), then `XNET_*` environment variables override single fields,
+// and the result is validated before the node starts.
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+/// Config file name inside a node's data dir.
+pub const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    /// Addresses to listen on for peers
+    pub listen_addrs: Vec<Multiaddr>,
+    /// Bootnode multiaddrs, each ending in /p2p/<peer id>
+    pub bootnodes: Vec<Multiaddr>,
+    /// Ollama server that runs the inference
+    pub ollama_url: String,
+    /// Seconds to wait for a connection to Ollama
+    pub ollama_connect_timeout_secs: u64,
+    /// Seconds one generation request may take
+    pub ollama_timeout_secs: u64,
+    /// Extra attempts when Ollama is busy or can't be reached
+    pub ollama_retries: u32,
+    /// Address of the HTTP API
+    pub api_addr: SocketAddr,
+    pub gossipsub_heartbeat_secs: u64,
+    /// Connections without traffic are closed after this long
+    pub idle_connection_timeout_secs: u64,
+    /// Desktop: seconds without user input before the machine starts providing compute
+    pub idle_threshold_secs: u64,
+    pub topics: Topics,
+    /// Private network name; topics become `xnet/<network_id>/...` so clusters don't hear each other
+    pub network_id: Option<String>,
+    /// Pre-shared key (swarm.key format) for a private network. Only nodes holding the same key
+    /// can connect, and QUIC is turned off since it can't use one
+    pub psk_file: Option<PathBuf>,
+    pub nat: NatConfig,
+    pub limits: Limits,
+    pub log: LogConfig,
+    /// Parameters every node on the network should agree on
+    pub network: NetworkParams,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            listen_addrs: vec![
+                "/ip4/0.0.0.0/tcp/0".parse().expect("valid multiaddr"),
+                "/ip4/0.0.0.0/udp/0/quic-v1".parse().expect("valid multiaddr"),
+            ],
+            bootnodes: Vec::new(),
+            ollama_url: DEFAULT_OLLAMA_URL.to_string(),
+            ollama_connect_timeout_secs: 5,
+            ollama_timeout_secs: 300,
+            ollama_retries: 3,
+            api_addr: SocketAddr::from(([127, 0, 0, 1], 3030)),
+            gossipsub_heartbeat_secs: 10,
+            idle_connection_timeout_secs: 60,
+            idle_threshold_secs: 60,
+            topics: Topics::default(),
+            network_id: None,
+            psk_file: None,
+            nat: NatConfig::default(),
+            limits: Limits::default(),
+            log: LogConfig::default(),
+            network: NetworkParams::default(),
+        }
+    }
+}
+
+/// Gossipsub topic names. Nodes only hear each other on matching topics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Topics {
+    pub tasks: String,
+    pub pipeline: String,
+    pub verification: String,
+    pub fl: String,
+    pub capabilities: String,
+    pub results: String,
+    pub receipts: String,
+    pub cancellations: String,
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Self {
+            tasks: "xnet/tasks/v1".to_string(),
+            pipeline: "xnet/pipeline/v1".to_string(),
+            verification: "xnet/verification/v1".to_string(),
+            fl: "xnet/fl/v1".to_string(),
+            capabilities: "xnet/capabilities/v1".to_string(),
+            results: "xnet/results/v1".to_string(),
+            receipts: "xnet/receipts/v1".to_string(),
+            cancellations: "xnet/cancel/v1".to_string(),
+        }
+    }
+}
+
+impl Topics {
+    pub fn all(&self) -> [&str; 8] {
+        [&self.tasks, &self.pipeline, &self.verification, &self.fl, &self.capabilities, &self.results, &self.receipts, &self.cancellations]
+    }
+
+    /// Moves every topic into the network's namespace: `xnet/tasks/v1` becomes `xnet/<network_id>/tasks/v1`.
+    pub fn namespaced(&self, network_id: &str) -> Self {
+        let namespace = |topic: &str| match topic.strip_prefix("xnet/") {
+            Some(rest) => format!("xnet/{}/{}", network_id, rest),
+            None => format!("{}/{}", network_id, topic),
+        };
+        Self {
+            tasks: namespace(&self.tasks),
+            pipeline: namespace(&self.pipeline),
+            verification: namespace(&self.verification),
+            fl: namespace(&self.fl),
+            capabilities: namespace(&self.capabilities),
+            results: namespace(&self.results),
+            receipts: namespace(&self.receipts),
+            cancellations: namespace(&self.cancellations),
+        }
+    }
+}
+
+/// NAT traversal. Nodes AutoNAT finds unreachable listen through relays; DCUtR then tries to
+/// replace relayed connections with direct ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NatConfig {
+    /// Relay connections for peers behind NAT
+    pub relay_server: bool,
+    /// Relay slots to hold while this node is not publicly reachable
+    pub max_relay_reservations: usize,
+    /// Only treat public IPs as reachable; turn off for LAN and test networks
+    pub only_global_ips: bool,
+}
+
+impl Default for NatConfig {
+    fn default() -> Self {
+        Self { relay_server: true, max_relay_reservations: 2, only_global_ips: true }
+    }
+}
+
+/// Spam protection: gossip rate limits and how much inference work a node takes on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Messages per second one peer may publish on a topic, and the burst allowed above that
+    pub peer_messages_per_sec: f64,
+    pub peer_burst: u32,
+    /// Messages per second accepted on a topic from all peers together
+    pub topic_messages_per_sec: f64,
+    pub topic_burst: u32,
+    /// Tasks run on the local model at once; local API requests go ahead of network tasks
+    pub max_concurrent_tasks: usize,
+    /// Tasks waiting for a free slot; further tasks are turned away
+    pub max_queued_tasks: usize,
+    /// Longest a task may wait and run here, unless its own deadline is sooner
+    pub task_timeout_secs: u64,
+    /// Caps on what network tasks may ask of the local model; local API requests aren't capped
+    pub max_task_tokens: u32,
+    pub max_task_context_window: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            peer_messages_per_sec: 2.0,
+            peer_burst: 10,
+            topic_messages_per_sec: 20.0,
+            topic_burst: 100,
+            max_concurrent_tasks: 2,
+            max_queued_tasks: 16,
+            task_timeout_secs: 300,
+            max_task_tokens: 1024,
+            max_task_context_window: 4096,
+        }
+    }
+}
+
+impl Limits {
+    pub fn task_timeout(&self) -> Duration {
+        Duration::from_secs(self.task_timeout_secs)
+    }
+
+    /// What network tasks are clamped to before they reach the runtime.
+    pub fn generation_limits(&self) -> GenerationLimits {
+        GenerationLimits { max_tokens: Some(self.max_task_tokens), max_context_window: Some(self.max_task_context_window) }
+    }
+}
+
+/// Logging, for the daemon's output and the desktop app's log files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Level filter, `info` or per module like `info,xnet_network=debug,libp2p_gossipsub=warn`
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { level: "info".to_string(), format: LogFormat::Text }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, with the span fields (task, session, peer) included
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {}, use text or json", s)),
+        }
+    }
+}
+
+// Stamps above this take minutes to mint
+const MAX_TASK_STAMP_BITS: u32 = 24;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+    Env { var: &'static str, message: String },
+    Invalid { field: &'static str, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Cannot access config {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Invalid config {}: {}", path.display(), e),
+            ConfigError::Env { var, message } => write!(f, "Invalid {}: {}", var, message),
+            ConfigError::Invalid { field, message } => write!(f, "Invalid config value `{}`: {}", field, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl NodeConfig {
+    /// Reads `path` if it exists (defaults otherwise), applies environment overrides and validates.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config = match path {
+            Some(path) if path.exists() => Self::from_file(path)?,
+            _ => Self::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))
+    }
+
+    /// Validates and writes the config as TOML.
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        self.validate()?;
+        let text = toml::to_string_pretty(self).map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))?;
+        std::fs::write(path, text).map_err(|e| ConfigError::Io(path.to_path_buf(), e))
+    }
+
+    /// Overrides fields from `XNET_LISTEN`, `XNET_BOOTNODES` (comma separated), `XNET_OLLAMA_URL`,
+    /// `XNET_OLLAMA_TIMEOUT_SECS`, `XNET_API_ADDR`, `XNET_HEARTBEAT_SECS`, `XNET_IDLE_CONNECTION_TIMEOUT_SECS`, `XNET_IDLE_THRESHOLD_SECS`,
+    /// `XNET_NETWORK_ID`, `XNET_PSK_FILE`, `XNET_MAX_TASK_TOKENS`, `XNET_MAX_TASK_CONTEXT_WINDOW`, `XNET_LOG` (level filter)
+    /// and `XNET_LOG_FORMAT`.
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        self.apply_env_from(|var| std::env::var(var).ok())
+    }
+
+    /// `apply_env` with variables looked up through `env` instead of the process environment.
+    pub fn apply_env_from(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        if let Some(addrs) = env_list(&env, "XNET_LISTEN")? {
+            self.listen_addrs = addrs;
+        }
+        if let Some(addrs) = env_list(&env, "XNET_BOOTNODES")? {
+            self.bootnodes = addrs;
+        }
+        if let Some(url) = env("XNET_OLLAMA_URL") {
+            self.ollama_url = url;
+        }
+        if let Some(secs) = env_value(&env, "XNET_OLLAMA_TIMEOUT_SECS")? {
+            self.ollama_timeout_secs = secs;
+        }
+        if let Some(addr) = env_value(&env, "XNET_API_ADDR")? {
+            self.api_addr = addr;
+        }
+        if let Some(secs) = env_value(&env, "XNET_HEARTBEAT_SECS")? {
+            self.gossipsub_heartbeat_secs = secs;
+        }
+        if let Some(secs) = env_value(&env, "XNET_IDLE_CONNECTION_TIMEOUT_SECS")? {
+            self.idle_connection_timeout_secs = secs;
+        }
+        if let Some(secs) = env_value(&env, "XNET_IDLE_THRESHOLD_SECS")? {
+            self.idle_threshold_secs = secs;
+        }
+        if let Some(network_id) = env("XNET_NETWORK_ID") {
+            self.network_id = Some(network_id);
+        }
+        if let Some(path) = env("XNET_PSK_FILE") {
+            self.psk_file = Some(PathBuf::from(path));
+        }
+        if let Some(tokens) = env_value(&env, "XNET_MAX_TASK_TOKENS")? {
+            self.limits.max_task_tokens = tokens;
+        }
+        if let Some(tokens) = env_value(&env, "XNET_MAX_TASK_CONTEXT_WINDOW")? {
+            self.limits.max_task_context_window = tokens;
+        }
+        if let Some(level) = env("XNET_LOG") {
+            self.log.level = level;
+        }
+        if let Some(format) = env_value(&env, "XNET_LOG_FORMAT")? {
+            self.log.format = format;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field, message: &str| Err(ConfigError::Invalid { field, message: message.to_string() });
+
+        if self.listen_addrs.is_empty() {
+            return invalid("listen_addrs", "at least one address is required");
+        }
+        if self.psk_file.is_some() && self.listen_addrs.iter().all(is_quic) {
+            return invalid("listen_addrs", "a private network (psk_file) needs a TCP address, QUIC can't use a pre-shared key");
+        }
+        if let Some(addr) = self.bootnodes.iter().find(|a| !matches!(a.iter().last(), Some(Protocol::P2p(_)))) {
+            return invalid("bootnodes", &format!("{} does not end in /p2p/<peer id>", addr));
+        }
+        if !(self.ollama_url.starts_with("http://") || self.ollama_url.starts_with("https://")) {
+            return invalid("ollama_url", "must be an http:// or https:// URL");
+        }
+        if self.ollama_connect_timeout_secs == 0 || self.ollama_timeout_secs == 0 {
+            return invalid("ollama_timeout_secs", "Ollama timeouts must be at least 1 second");
+        }
+        if self.gossipsub_heartbeat_secs == 0 {
+            return invalid("gossipsub_heartbeat_secs", "must be at least 1");
+        }
+        if self.idle_connection_timeout_secs == 0 {
+            return invalid("idle_connection_timeout_secs", "must be at least 1");
+        }
+
+        if let Some(id) = &self.network_id
+            && (id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        {
+            return invalid("network_id", "use letters, digits, '-' and '_' only");
+        }
+
+        if self.log.level.trim().is_empty() {
+            return invalid("log.level", "use a level such as info or debug");
+        }
+
+        let topics = self.topics.all();
+        if topics.iter().any(|t| t.trim().is_empty()) {
+            return invalid("topics", "topic names cannot be empty");
+        }
+        if topics.iter().enumerate().any(|(i, t)| topics[..i].contains(t)) {
+            return invalid("topics", "topic names must be distinct");
+        }
+
+        let limits = &self.limits;
+        if [limits.peer_messages_per_sec, limits.topic_messages_per_sec].iter().any(|r| !r.is_finite() || *r <= 0.0) {
+            return invalid("limits", "message rates must be positive");
+        }
+        if limits.peer_burst == 0 || limits.topic_burst == 0 || limits.max_concurrent_tasks == 0 || limits.task_timeout_secs == 0 {
+            return invalid("limits", "bursts, max_concurrent_tasks and task_timeout_secs must be at least 1");
+        }
+        if limits.max_task_tokens == 0 || limits.max_task_context_window == 0 {
+            return invalid("limits", "max_task_tokens and max_task_context_window must be at least 1");
+        }
+        if self.network.task_stamp_bits > MAX_TASK_STAMP_BITS {
+            return invalid("network.task_stamp_bits", &format!("at most {} bits", MAX_TASK_STAMP_BITS));
+        }
+
+        let rewards = &self.network.rewards;
+        if [rewards.uptime_per_minute, rewards.per_task, rewards.per_relay, rewards.per_verification].iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return invalid("network.rewards", "weights must be finite and non-negative");
+        }
+        Ok(())
+    }
+
+    /// Topic names with the network namespace applied.
+    pub fn network_topics(&self) -> Topics {
+        match &self.network_id {
+            Some(id) => self.topics.namespaced(id),
+            None => self.topics.clone(),
+        }
+    }
+
+    /// Reads the key in `psk_file`, if one is configured.
+    pub fn pre_shared_key(&self) -> Result<Option<PreSharedKey>, ConfigError> {
+        let Some(path) = &self.psk_file else {
+            return Ok(None);
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+        text.trim().parse().map(Some).map_err(|e| ConfigError::Parse(path.clone(), format!("not a pre-shared key: {}", e)))
+    }
+
+    /// A runtime for `ollama_url` with the configured timeouts and retries.
+    pub fn ollama_runtime(&self) -> OllamaRuntime {
+        OllamaRuntime::new(&self.ollama_url)
+            .with_timeouts(Duration::from_secs(self.ollama_connect_timeout_secs), Duration::from_secs(self.ollama_timeout_secs))
+            .with_retry(RetryPolicy { max_retries: self.ollama_retries, ..RetryPolicy::default() })
+    }
+
+    pub fn gossipsub_heartbeat(&self) -> Duration {
+        Duration::from_secs(self.gossipsub_heartbeat_secs)
+    }
+
+    pub fn idle_connection_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_connection_timeout_secs)
+    }
+}
+
+/// A fresh key for a private network, in swarm.key format.
+pub fn generate_psk() -> PreSharedKey {
+    PreSharedKey::new(rand::random())
+}
+
+pub(crate) fn is_quic(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| matches!(p, Protocol::QuicV1 | Protocol::Quic))
+}
+
+fn env_value<T: std::str::FromStr>(env: impl Fn(&str) -> Option<String>, var: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    match env(var) {
+        Some(value) => value.trim().parse().map(Some).map_err(|e: T::Err| ConfigError::Env { var, message: e.to_string() }),
+        None => Ok(None),
+    }
+}
+
+fn env_list(env: impl Fn(&str) -> Option<String>, var: &'static str) -> Result<Option<Vec<Multiaddr>>, ConfigError> {
+    match env(var) {
+        Some(value) => value.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse().map_err(|e: libp2p::multiaddr::Error| ConfigError::Env { var, message: format!("{}: {}", s, e) }))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The field `validate` complains about after `change` is applied to the defaults.
+    fn invalid_field(change: impl FnOnce(&mut NodeConfig)) -> &'static str {
+        let mut config = NodeConfig::default();
+        change(&mut config);
+        match config.validate() {
+            Err(ConfigError::Invalid { field, .. }) => field,
+            other => panic!("expected an invalid field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(NodeConfig::default().validate().is_ok());
+        let parsed: NodeConfig = toml::from_str("").unwrap();
+        assert_eq!(parsed, NodeConfig::default());
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(toml::from_str::<NodeConfig>("olama_url = \"http://x\"").is_err());
+        assert!(toml::from_str::<NodeConfig>("[limits]\nmax_tasks = 3").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert_eq!(invalid_field(|c| c.bootnodes = vec!["/ip4/1.2.3.4/tcp/4001".parse().unwrap()]), "bootnodes");
+        assert_eq!(invalid_field(|c| c.ollama_url = "localhost:11434".to_string()), "ollama_url");
+        assert_eq!(invalid_field(|c| c.ollama_timeout_secs = 0), "ollama_timeout_secs");
+        assert_eq!(invalid_field(|c| {
+            c.psk_file = Some(PathBuf::from("swarm.key"));
+            c.listen_addrs = vec!["/ip4/0.0.0.0/udp/0/quic-v1".parse().unwrap()];
+        }), "listen_addrs");
+        assert_eq!(invalid_field(|c| c.network_id = Some("acme/prod".to_string())), "network_id");
+        assert_eq!(invalid_field(|c| c.log.level = " ".to_string()), "log.level");
+        assert_eq!(invalid_field(|c| c.topics.results = c.topics.tasks.clone()), "topics");
+        assert_eq!(invalid_field(|c| c.limits.peer_messages_per_sec = f64::NAN), "limits");
+        assert_eq!(invalid_field(|c| c.limits.max_concurrent_tasks = 0), "limits");
+        assert_eq!(invalid_field(|c| c.limits.max_task_tokens = 0), "limits");
+        assert_eq!(invalid_field(|c| c.network.task_stamp_bits = MAX_TASK_STAMP_BITS + 1), "network.task_stamp_bits");
+        assert_eq!(invalid_field(|c| c.network.rewards.per_task = -1.0), "network.rewards");
+    }
+
+    #[test]
+    fn namespaces_topics() {
+        assert_eq!(NodeConfig::default().network_topics().tasks, "xnet/tasks/v1");
+        let config = NodeConfig { network_id: Some("acme".to_string()), ..NodeConfig::default() };
+        assert_eq!(config.network_topics().tasks, "xnet/acme/tasks/v1");
+    }
+
+    #[test]
+    fn environment_overrides_fields() {
+        let mut vars = std::collections::HashMap::from([
+            ("XNET_BOOTNODES", "/ip4/1.2.3.4/tcp/4001/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN, "),
+            ("XNET_OLLAMA_TIMEOUT_SECS", " 42 "),
+            ("XNET_LOG_FORMAT", "json"),
+            ("XNET_MAX_TASK_TOKENS", "256"),
+        ]);
+        let mut config = NodeConfig::default();
+        config.apply_env_from(|var| vars.get(var).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.bootnodes.len(), 1);
+        assert_eq!(config.limits.generation_limits().max_tokens, Some(256));
+        assert_eq!(config.ollama_timeout_secs, 42);
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert!(config.validate().is_ok());
+
+        vars.insert("XNET_OLLAMA_TIMEOUT_SECS", "soon");
+        let result = config.apply_env_from(|var| vars.get(var).map(|v| v.to_string()));
+        assert!(matches!(result, Err(ConfigError::Env { var: "XNET_OLLAMA_TIMEOUT_SECS", .. })));
+    }
+}
@@ -0,0 +1,287 @@
+use crate::store::{DhtProvider, DhtRecord, Store};
+use libp2p::kad::store::{MemoryStore, RecordStore, Result};
+use libp2p::kad::{ProviderRecord, Record, RecordKey};
+use libp2p::{Multiaddr, PeerId};
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+// Kademlia timing. Records expire after their TTL unless the publisher (or, for provider
+// announcements, the provider) republishes them; kad's background jobs do the republishing
+// from whatever this store holds, including records restored from disk.
+pub const RECORD_TTL: Duration = Duration::from_secs(48 * 60 * 60);
+pub const RECORD_PUBLICATION_INTERVAL: Duration = Duration::from_secs(22 * 60 * 60);
+pub const PROVIDER_RECORD_TTL: Duration = Duration::from_secs(48 * 60 * 60);
+pub const PROVIDER_PUBLICATION_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// A `RecordStore` that serves from memory and writes through to the node store, so records
+/// and provider announcements survive a restart. Expired entries are dropped on load and by
+/// `remove_expired`. Our own provider announcements are never persisted: after a restart the
+/// node re-announces only what it actually holds.
+pub struct PersistentStore {
+    local_id: PeerId,
+    memory: MemoryStore,
+    store: Store,
+}
+
+impl PersistentStore {
+    pub fn open(local_id: PeerId, store: Store) -> Self {
+        let mut memory = MemoryStore::new(local_id);
+        let now = Instant::now();
+
+        match store.dht_records() {
+            Ok(records) => {
+                for (key, stored) in records {
+                    let key = RecordKey::from(key);
+                    match from_unix(stored.expires_at, now) {
+                        Some(expires) => {
+                            let record = Record {
+                                key,
+                                value: stored.value,
+                                publisher: stored.publisher.and_then(|p| p.parse().ok()),
+                                expires,
+                            };
+                            if let Err(e) = memory.put(record) {
+                                tracing::warn!("Failed to restore DHT record: {}", e);
+                            }
+                        }
+                        None => {
+                            let _ = store.remove_dht_record(key.as_ref());
+                        }
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Failed to load DHT records: {}", e),
+        }
+
+        match store.dht_providers() {
+            Ok(providers) => {
+                for (key, stored) in providers {
+                    let key = RecordKey::from(key);
+                    let (Some(expires), Ok(provider)) = (from_unix(stored.expires_at, now), stored.provider.parse::<PeerId>()) else {
+                        let _ = store.remove_dht_provider(key.as_ref(), &stored.provider);
+                        continue;
+                    };
+                    if provider == local_id {
+                        let _ = store.remove_dht_provider(key.as_ref(), &stored.provider);
+                        continue;
+                    }
+                    let record = ProviderRecord {
+                        key,
+                        provider,
+                        expires,
+                        addresses: stored.addresses.iter().filter_map(|a| a.parse::<Multiaddr>().ok()).collect(),
+                    };
+                    if let Err(e) = memory.add_provider(record) {
+                        tracing::warn!("Failed to restore provider record: {}", e);
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Failed to load DHT provider records: {}", e),
+        }
+
+        Self { local_id, memory, store }
+    }
+
+    /// Drops expired records and provider announcements from memory and disk.
+    pub fn remove_expired(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<RecordKey> = self.memory.records()
+            .filter(|r| r.is_expired(now))
+            .map(|r| r.key.clone())
+            .collect();
+        for key in expired {
+            self.remove(&key);
+        }
+
+        // Provider records of other peers are only enumerable from disk
+        let providers = match self.store.dht_providers() {
+            Ok(providers) => providers,
+            Err(e) => {
+                tracing::warn!("Failed to load DHT provider records: {}", e);
+                return;
+            }
+        };
+        let now_unix = xnet_core::unix_timestamp();
+        for (key, stored) in providers {
+            if stored.expires_at.is_some_and(|t| t <= now_unix) {
+                match stored.provider.parse::<PeerId>() {
+                    Ok(provider) => self.remove_provider(&RecordKey::from(key), &provider),
+                    Err(_) => {
+                        let _ = self.store.remove_dht_provider(&key, &stored.provider);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl RecordStore for PersistentStore {
+    type RecordsIter<'a> = <MemoryStore as RecordStore>::RecordsIter<'a>;
+    type ProvidedIter<'a> = <MemoryStore as RecordStore>::ProvidedIter<'a>;
+
+    fn get(&self, k: &RecordKey) -> Option<Cow<'_, Record>> {
+        self.memory.get(k)
+    }
+
+    fn put(&mut self, r: Record) -> Result<()> {
+        let stored = DhtRecord {
+            value: r.value.clone(),
+            publisher: r.publisher.map(|p| p.to_string()),
+            expires_at: to_unix(r.expires),
+        };
+        let key = r.key.clone();
+        self.memory.put(r)?;
+        if let Err(e) = self.store.put_dht_record(key.as_ref(), &stored) {
+            tracing::warn!("Failed to persist DHT record: {}", e);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, k: &RecordKey) {
+        self.memory.remove(k);
+        if let Err(e) = self.store.remove_dht_record(k.as_ref()) {
+            tracing::warn!("Failed to remove DHT record: {}", e);
+        }
+    }
+
+    fn records(&self) -> Self::RecordsIter<'_> {
+        self.memory.records()
+    }
+
+    fn add_provider(&mut self, record: ProviderRecord) -> Result<()> {
+        let stored = DhtProvider {
+            provider: record.provider.to_string(),
+            addresses: record.addresses.iter().map(|a| a.to_string()).collect(),
+            expires_at: to_unix(record.expires),
+        };
+        let key = record.key.clone();
+        let local = record.provider == self.local_id;
+        self.memory.add_provider(record)?;
+        if local {
+            return Ok(());
+        }
+        if let Err(e) = self.store.put_dht_provider(key.as_ref(), &stored) {
+            tracing::warn!("Failed to persist provider record: {}", e);
+        }
+        Ok(())
+    }
+
+    fn providers(&self, key: &RecordKey) -> Vec<ProviderRecord> {
+        self.memory.providers(key)
+    }
+
+    fn provided(&self) -> Self::ProvidedIter<'_> {
+        self.memory.provided()
+    }
+
+    fn remove_provider(&mut self, k: &RecordKey, p: &PeerId) {
+        self.memory.remove_provider(k, p);
+        if let Err(e) = self.store.remove_dht_provider(k.as_ref(), &p.to_string()) {
+            tracing::warn!("Failed to remove provider record: {}", e);
+        }
+    }
+}
+
+// Expiry is a monotonic `Instant` in memory and Unix seconds on disk
+
+fn to_unix(expires: Option<Instant>) -> Option<u64> {
+    expires.map(|t| xnet_core::unix_timestamp() + t.saturating_duration_since(Instant::now()).as_secs())
+}
+
+/// `None` if the record has already expired, otherwise its expiry as an `Instant`.
+fn from_unix(expires_at: Option<u64>, now: Instant) -> Option<Option<Instant>> {
+    match expires_at {
+        None => Some(None),
+        Some(t) => {
+            let remaining = t.checked_sub(xnet_core::unix_timestamp()).filter(|s| *s > 0)?;
+            Some(Some(now + Duration::from_secs(remaining)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key: &[u8], expires: Option<Instant>) -> Record {
+        Record { key: RecordKey::new(&key), value: vec![1, 2, 3], publisher: None, expires }
+    }
+
+    fn provider(key: &[u8], provider: PeerId, expires: Option<Instant>) -> ProviderRecord {
+        ProviderRecord { key: RecordKey::new(&key), provider, expires, addresses: Vec::new() }
+    }
+
+    #[test]
+    fn restores_records_and_remote_providers() {
+        let store = Store::in_memory().unwrap();
+        let (local, remote) = (PeerId::random(), PeerId::random());
+        let expires = Some(Instant::now() + Duration::from_secs(3600));
+        {
+            let mut dht = PersistentStore::open(local, store.clone());
+            dht.put(record(b"a", expires)).unwrap();
+            dht.put(record(b"b", None)).unwrap();
+            dht.add_provider(provider(b"blob", remote, expires)).unwrap();
+            dht.add_provider(provider(b"blob", local, expires)).unwrap();
+            assert_eq!(dht.provided().count(), 1);
+        }
+        store.flush().unwrap();
+
+        let dht = PersistentStore::open(local, store.clone());
+        assert_eq!(dht.records().count(), 2);
+        assert_eq!(dht.get(&RecordKey::new(b"a")).unwrap().value, [1, 2, 3]);
+        assert!(dht.get(&RecordKey::new(b"a")).unwrap().expires.is_some());
+        assert!(dht.get(&RecordKey::new(b"b")).unwrap().expires.is_none());
+        let providers = dht.providers(&RecordKey::new(b"blob"));
+        assert_eq!(providers.len(), 1);
+        assert_eq!(providers[0].provider, remote);
+        assert_eq!(dht.provided().count(), 0);
+    }
+
+    #[test]
+    fn drops_local_providers_left_on_disk() {
+        let store = Store::in_memory().unwrap();
+        let local = PeerId::random();
+        let stored = DhtProvider { provider: local.to_string(), addresses: Vec::new(), expires_at: None };
+        store.put_dht_provider(b"blob", &stored).unwrap();
+
+        let dht = PersistentStore::open(local, store.clone());
+        assert!(dht.providers(&RecordKey::new(b"blob")).is_empty());
+        assert!(store.dht_providers().unwrap().is_empty());
+    }
+
+    #[test]
+    fn drops_expired_entries_on_load() {
+        let store = Store::in_memory().unwrap();
+        let past = Some(xnet_core::unix_timestamp() - 10);
+        store.put_dht_record(b"old", &DhtRecord { value: vec![1], publisher: None, expires_at: past }).unwrap();
+        store.put_dht_record(b"new", &DhtRecord { value: vec![2], publisher: None, expires_at: None }).unwrap();
+        let stale = DhtProvider { provider: PeerId::random().to_string(), addresses: Vec::new(), expires_at: past };
+        store.put_dht_provider(b"blob", &stale).unwrap();
+
+        let dht = PersistentStore::open(PeerId::random(), store.clone());
+        assert!(dht.get(&RecordKey::new(b"old")).is_none());
+        assert!(dht.get(&RecordKey::new(b"new")).is_some());
+        assert!(dht.providers(&RecordKey::new(b"blob")).is_empty());
+        assert_eq!(store.dht_records().unwrap().len(), 1);
+        assert!(store.dht_providers().unwrap().is_empty());
+    }
+
+    #[test]
+    fn remove_expired_clears_memory_and_disk() {
+        let store = Store::in_memory().unwrap();
+        let remote = PeerId::random();
+        let mut dht = PersistentStore::open(PeerId::random(), store.clone());
+        dht.put(record(b"old", Some(Instant::now()))).unwrap();
+        dht.put(record(b"new", None)).unwrap();
+        let stale = DhtProvider { provider: remote.to_string(), addresses: Vec::new(), expires_at: Some(xnet_core::unix_timestamp() - 10) };
+        store.put_dht_provider(b"blob", &stale).unwrap();
+        dht.add_provider(provider(b"fresh", remote, None)).unwrap();
+
+        dht.remove_expired();
+        assert!(dht.get(&RecordKey::new(b"old")).is_none());
+        assert!(dht.get(&RecordKey::new(b"new")).is_some());
+        let on_disk: Vec<Vec<u8>> = store.dht_providers().unwrap().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(on_disk, [RecordKey::new(b"fresh").to_vec()]);
+        assert_eq!(store.dht_records().unwrap().len(), 1);
+    }
+}
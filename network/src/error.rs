@@ -0,0 +1,118 @@
+use crate::config::ConfigError;
+use crate::store::StoreError;
+use libp2p::{Multiaddr, PeerId};
+use std::fmt;
+use std::time::Duration;
+use xnet_core::{DynError, LedgerError, ModelCapability};
+
+/// Why the node couldn't start, or why a call to it failed. `P2PNode` methods return these
+/// directly; the `NetworkInterface` methods box them in a `DynError`, where
+/// `downcast_ref::<NetworkError>()` tells them apart.
+#[derive(Debug)]
+pub enum NetworkError {
+    Config(ConfigError),
+    /// The keypair bytes can't be decoded
+    Identity(String),
+    /// The data dir or a legacy file in it
+    Io(std::io::Error),
+    Store(StoreError),
+    /// The node's own ledger doesn't verify, or can't take an entry
+    Ledger(LedgerError),
+    /// The transports or behaviours couldn't be set up
+    Transport(String),
+    Listen { addr: Multiaddr, message: String },
+    Subscribe { topic: String, message: String },
+    /// No listen address came up in time
+    NotListening(Duration),
+    /// The node's event loop has stopped
+    Stopped,
+    /// No peer has advertised the model with every capability the task needs
+    NoProvider { model: String, required: Vec<ModelCapability> },
+    /// A task references an image that wasn't added with `put_blob`
+    BlobMissing(String),
+    /// A task's image was evicted from the blob store before the task could start
+    BlobEvicted(String),
+    BlobTooLarge { size: usize, max: usize },
+    /// A task bid that is negative or not a number
+    InvalidPrice(f64),
+    DeadlinePassed,
+    /// Nothing to cancel under this task id
+    NoOpenTask(String),
+    LedgerFetch { peer: PeerId, message: String },
+    /// Gossipsub wouldn't take the message, e.g. no peers on the topic
+    Publish(String),
+    /// The task was cancelled, or expired, before a result came
+    Cancelled,
+    /// Local inference failed; usually holds a `RuntimeError`
+    Runtime(DynError),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Config(e) => write!(f, "{}", e),
+            NetworkError::Identity(e) => write!(f, "Invalid identity key: {}", e),
+            NetworkError::Io(e) => write!(f, "Data dir I/O failed: {}", e),
+            NetworkError::Store(e) => write!(f, "Node store failed: {}", e),
+            NetworkError::Ledger(e) => write!(f, "Credit ledger failed: {}", e),
+            NetworkError::Transport(e) => write!(f, "Failed to set up the transport: {}", e),
+            NetworkError::Listen { addr, message } => write!(f, "Cannot listen on {}: {}", addr, message),
+            NetworkError::Subscribe { topic, message } => write!(f, "Cannot subscribe to {}: {}", topic, message),
+            NetworkError::NotListening(after) => write!(f, "Not listening on any address after {}s", after.as_secs()),
+            NetworkError::Stopped => write!(f, "The node has stopped"),
+            NetworkError::NoProvider { model, required } => write!(f, "No peer advertises model {} with {:?}", model, required),
+            NetworkError::BlobMissing(hash) => write!(f, "Image {} must be added with put_blob before publishing", hash),
+            NetworkError::BlobEvicted(hash) => write!(f, "Image {} was evicted before the task could start", hash),
+            NetworkError::BlobTooLarge { size, max } => write!(f, "Blob too large: {} bytes (max {})", size, max),
+            NetworkError::InvalidPrice(price) => write!(f, "Invalid max price {}", price),
+            NetworkError::DeadlinePassed => write!(f, "Task deadline has already passed"),
+            NetworkError::NoOpenTask(task_id) => write!(f, "No open task {}", task_id),
+            NetworkError::LedgerFetch { peer, message } => write!(f, "Ledger fetch from {} failed: {}", peer, message),
+            NetworkError::Publish(e) => write!(f, "Publish failed: {}", e),
+            NetworkError::Cancelled => write!(f, "Task was cancelled"),
+            NetworkError::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NetworkError::Config(e) => Some(e),
+            NetworkError::Io(e) => Some(e),
+            NetworkError::Store(e) => Some(e),
+            NetworkError::Ledger(e) => Some(e),
+            NetworkError::Runtime(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for NetworkError {
+    fn from(e: ConfigError) -> Self {
+        NetworkError::Config(e)
+    }
+}
+
+impl From<std::io::Error> for NetworkError {
+    fn from(e: std::io::Error) -> Self {
+        NetworkError::Io(e)
+    }
+}
+
+impl From<StoreError> for NetworkError {
+    fn from(e: StoreError) -> Self {
+        NetworkError::Store(e)
+    }
+}
+
+impl From<LedgerError> for NetworkError {
+    fn from(e: LedgerError) -> Self {
+        NetworkError::Ledger(e)
+    }
+}
+
+/// For `map_err` on the command channel: the send failed or the reply never came.
+pub(crate) fn stopped<E>(_: E) -> NetworkError {
+    NetworkError::Stopped
+}
@@ -0,0 +1,172 @@
+use libp2p::{identity, request_response, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use crate::error::NetworkError;
+use crate::store::{Store, STORE_FILE};
+use std::path::Path;
+use xnet_core::{EntryKind, KeyHandover, Ledger, LedgerError, LedgerEvent, SignedEntry};
+
+// Peers fetch each other's ledgers over request/response and verify the chain
+// against the owner's peer id before trusting the balance.
+pub const LEDGER_PROTOCOL: StreamProtocol = StreamProtocol::new("/xnet/ledger/1.0.0");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerRequest {
+    pub from_seq: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerResponse {
+    pub entries: Vec<SignedEntry>,
+}
+
+pub type LedgerBehaviour = request_response::cbor::Behaviour<LedgerRequest, LedgerResponse>;
+
+pub fn new_behaviour() -> LedgerBehaviour {
+    request_response::cbor::Behaviour::new(
+        [(LEDGER_PROTOCOL, request_response::ProtocolSupport::Full)],
+        request_response::Config::default(),
+    )
+}
+
+/// The node's own ledger, persisted entry by entry in the node store.
+pub struct LocalLedger {
+    pub ledger: Ledger,
+    store: Store,
+}
+
+impl LocalLedger {
+    /// Loads and verifies the ledger from the store. Legacy files in the data dir are imported
+    /// once: a `ledger.json` chain as-is, or a `wallet.json` balance as an `Opening` entry.
+    pub fn open(store: Store, data_dir: Option<&Path>, keys: &identity::Keypair) -> Result<Self, NetworkError> {
+        let entries = store.ledger_entries()?;
+        let mut local = Self { ledger: Ledger::from_entries(entries, &keys.public())?, store };
+        let Some(dir) = data_dir else {
+            return Ok(local);
+        };
+
+        let ledger_path = dir.join("ledger.json");
+        if local.ledger.entries().is_empty() && ledger_path.exists() {
+            let entries: Vec<SignedEntry> = serde_json::from_str(&std::fs::read_to_string(&ledger_path)?).map_err(std::io::Error::from)?;
+            local.ledger = Ledger::from_entries(entries, &keys.public())?;
+            for entry in local.ledger.entries() {
+                local.store.append_ledger_entry(entry)?;
+            }
+            std::fs::rename(&ledger_path, dir.join("ledger.json.migrated"))?;
+        }
+
+        let wallet_path = dir.join("wallet.json");
+        if local.ledger.entries().is_empty() && wallet_path.exists() {
+            #[derive(Deserialize)]
+            struct Wallet {
+                balance: f64,
+            }
+            let wallet: Wallet = serde_json::from_str(&std::fs::read_to_string(&wallet_path)?).map_err(std::io::Error::from)?;
+            if wallet.balance > 0.0 {
+                local.record(keys, EntryKind::Earn, wallet.balance, LedgerEvent::Opening)?;
+            }
+            std::fs::rename(&wallet_path, dir.join("wallet.json.migrated"))?;
+        }
+
+        Ok(local)
+    }
+
+    pub fn record(&mut self, keys: &identity::Keypair, kind: EntryKind, amount: f64, event: LedgerEvent) -> Result<SignedEntry, NetworkError> {
+        let entry = self.ledger.record(keys, kind, amount, event)?.clone();
+        self.store.append_ledger_entry(&entry)?;
+        Ok(entry)
+    }
+
+    /// Ends the chain for `old_keys` with a zero-amount `KeyRotated` entry; from then on the
+    /// handover's new key signs (and owns) the ledger.
+    pub fn rotate_key(&mut self, old_keys: &identity::Keypair, handover: &KeyHandover) -> Result<SignedEntry, NetworkError> {
+        handover.verify()?;
+        if handover.old_peer_id != old_keys.public().to_peer_id().to_string() {
+            // The handover isn't from this ledger's key
+            return Err(LedgerError::WrongOwner.into());
+        }
+        let event = LedgerEvent::KeyRotated { handover: handover.clone() };
+        self.record(old_keys, EntryKind::Earn, 0.0, event)
+    }
+}
+
+/// Carries the ledger in `data_dir` over to a new identity key. The node must not be running,
+/// it holds the store open.
+pub fn hand_over_ledger(data_dir: &Path, old_keys: &identity::Keypair, handover: &KeyHandover) -> Result<SignedEntry, NetworkError> {
+    let store = Store::open(&data_dir.join(STORE_FILE))?;
+    let mut local = LocalLedger::open(store, Some(data_dir), old_keys)?;
+    local.rotate_key(old_keys, handover)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::TempDir;
+
+    fn legacy_chain(keys: &identity::Keypair) -> Ledger {
+        let mut ledger = Ledger::new();
+        ledger.record(keys, EntryKind::Earn, 3.0, LedgerEvent::Uptime { minutes: 30 }).unwrap();
+        ledger.record(keys, EntryKind::Spend, 1.0, LedgerEvent::TasksRelayed { count: 1 }).unwrap();
+        ledger
+    }
+
+    #[test]
+    fn imports_legacy_ledger_once() {
+        let dir = TempDir::new("ledger");
+        let keys = identity::Keypair::generate_ed25519();
+        let legacy = legacy_chain(&keys);
+        std::fs::write(dir.0.join("ledger.json"), serde_json::to_vec(legacy.entries()).unwrap()).unwrap();
+
+        let store = Store::open(&dir.0.join(STORE_FILE)).unwrap();
+        let local = LocalLedger::open(store.clone(), Some(&dir.0), &keys).unwrap();
+        assert_eq!(local.ledger.balance(), 2.0);
+        assert_eq!(store.ledger_entries().unwrap(), legacy.entries());
+        assert!(!dir.0.join("ledger.json").exists());
+        assert!(dir.0.join("ledger.json.migrated").exists());
+
+        // The store is the source of truth from now on
+        let reopened = LocalLedger::open(store, Some(&dir.0), &keys).unwrap();
+        assert_eq!(reopened.ledger.entries(), legacy.entries());
+    }
+
+    #[test]
+    fn imports_wallet_balance_as_opening_entry() {
+        let dir = TempDir::new("ledger");
+        let keys = identity::Keypair::generate_ed25519();
+        std::fs::write(dir.0.join("wallet.json"), r#"{"balance": 12.5}"#).unwrap();
+
+        let local = LocalLedger::open(Store::in_memory().unwrap(), Some(&dir.0), &keys).unwrap();
+        assert_eq!(local.ledger.balance(), 12.5);
+        assert_eq!(local.ledger.entries()[0].entry.event, LedgerEvent::Opening);
+        assert!(dir.0.join("wallet.json.migrated").exists());
+    }
+
+    #[test]
+    fn refuses_someone_elses_legacy_ledger() {
+        let dir = TempDir::new("ledger");
+        let keys = identity::Keypair::generate_ed25519();
+        let other = identity::Keypair::generate_ed25519();
+        std::fs::write(dir.0.join("ledger.json"), serde_json::to_vec(legacy_chain(&other).entries()).unwrap()).unwrap();
+
+        let opened = LocalLedger::open(Store::in_memory().unwrap(), Some(&dir.0), &keys);
+        assert!(matches!(opened, Err(NetworkError::Ledger(LedgerError::BadSignature { seq: 0 }))));
+        assert!(dir.0.join("ledger.json").exists());
+    }
+
+    #[test]
+    fn rotation_moves_ownership() {
+        let dir = TempDir::new("ledger");
+        let (old, new) = (identity::Keypair::generate_ed25519(), identity::Keypair::generate_ed25519());
+        let store = Store::open(&dir.0.join(STORE_FILE)).unwrap();
+        let mut local = LocalLedger::open(store.clone(), None, &old).unwrap();
+        local.record(&old, EntryKind::Earn, 5.0, LedgerEvent::Uptime { minutes: 50 }).unwrap();
+
+        // A handover for some other key doesn't apply to this ledger
+        let stranger = identity::Keypair::generate_ed25519();
+        let wrong = KeyHandover::sign(&stranger, &new).unwrap();
+        assert!(matches!(local.rotate_key(&old, &wrong), Err(NetworkError::Ledger(LedgerError::WrongOwner))));
+
+        local.rotate_key(&old, &KeyHandover::sign(&old, &new).unwrap()).unwrap();
+        assert!(LocalLedger::open(store.clone(), None, &old).is_err());
+        assert_eq!(LocalLedger::open(store, None, &new).unwrap().ledger.balance(), 5.0);
+    }
+}
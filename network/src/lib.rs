@@ -1,175 +1,398 @@
+mod accounting;
+pub mod api;
 mod behaviour;
+mod blobs;
+mod config;
+mod dht;
+mod error;
+mod ledger;
+mod limits;
+mod peers;
+mod store;
+mod validation;
 
-use anyhow::Result;
+use crate::accounting::ContributionAccountant;
 use crate::behaviour::{RhizomeBehaviour, RhizomeBehaviourEvent};
+use crate::blobs::{BlobRequest, BlobResponse, BlobStore, PendingTask};
+use crate::dht::PersistentStore;
+use crate::error::stopped;
+use crate::ledger::{LedgerRequest, LedgerResponse, LocalLedger};
+use crate::limits::{Job, Origin, RateLimiter, WorkQueue};
+use crate::peers::PeerTable;
+use crate::validation::{GossipMessage, Invalid};
+use tracing::{debug, debug_span, error, info, info_span, warn, Instrument};
 use libp2p::{
     futures::StreamExt,
-    gossipsub, kad, mdns, noise, tcp, yamux, SwarmBuilder,
-    identity,
+    autonat, dcutr, gossipsub, identify, kad, mdns, noise, ping, pnet, relay, request_response, tcp, yamux, SwarmBuilder,
+    core::transport::ListenerId, core::upgrade, identity, multiaddr::Protocol, Transport,
 };
+use tokio::task::AbortHandle;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use std::time::Duration;
-use tokio::sync::{mpsc, broadcast};
-use xnet_core::{DynError, InferenceTask, PipelineEvent, VerificationEvent, FLEvent, RuntimeInterface};
-use xnet_runtime::OllamaRuntime;
+use tokio::sync::{mpsc, broadcast, oneshot};
+use xnet_core::{BlobRef, CapabilityAdvertisement, ChallengeTarget, DynError, EntryKind, GenerationOptions, InferenceTask, Ledger, LedgerError, LedgerEvent, ModelCapability, ModelInfo, PeerInfo, PipelineEvent, SignedEntry, SignedReceipt, TaskCancel, TaskOutput, TaskReceipt, TaskRecord, TaskResult, TaskRole, TaskStatus, VerificationEvent, FLEvent, RuntimeInterface};
+use std::collections::HashMap;
+use xnet_runtime::{OllamaRuntime, RuntimeError};
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 
 pub use xnet_core::NetworkInterface;
+pub use crate::accounting::{NetworkParams, RewardWeights};
+pub use crate::config::{generate_psk, ConfigError, LogConfig, LogFormat, NodeConfig, Topics, CONFIG_FILE, DEFAULT_OLLAMA_URL};
+pub use crate::error::NetworkError;
+pub use crate::ledger::hand_over_ledger;
+pub use crate::store::{DhtRecord, PeerRecord, Store, StoreError, STORE_FILE};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum NetworkEvent {
     PeerConnected(String),
     PeerDisconnected(String),
+    PeerUpdated(PeerInfo),
     TaskReceived(InferenceTask),
+    /// A notice for the UI to show; diagnostics go to the tracing log instead
     Message(String),
     DhtEvent(String),
     MetricsUpdated(NodeMetrics),
     PipelineEvent(PipelineEvent),
     VerificationEvent(VerificationEvent),
     FLEvent(FLEvent),
+    CapabilitiesAdvertised(CapabilityAdvertisement),
+    LedgerEntryAdded(SignedEntry),
+    TaskCompleted(TaskResult),
 }
 
 use xnet_core::NodeMetrics;
 
+// How long a published task keeps its bid reserved while waiting for a result
+const RESULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+// How often expired DHT records are purged from the store
+const DHT_CLEANUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// Providers repeat their capability advertisement this often; requesters forget a provider
+// they haven't heard from in three rounds
+const CAPABILITY_ADVERT_INTERVAL: Duration = Duration::from_secs(60);
+const CAPABILITY_TTL: Duration = Duration::from_secs(3 * 60);
+
+// How long `P2PNode::new` waits for the first listen address
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How long shutdown waits for running inference before closing anyway
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+// How long shutdown waits for connections to close
+const SHUTDOWN_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Sent in identify so peers can tell xNet nodes apart
+const IDENTIFY_PROTOCOL_VERSION: &str = "/xnet/1.0.0";
+
+// Saved peers put back into the routing table on startup
+const MAX_RESTORED_PEERS: usize = 50;
+const MIN_RESTORE_SCORE: i64 = -3;
+
+/// A task this node published and hasn't settled yet.
+struct OpenTask {
+    max_price: f64,
+    published_at: std::time::Instant,
+    waiter: Option<oneshot::Sender<TaskResult>>,
+}
+
+/// How a worker ran a task, as committed to in its receipt.
+struct Execution {
+    model_digest: String,
+    options: GenerationOptions,
+    started_at: u64,  // Unix milliseconds
+    finished_at: u64, // Unix milliseconds
+}
+
 #[derive(Clone)]
 pub struct P2PNode {
     sender: mpsc::Sender<Command>,
     event_sender: broadcast::Sender<NetworkEvent>,
+    task_stamp_bits: u32,
+    peer_id: libp2p::PeerId,
 }
 
 enum Command {
-    PublishTask(InferenceTask),
+    PublishTask {
+        task: InferenceTask,
+        reply: oneshot::Sender<Result<(), NetworkError>>,
+        result: Option<oneshot::Sender<TaskResult>>,
+    },
     PublishPipeline(PipelineEvent),
     PublishVerification(VerificationEvent),
     PublishFL(FLEvent),
     StartProviding,
+    StopProviding,
+    AdvertiseCapabilities(Vec<ModelInfo>),
+    PutBlob(Vec<u8>, Option<String>, oneshot::Sender<Result<BlobRef, NetworkError>>),
+    TaskFinished { task: InferenceTask, requester: String, output: Result<TaskOutput, String>, execution: Execution },
+    RunLocal(InferenceTask, Vec<Vec<u8>>, oneshot::Sender<Result<TaskOutput, NetworkError>>),
+    LocalTaskFinished(String),
+    CancelTask(String, oneshot::Sender<Result<(), NetworkError>>),
+    GetLedger(oneshot::Sender<Vec<SignedEntry>>),
+    GetTasks(oneshot::Sender<Vec<TaskRecord>>),
+    GetMetrics(oneshot::Sender<NodeMetrics>),
+    GetPeers(oneshot::Sender<Vec<PeerInfo>>),
+    FetchLedger(libp2p::PeerId, oneshot::Sender<Result<Ledger, NetworkError>>),
+    Shutdown(oneshot::Sender<()>),
 }
 
 use libp2p::Multiaddr;
 
 impl P2PNode {
-    pub async fn new(bootnodes: Vec<Multiaddr>, keypair_bytes: Option<Vec<u8>>, initial_credits: f64) -> Result<Self> {
+    /// `data_dir` holds the node store (`node.redb`: tasks, receipts, peers, ledger); without one
+    /// everything lives in memory only.
+    pub async fn new(keypair_bytes: Option<Vec<u8>>, data_dir: Option<PathBuf>, config: NodeConfig) -> Result<Self, NetworkError> {
+        config.validate()?;
+        let psk = config.pre_shared_key()?;
+        let task_stamp_bits = config.network.task_stamp_bits;
         let (sender, mut receiver) = mpsc::channel(32);
         let (event_sender, _) = broadcast::channel(100);
         let event_sender_clone = event_sender.clone();
         
         let command_sender = sender.clone(); // Clone for the event loop
 
-        tokio::spawn(async move {
-            // Initialize Ollama Runtime
-            let runtime = OllamaRuntime::new("http://localhost:11434");
-            
-            let id_keys = if let Some(bytes) = keypair_bytes {
-                identity::Keypair::from_protobuf_encoding(&bytes)?
-            } else {
-                identity::Keypair::generate_ed25519()
-            };
-            let peer_id = id_keys.public().to_peer_id();
-            println!("Local Peer ID: {}", peer_id);
-            
-            // Note: We use command_sender inside the loop for self-messaging if needed
-            let sender = command_sender; // Rename for clarity inside, or just use command_sender
+        let id_keys = if let Some(bytes) = keypair_bytes {
+            identity::Keypair::from_protobuf_encoding(&bytes).map_err(|e| NetworkError::Identity(e.to_string()))?
+        } else {
+            identity::Keypair::generate_ed25519()
+        };
+
+        let store = match &data_dir {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)?;
+                Store::open(&dir.join(STORE_FILE))?
+            }
+            None => Store::in_memory()?,
+        };
+
+        // A ledger that fails verification is an error, never silently reset
+        let mut local_ledger = LocalLedger::open(store.clone(), data_dir.as_deref(), &id_keys)?;
+
+        // Task history survives restarts, including receipts still waiting for a countersignature
+        let mut task_records: HashMap<String, TaskRecord> = store.tasks()?
+            .into_iter()
+            .map(|record| (record.task.id.clone(), record))
+            .collect();
 
-            let mut swarm = SwarmBuilder::with_existing_identity(id_keys)
-                .with_tokio()
+        let ledger_keys = id_keys.clone();
+        let peer_id = id_keys.public().to_peer_id();
+        info!("Local Peer ID: {}", peer_id);
+
+        // With a pre-shared key only TCP is used: the key is checked before noise, so nodes
+        // outside the private network can't even complete a handshake
+        let transport_error = |e: &dyn std::fmt::Display| NetworkError::Transport(e.to_string());
+        let builder = SwarmBuilder::with_existing_identity(id_keys).with_tokio();
+        let mut swarm = match psk {
+            Some(psk) => builder
+                .with_other_transport(|key| -> Result<_, Box<dyn std::error::Error + Send + Sync>> {
+                    Ok(tcp::tokio::Transport::new(tcp::Config::default())
+                        .and_then(move |socket, _| pnet::PnetConfig::new(psk).handshake(socket))
+                        .upgrade(upgrade::Version::V1Lazy)
+                        .authenticate(noise::Config::new(key)?)
+                        .multiplex(yamux::Config::default()))
+                }).map_err(|e| transport_error(&e))?
+                .with_relay_client(noise::Config::new, yamux::Config::default).map_err(|e| transport_error(&e))?
+                .with_behaviour(|key, relay_client| new_behaviour(key, relay_client, &config, &store)).map_err(|e| transport_error(&e))?
+                .with_swarm_config(|c| c.with_idle_connection_timeout(config.idle_connection_timeout()))
+                .build(),
+            None => builder
                 .with_tcp(
                     tcp::Config::default(),
                     noise::Config::new,
                     yamux::Config::default,
-                )?
+                ).map_err(|e| transport_error(&e))?
                 .with_quic()
-                .with_behaviour(|key| {
-                    let peer_id = key.public().to_peer_id();
-                    
-                    // Gossipsub config
-                    let message_id_fn = |message: &gossipsub::Message| {
-                        let mut s = DefaultHasher::new();
-                        message.data.hash(&mut s);
-                        gossipsub::MessageId::from(s.finish().to_string())
-                    };
-                    let gossipsub_config = gossipsub::ConfigBuilder::default()
-                        .heartbeat_interval(Duration::from_secs(10))
-                        .validation_mode(gossipsub::ValidationMode::Strict)
-                        .message_id_fn(message_id_fn)
-                        .max_transmit_size(256 * 1024) // Increase max size for Tensors
-                        .build()
-                        .map_err(|msg| std::io::Error::new(std::io::ErrorKind::Other, msg))?;
-
-                    let gossipsub = gossipsub::Behaviour::new(
-                        gossipsub::MessageAuthenticity::Signed(key.clone()),
-                        gossipsub_config,
-                    )?;
-
-                    // mDNS config
-                    let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id)?;
-                    
-                    // Kademlia (DHT) config
-                    let store = kad::store::MemoryStore::new(peer_id);
-                    let kad_config = kad::Config::default();
-                    let kad = kad::Behaviour::with_config(peer_id, store, kad_config);
-
-                    Ok(RhizomeBehaviour { gossipsub, mdns, kad })
-                })?
-                .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
-                .build();
-
-            swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
-            swarm.listen_on("/ip4/0.0.0.0/udp/0/quic-v1".parse()?)?;
-
-            // Subscribe to topics
-            let tasks_topic = gossipsub::IdentTopic::new("xnet/tasks/v1");
-            swarm.behaviour_mut().gossipsub.subscribe(&tasks_topic)?;
-
-            let pipeline_topic = gossipsub::IdentTopic::new("xnet/pipeline/v1");
-            swarm.behaviour_mut().gossipsub.subscribe(&pipeline_topic)?;
-
-            let verification_topic = gossipsub::IdentTopic::new("xnet/verification/v1");
-            swarm.behaviour_mut().gossipsub.subscribe(&verification_topic)?;
-
-            let fl_topic = gossipsub::IdentTopic::new("xnet/fl/v1");
-            swarm.behaviour_mut().gossipsub.subscribe(&fl_topic)?;
+                .with_relay_client(noise::Config::new, yamux::Config::default).map_err(|e| transport_error(&e))?
+                .with_behaviour(|key, relay_client| new_behaviour(key, relay_client, &config, &store)).map_err(|e| transport_error(&e))?
+                .with_swarm_config(|c| c.with_idle_connection_timeout(config.idle_connection_timeout()))
+                .build(),
+        };
+
+        // Binding happens here, so a port in use fails the start instead of the detached event loop
+        let mut listeners: HashMap<ListenerId, Multiaddr> = HashMap::new();
+        for addr in config.listen_addrs.iter().cloned() {
+            if psk.is_some() && crate::config::is_quic(&addr) {
+                warn!("QUIC can't use the pre-shared key, not listening on {}", addr);
+                continue;
+            }
+            let id = swarm.listen_on(addr.clone()).map_err(|e| NetworkError::Listen { addr: addr.clone(), message: e.to_string() })?;
+            listeners.insert(id, addr);
+        }
+
+        // Subscribe to topics
+        let topics = config.network_topics();
+        for topic in topics.all() {
+            swarm.behaviour_mut().gossipsub.subscribe(&gossipsub::IdentTopic::new(topic))
+                .map_err(|e| NetworkError::Subscribe { topic: topic.to_string(), message: e.to_string() })?;
+        }
+
+        // Told once the first address is up, or that none came up
+        let (ready, started) = oneshot::channel::<Result<(), NetworkError>>();
+
+        tokio::spawn(async move {
+            // Initialize Ollama Runtime
+            // Network tasks are capped so a single peer can't monopolise this worker
+            let runtime = config.ollama_runtime().with_limits(config.limits.generation_limits());
+            // API requests from this machine run uncapped, like single node mode
+            let local_runtime = config.ollama_runtime();
+
+            let local_id = peer_id.to_string();
+            let mut ready = Some(ready);
             
+            // Note: We use command_sender inside the loop for self-messaging if needed
+            let sender = command_sender; // Rename for clarity inside, or just use command_sender
+
             // Set Kademlia mode to Server
             swarm.behaviour_mut().kad.set_mode(Some(kad::Mode::Server));
 
             // Bootstrap
-            for addr in bootnodes {
-                println!("Bootstrapping with {}", addr);
+            for addr in config.bootnodes.iter().cloned() {
+                info!("Bootstrapping with {}", addr);
                 // Extract PeerId from Multiaddr if present (required for add_address)
                 // For Kademlia add_address, we need PeerId.
                 // Assuming multiaddr ends with /p2p/<peer_id>
                 if let Some(libp2p::multiaddr::Protocol::P2p(peer_id)) = addr.iter().last() {
-                     println!("Adding bootnode: {} ({})", peer_id, addr);
+                     debug!("Adding bootnode: {} ({})", peer_id, addr);
                      swarm.behaviour_mut().kad.add_address(&peer_id, addr);
                 } else {
-                     println!("Invalid bootnode address (missing PeerID): {}", addr);
+                     warn!("Invalid bootnode address (missing PeerID): {}", addr);
+                }
+            }
+            // Peers from previous runs seed the routing table, so a restart doesn't depend on bootnodes
+            match store.peers() {
+                Ok(peers) => {
+                    let mut restored = 0;
+                    for peer in peers.iter().filter(|p| p.score >= MIN_RESTORE_SCORE).take(MAX_RESTORED_PEERS) {
+                        let Ok(peer_id) = peer.peer_id.parse::<libp2p::PeerId>() else { continue };
+                        for addr in peer.addresses.iter().filter_map(|a| a.parse::<Multiaddr>().ok()) {
+                            swarm.behaviour_mut().kad.add_address(&peer_id, addr);
+                        }
+                        restored += 1;
+                    }
+                    info!("Restored {} known peers into the routing table", restored);
                 }
+                Err(e) => warn!("Failed to load known peers: {}", e),
             }
             if let Err(e) = swarm.behaviour_mut().kad.bootstrap() {
-                println!("Bootstrap error: {:?}", e);
+                warn!("Bootstrap error: {:?}", e);
             }
 
             let start_time = std::time::Instant::now();
             let mut metrics = NodeMetrics::new();
-            metrics.credits = initial_credits;
+            metrics.credits = local_ledger.ledger.balance();
             let mut last_metrics_update = std::time::Instant::now();
+            let mut last_dht_cleanup = std::time::Instant::now();
+
+            // Decides which contribution earns credits
+            let mut accountant = ContributionAccountant::new(config.network.rewards);
+            let mut ledger_fetches: HashMap<request_response::OutboundRequestId, (libp2p::PeerId, oneshot::Sender<Result<Ledger, NetworkError>>)> = HashMap::new();
+
+            // Payments: bids reserved for our open tasks, and receipts we issued as a worker
+            let mut open_tasks: HashMap<String, OpenTask> = HashMap::new();
+            let mut reserved_credits: f64 = 0.0;
+            let mut issued_receipts: HashMap<String, SignedReceipt> = task_records.values()
+                .filter(|r| r.role == TaskRole::Worker)
+                .filter_map(|r| r.receipt.clone())
+                .filter(|receipt| receipt.requester_signature.is_none())
+                .map(|receipt| (receipt.receipt.task_id.clone(), receipt))
+                .collect();
+
+            // Models this node serves (filled when it announces itself as a provider)
+            // and the models each remote provider has advertised, with when we last heard it.
+            let mut local_models: Vec<ModelInfo> = Vec::new();
+            let mut accepting_tasks = true; // Until told to stop providing
+            let mut peer_capabilities: HashMap<String, (Vec<ModelInfo>, std::time::Instant)> = HashMap::new();
+            let mut last_capability_advert = std::time::Instant::now();
+            let mut capability_advert_due = false; // A peer joined the capabilities topic
+
+            // Task images: our own and fetched ones, tasks waiting on fetches, and in-flight requests
+            let mut blob_store = BlobStore::default();
+            let mut pending_tasks: HashMap<String, PendingTask> = HashMap::new();
+            let mut blob_fetches: HashMap<request_response::OutboundRequestId, String> = HashMap::new();
+
+            // Relays we could listen through (peers advertising the hop protocol), and the slots we hold
+            let mut relay_candidates: HashMap<libp2p::PeerId, Multiaddr> = HashMap::new();
+            let mut relay_reservations: HashMap<libp2p::PeerId, ListenerId> = HashMap::new();
+            let mut publicly_reachable: Option<bool> = None;
+
+            // Everyone we have been connected to: addresses, identify info, RTT and connection state
+            let mut peer_table = PeerTable::default();
+
+            // Spam protection: gossip rate limits, and the inference slots and queue
+            let mut rate_limiter = RateLimiter::new(config.limits.clone());
+            let mut work_queue = WorkQueue::new(&config.limits);
+
+            // Shutdown: who to tell once stopped, and how long to wait for running inference
+            let mut shutdown: Vec<oneshot::Sender<()>> = Vec::new();
+            let mut drain_deadline = tokio::time::Instant::now();
 
             loop {
+                if !shutdown.is_empty() && work_queue.running() == 0 {
+                    break;
+                }
+
                 // Emit metrics every 5 seconds
                 if last_metrics_update.elapsed() > Duration::from_secs(5) {
                     metrics.uptime_seconds = start_time.elapsed().as_secs();
-                    
-                    // Simple Reward Calculation Logic (Proof of Contribution)
-                    // 0.1 Credit per minute of uptime
-                    // 5.0 Credits per task processed
-                    // 1.0 Credit per task relayed
-                    let uptime_credits = (metrics.uptime_seconds as f64 / 60.0) * 0.1;
-                    let processing_credits = metrics.tasks_processed as f64 * 5.0;
-                    let relay_credits = metrics.tasks_relayed as f64 * 1.0;
-                    
-                    metrics.credits = uptime_credits + processing_credits + relay_credits;
+
+                    // Book contribution into the signed ledger; the balance is derived from it
+                    if let Some((minutes, amount)) = accountant.take_uptime(metrics.uptime_seconds) {
+                        record_credit(&mut local_ledger, &ledger_keys, EntryKind::Earn, amount, LedgerEvent::Uptime { minutes }, &event_sender_clone);
+                    }
+                    if let Some((count, amount)) = accountant.take_relays() {
+                        record_credit(&mut local_ledger, &ledger_keys, EntryKind::Earn, amount, LedgerEvent::TasksRelayed { count }, &event_sender_clone);
+                    }
+
+                    metrics.credits = local_ledger.ledger.balance();
+
+                    // Release bids for tasks nobody answered
+                    open_tasks.retain(|task_id, open| {
+                        let expired = open.published_at.elapsed() > RESULT_TIMEOUT;
+                        if expired {
+                            info!(task_id = %task_id, "Task expired without a result");
+                            publish_cancel(&mut swarm, &topics, task_id, &local_id);
+                            reserved_credits -= open.max_price;
+                            if let Some(record) = task_records.get_mut(task_id) {
+                                record.status = TaskStatus::Failed("No result before timeout".to_string());
+                                record.updated_at = xnet_core::unix_timestamp();
+                                save_task(&store, record);
+                            }
+                        }
+                        !expired
+                    });
+
+                    rate_limiter.prune(std::time::Instant::now());
+                    for job in work_queue.expire(tokio::time::Instant::now()) {
+                        reject_job(job, RuntimeError::Timeout("task deadline passed".to_string()), &mut task_records, &store, &event_sender_clone);
+                    }
+                    metrics.running_tasks = work_queue.running() as u64;
+                    metrics.queued_tasks = work_queue.queued() as u64;
+
+                    // Late joiners hear from us on the next tick, everyone else once a minute
+                    if !local_models.is_empty() && (capability_advert_due || last_capability_advert.elapsed() > CAPABILITY_ADVERT_INTERVAL) {
+                        publish_capabilities(&mut swarm, &topics, &local_id, local_models.clone());
+                        last_capability_advert = std::time::Instant::now();
+                        capability_advert_due = false;
+                    }
+                    peer_capabilities.retain(|_, (_, heard)| heard.elapsed() < CAPABILITY_TTL);
+
+                    for hash in blob_store.take_evicted() {
+                        swarm.behaviour_mut().kad.stop_providing(&kad::RecordKey::new(&format!("xnet-blob-v1/{}", hash)));
+                    }
+
+                    // Task, peer and DHT writes since the last tick go to disk together
+                    if let Err(e) = store.flush() {
+                        error!("Failed to flush the node store: {}", e);
+                    }
+
+                    if last_dht_cleanup.elapsed() > DHT_CLEANUP_INTERVAL {
+                        swarm.behaviour_mut().kad.store_mut().remove_expired();
+                        last_dht_cleanup = std::time::Instant::now();
+                    }
 
                     let _ = event_sender_clone.send(NetworkEvent::MetricsUpdated(metrics.clone()));
                     last_metrics_update = std::time::Instant::now();
@@ -179,61 +402,126 @@ impl P2PNode {
                      event = swarm.select_next_some() => {
                         match event {
                              libp2p::swarm::SwarmEvent::NewListenAddr { address, .. } => {
-                                 println!("Listening on {:?}", address);
+                                 info!("Listening on {}", address);
+                                 if let Some(ready) = ready.take() {
+                                     let _ = ready.send(Ok(()));
+                                 }
                              },
                              libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
                                  for (peer_id, multiaddr) in list {
-                                     println!("mDNS discovered a new peer: {}", peer_id);
+                                     debug!("mDNS discovered a new peer: {}", peer_id);
                                      swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
                                      swarm.behaviour_mut().kad.add_address(&peer_id, multiaddr); // Add to DHT
-                                     let _ = event_sender_clone.send(NetworkEvent::PeerConnected(peer_id.to_string()));
                                  }
                              },
                              libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
                                  for (peer_id, _multiaddr) in list {
-                                     println!("mDNS discover peer has expired: {}", peer_id);
+                                     debug!("mDNS discover peer has expired: {}", peer_id);
                                      swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
-                                     let _ = event_sender_clone.send(NetworkEvent::PeerDisconnected(peer_id.to_string()));
                                  }
                              },
-                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Gossipsub(gossipsub::Event::Message { propagation_source: peer_id, message_id: _, message })) => {
-                                 metrics.tasks_relayed += 1;
-                                 let topic = message.topic.as_str();
-
-                                 if topic == "xnet/tasks/v1" {
-                                     // Handle Task with REAL Inference
-                                     if let Ok(task) = serde_json::from_slice::<InferenceTask>(&message.data) {
-                                         metrics.tasks_processed += 1;
-                                         println!("Got task from {}: {:?}", peer_id, task.id);
+                             // A peer that joins late missed our last advert
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Gossipsub(gossipsub::Event::Subscribed { topic, .. }))
+                                 if topic == gossipsub::IdentTopic::new(&topics.capabilities).hash() => {
+                                 capability_advert_due = true;
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Gossipsub(gossipsub::Event::Message { propagation_source: peer_id, message_id, message })) => {
+                                 // Floods are dropped before any decoding; going over the limit isn't misbehaviour on its own
+                                 if !rate_limiter.allow(message.source.unwrap_or(peer_id), &message.topic, std::time::Instant::now()) {
+                                     let _ = swarm.behaviour_mut().gossipsub.report_message_validation_result(&message_id, &peer_id, gossipsub::MessageAcceptance::Ignore);
+                                     continue;
+                                 }
+                                 let _peer = debug_span!("peer", peer = %peer_id).entered();
+                                 // Nothing is forwarded until it validates; rejections count against the sender's score
+                                 let decoded = validation::validate(&topics, &message, xnet_core::unix_timestamp_millis());
+                                 let acceptance = decoded.as_ref().map_or_else(Invalid::acceptance, |_| gossipsub::MessageAcceptance::Accept);
+                                 let _ = swarm.behaviour_mut().gossipsub.report_message_validation_result(&message_id, &peer_id, acceptance);
+                                 let decoded = match decoded {
+                                     Ok(decoded) => decoded,
+                                     Err(invalid) => {
+                                         debug!(topic = %message.topic, "Dropped message: {}", invalid.reason);
+                                         if invalid.reject {
+                                             update_peer(&store, &peer_id, None, -1);
+                                         }
+                                         continue;
+                                     }
+                                 };
+
+                                 match decoded {
+                                     GossipMessage::Task(task) => {
+                                         let _task = info_span!("task", task_id = %task.id).entered();
+                                         // Handle Task with REAL Inference
+                                         // Relay credit only when gossipsub forwards it to someone other than where it came from
+                                         let forwarded = swarm.behaviour().gossipsub.mesh_peers(&message.topic)
+                                             .any(|p| *p != peer_id && Some(*p) != message.source);
+                                         if forwarded && accountant.record_relay(&task.id) {
+                                             metrics.tasks_relayed += 1;
+                                         }
+
+                                         if !accepting_tasks {
+                                             continue;
+                                         }
+
+                                         // Embedding and vision jobs only go to nodes that advertise a matching model
+                                         let required = task.required_capabilities();
+                                         if required.iter().any(|c| *c != ModelCapability::Completion) {
+                                             let serves = local_models.iter().any(|m| {
+                                                 m.name == task.model_name && required.iter().all(|c| m.supports(*c))
+                                             });
+                                             if !serves {
+                                                 debug!("Skipping task (model {} not served here with {:?})", task.model_name, required);
+                                                 continue;
+                                             }
+                                         }
+                                         // Requesters we have no countersigned receipt from pay with proof of work
+                                         let stamp_bits = config.network.task_stamp_bits;
+                                         if stamp_bits > 0 && !task.has_stamp(stamp_bits) && !has_reputation(&task_records, task.requester.as_deref()) {
+                                             debug!("Skipping task (no proof-of-work stamp)");
+                                             continue;
+                                         }
+                                         info!(model = %task.model_name, "Got task");
                                          let _ = event_sender_clone.send(NetworkEvent::TaskReceived(task.clone()));
-                                         
-                                         // Perform real inference using Ollama
-                                         let runtime_clone = runtime.clone();
-                                         let event_sender_for_result = event_sender_clone.clone();
-                                         tokio::spawn(async move {
-                                             match runtime_clone.generate(&task.model_name, &task.prompt).await {
-                                                 Ok(response) => {
-                                                     let preview = if response.len() > 50 {
-                                                         format!("{}...", &response[..50])
+
+                                         // Images are fetched from the task author before running
+                                         let missing: std::collections::HashSet<String> = task.images.iter()
+                                             .filter(|b| !blob_store.contains(&b.hash))
+                                             .map(|b| b.hash.clone())
+                                             .collect();
+                                         let author = message.source.unwrap_or(peer_id);
+                                         let mut record = TaskRecord::new(task.clone(), TaskRole::Worker);
+                                         record.status = TaskStatus::Processing;
+                                         save_task(&store, &record);
+                                         task_records.insert(task.id.clone(), record);
+                                         let deadline = job_deadline(&task, config.limits.task_timeout());
+                                         if missing.is_empty() {
+                                             match blob_store.resolve(&task.images) {
+                                                 Ok(images) => {
+                                                     let job = Job { task, images, origin: Origin::Network(author), deadline };
+                                                     if work_queue.has_room(pending_tasks.len()) {
+                                                         work_queue.submit(job);
                                                      } else {
-                                                         response.clone()
-                                                     };
-                                                     println!("[REAL AI] Task {} completed: {}", task.id, preview);
-                                                     let msg = format!("[AI Response] {}", response);
-                                                     let _ = event_sender_for_result.send(NetworkEvent::Message(msg));
-                                                 },
-                                                 Err(e) => {
-                                                     println!("[REAL AI] Task {} failed: {}", task.id, e);
-                                                     let msg = format!("[AI Error] {}", e);
-                                                     let _ = event_sender_for_result.send(NetworkEvent::Message(msg));
+                                                         reject_job(job, RuntimeError::Overloaded("worker queue is full".to_string()), &mut task_records, &store, &event_sender_clone);
+                                                     }
                                                  }
+                                                 Err(e) => fail_task(&task.id, e, &mut task_records, &store, &event_sender_clone),
                                              }
-                                         });
+                                             start_jobs(&mut work_queue, &runtime, &local_runtime, &event_sender_clone, &sender);
+                                         } else if !work_queue.has_room(pending_tasks.len()) {
+                                             // No point fetching images for a task there's no room for
+                                             let job = Job { task, images: Vec::new(), origin: Origin::Network(author), deadline };
+                                             reject_job(job, RuntimeError::Overloaded("worker queue is full".to_string()), &mut task_records, &store, &event_sender_clone);
+                                         } else {
+                                             for hash in &missing {
+                                                 let request_id = swarm.behaviour_mut().blobs.send_request(&author, BlobRequest { hash: hash.clone() });
+                                                 blob_fetches.insert(request_id, hash.clone());
+                                             }
+                                             pending_tasks.insert(task.id.clone(), PendingTask { task, author, missing });
+                                         }
                                      }
-                                 } else if topic == "xnet/pipeline/v1" {
-                                     // Handle Pipeline Event
-                                     if let Ok(event) = serde_json::from_slice::<PipelineEvent>(&message.data) {
-                                         println!("Got pipeline event from {}: {:?}", peer_id, event);
+                                     GossipMessage::Pipeline(event) => {
+                                         let _session = info_span!("session", session_id = %event.session_id()).entered();
+                                         // Handle Pipeline Event
+                                         debug!(?event, "Got pipeline event");
                                          let _ = event_sender_clone.send(NetworkEvent::PipelineEvent(event.clone()));
                                          
                                          // Simulation Logic for Swarm Parallelism
@@ -261,80 +549,420 @@ impl P2PNode {
                                                      let _ = swarm_sender.send(Command::PublishPipeline(next_event)).await;
                                                  });
                                              },
-                                             PipelineEvent::ForwardPass { session_id, layer_start, tensor: _ } => {
-                                                 if layer_start < 30 {
-                                                     // Continue the chain
-                                                     let next_layer = layer_start + 10;
-                                                     let next_event = if next_layer >= 30 {
-                                                         PipelineEvent::Result { 
-                                                             session_id, 
-                                                             token: "Hello xNet!".to_string() 
-                                                         }
-                                                     } else {
-                                                         PipelineEvent::ForwardPass {
-                                                             session_id,
-                                                             layer_start: next_layer,
-                                                             tensor: xnet_core::Tensor { shape: vec![1, 4096], data: vec![0.2; 10] }
-                                                         }
-                                                     };
-                                                     
-                                                     // Broadcast after a delay
-                                                     let swarm_sender = sender.clone();
-                                                     tokio::spawn(async move {
-                                                         tokio::time::sleep(Duration::from_secs(2)).await;
-                                                         let _ = swarm_sender.send(Command::PublishPipeline(next_event)).await;
-                                                     });
-                                                 }
+                                             PipelineEvent::ForwardPass { session_id, layer_start, tensor: _ } if layer_start < 30 => {
+                                                 // Continue the chain
+                                                 let next_layer = layer_start + 10;
+                                                 let next_event = if next_layer >= 30 {
+                                                     PipelineEvent::Result { 
+                                                         session_id, 
+                                                         token: "Hello xNet!".to_string() 
+                                                     }
+                                                 } else {
+                                                     PipelineEvent::ForwardPass {
+                                                         session_id,
+                                                         layer_start: next_layer,
+                                                         tensor: xnet_core::Tensor { shape: vec![1, 4096], data: vec![0.2; 10] }
+                                                     }
+                                                 };
+                                                 
+                                                 // Broadcast after a delay
+                                                 let swarm_sender = sender.clone();
+                                                 tokio::spawn(async move {
+                                                     tokio::time::sleep(Duration::from_secs(2)).await;
+                                                     let _ = swarm_sender.send(Command::PublishPipeline(next_event)).await;
+                                                 });
                                              },
                                              _ => {}
                                          }
                                      }
-                                 } else if topic == "xnet/verification/v1" {
-                                      // Handle Verification Event
-                                     if let Ok(event) = serde_json::from_slice::<VerificationEvent>(&message.data) {
-                                         println!("Got verification event from {}: {:?}", peer_id, event);
+                                     GossipMessage::Verification(event) => {
+                                         // Handle Verification Event
+                                         debug!(?event, "Got verification event");
                                          let _ = event_sender_clone.send(NetworkEvent::VerificationEvent(event));
                                      }
-                                 } else if topic == "xnet/fl/v1" {
-                                     // Handle FL Event
-                                     if let Ok(event) = serde_json::from_slice::<FLEvent>(&message.data) {
-                                         println!("Got FL event from {}: {:?}", peer_id, event);
+                                     GossipMessage::FL(event) => {
+                                         // Handle FL Event
+                                         debug!(?event, "Got FL event");
                                          let _ = event_sender_clone.send(NetworkEvent::FLEvent(event));
                                      }
+                                     GossipMessage::Result(result) => {
+                                         let _task = info_span!("task", task_id = %result.task_id).entered();
+                                         // Handle Task Result: settle if it answers one of our open tasks (first valid result wins)
+                                         let receipt = &result.receipt.receipt;
+                                         let acceptable = open_tasks.get(&result.task_id).is_some_and(|open| {
+                                             receipt.requester == local_id
+                                                 && receipt.worker == result.worker
+                                                 && receipt.price <= open.max_price
+                                                 && task_records.get(&result.task_id)
+                                                     .is_some_and(|record| result.receipt.verify_against(&record.task, &result.output).is_ok())
+                                         });
+                                         if acceptable {
+                                             let open = open_tasks.remove(&result.task_id).expect("checked above");
+                                             reserved_credits -= open.max_price;
+
+                                             let mut result = result;
+                                             if let Err(e) = result.receipt.countersign(&ledger_keys) {
+                                                 warn!("Failed to countersign receipt: {}", e);
+                                                 continue;
+                                             }
+                                             let price = result.receipt.receipt.price;
+                                             if price > 0.0 {
+                                                 let event = LedgerEvent::PaymentSent {
+                                                     task_id: result.task_id.clone(),
+                                                     to: result.worker.clone(),
+                                                     receipt_hash: result.receipt.hash(),
+                                                 };
+                                                 record_credit(&mut local_ledger, &ledger_keys, EntryKind::Spend, price, event, &event_sender_clone);
+                                             }
+                                             if let Ok(data) = serde_json::to_vec(&result.receipt) {
+                                                 let topic = gossipsub::IdentTopic::new(&topics.receipts);
+                                                 if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, data) {
+                                                     warn!("Publish receipt error: {:?}", e);
+                                                 }
+                                             }
+                                             if let Some(record) = task_records.get_mut(&result.task_id) {
+                                                 record.status = TaskStatus::Completed;
+                                                 record.output = Some(result.output.clone());
+                                                 record.receipt = Some(result.receipt.clone());
+                                                 record.updated_at = xnet_core::unix_timestamp();
+                                                 save_task(&store, record);
+                                             }
+                                             info!("Task settled with {} for {:.2} credits", result.worker, price);
+                                             let _ = event_sender_clone.send(NetworkEvent::TaskCompleted(result.clone()));
+                                             if let Some(waiter) = open.waiter {
+                                                 let _ = waiter.send(result);
+                                             }
+                                         }
+                                     }
+                                     GossipMessage::Receipt(receipt) => {
+                                         let _task = info_span!("task", task_id = %receipt.receipt.task_id).entered();
+                                         // Handle Countersigned Receipt: get paid for work we did
+                                         let issued = issued_receipts.get(&receipt.receipt.task_id)
+                                             .is_some_and(|ours| ours.receipt == receipt.receipt);
+                                         if issued {
+                                             issued_receipts.remove(&receipt.receipt.task_id);
+                                             if let Some(record) = task_records.get_mut(&receipt.receipt.task_id) {
+                                                 record.receipt = Some(receipt.clone());
+                                                 record.updated_at = xnet_core::unix_timestamp();
+                                                 save_task(&store, record);
+                                             }
+
+                                             // Execution is only credited once the requester has accepted the result
+                                             if let Some(amount) = accountant.verified_completion(&receipt.receipt.task_id) {
+                                                 let event = LedgerEvent::TaskExecuted {
+                                                     task_id: receipt.receipt.task_id.clone(),
+                                                     requester: receipt.receipt.requester.clone(),
+                                                 };
+                                                 record_credit(&mut local_ledger, &ledger_keys, EntryKind::Earn, amount, event, &event_sender_clone);
+                                             }
+                                             if receipt.receipt.price > 0.0 {
+                                                 let event = LedgerEvent::PaymentReceived {
+                                                     task_id: receipt.receipt.task_id.clone(),
+                                                     from: receipt.receipt.requester.clone(),
+                                                     receipt_hash: receipt.hash(),
+                                                 };
+                                                 record_credit(&mut local_ledger, &ledger_keys, EntryKind::Earn, receipt.receipt.price, event, &event_sender_clone);
+                                             }
+                                         }
+                                     }
+                                     GossipMessage::Capabilities(advert) => {
+                                         // Handle Capability Advertisement
+                                         debug!("Peer {} advertises {} models", advert.peer_id, advert.models.len());
+                                         if advert.models.is_empty() {
+                                             peer_capabilities.remove(&advert.peer_id);
+                                         } else {
+                                             peer_capabilities.insert(advert.peer_id.clone(), (advert.models.clone(), std::time::Instant::now()));
+                                         }
+                                         let _ = event_sender_clone.send(NetworkEvent::CapabilitiesAdvertised(advert));
+                                     }
+                                     GossipMessage::Cancel(cancel) => {
+                                         let _task = info_span!("task", task_id = %cancel.task_id).entered();
+                                         // Only tasks we took from this requester
+                                         let ours = task_records.get(&cancel.task_id).is_some_and(|r| {
+                                             r.role == TaskRole::Worker && r.task.requester.as_deref() == Some(cancel.requester.as_str())
+                                         });
+                                         if !ours {
+                                             continue;
+                                         }
+                                         let waiting = pending_tasks.remove(&cancel.task_id).is_some();
+                                         if waiting || work_queue.cancel(&cancel.task_id) {
+                                             info!("Task cancelled by its requester");
+                                             if let Some(record) = task_records.get_mut(&cancel.task_id) {
+                                                 record.status = TaskStatus::Failed("Cancelled by requester".to_string());
+                                                 record.updated_at = xnet_core::unix_timestamp();
+                                                 save_task(&store, record);
+                                             }
+                                             start_jobs(&mut work_queue, &runtime, &local_runtime, &event_sender_clone, &sender);
+                                         }
+                                     }
                                  }
-                                 
-                                 let text = String::from_utf8_lossy(&message.data);
-                                 let _ = event_sender_clone.send(NetworkEvent::Message(format!("[{}] {}", topic, text)));
                              },
                              libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Kad(kad::Event::ModeChanged { new_mode })) => {
-                                 println!("Kademlia mode changed: {:?}", new_mode);
+                                 debug!("Kademlia mode changed: {:?}", new_mode);
                                  let _ = event_sender_clone.send(NetworkEvent::DhtEvent(format!("Mode: {:?}", new_mode)));
                              },
                              libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Kad(kad::Event::RoutingUpdated { peer, .. })) => {
-                                 println!("DHT Routing updated: {:?}", peer);
+                                 debug!("DHT Routing updated: {:?}", peer);
                                  let _ = event_sender_clone.send(NetworkEvent::DhtEvent(format!("Route Added: {}", peer)));
                              },
                              // Catch-all for other Kademlia events to avoid noise
                              libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Kad(_)) => {},
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Blobs(request_response::Event::Message { peer, message, .. })) => {
+                                 match message {
+                                     request_response::Message::Request { request, channel, .. } => {
+                                         let data = blob_store.get(&request.hash).cloned();
+                                         if swarm.behaviour_mut().blobs.send_response(channel, BlobResponse { data }).is_err() {
+                                             warn!(%peer, "Failed to send blob {}", request.hash);
+                                         }
+                                     }
+                                     request_response::Message::Response { request_id, response } => {
+                                         let Some(hash) = blob_fetches.remove(&request_id) else { continue };
+                                         let stored = response.data
+                                             .map(|data| blob_store.insert_verified(&hash, data))
+                                             .unwrap_or(false);
+                                         if !stored {
+                                             warn!(%peer, "Peer could not provide a valid blob {}", hash);
+                                             fail_pending_tasks(&mut pending_tasks, &hash, &event_sender_clone);
+                                             continue;
+                                         }
+
+                                         // Start every task that was only waiting on this blob
+                                         let ready: Vec<String> = pending_tasks.iter_mut()
+                                             .filter_map(|(id, pending)| {
+                                                 pending.missing.remove(&hash);
+                                                 pending.missing.is_empty().then(|| id.clone())
+                                             })
+                                             .collect();
+                                         for id in ready {
+                                             if let Some(pending) = pending_tasks.remove(&id) {
+                                                 // Another fetch may have pushed an earlier image out; never run without it
+                                                 let images = match blob_store.resolve(&pending.task.images) {
+                                                     Ok(images) => images,
+                                                     Err(e) => {
+                                                         fail_task(&pending.task.id, e, &mut task_records, &store, &event_sender_clone);
+                                                         continue;
+                                                     }
+                                                 };
+                                                 let deadline = job_deadline(&pending.task, config.limits.task_timeout());
+                                                 let job = Job { task: pending.task, images, origin: Origin::Network(pending.author), deadline };
+                                                 if work_queue.has_room(pending_tasks.len()) {
+                                                     work_queue.submit(job);
+                                                 } else {
+                                                     reject_job(job, RuntimeError::Overloaded("worker queue is full".to_string()), &mut task_records, &store, &event_sender_clone);
+                                                 }
+                                             }
+                                         }
+                                         start_jobs(&mut work_queue, &runtime, &local_runtime, &event_sender_clone, &sender);
+                                     }
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Blobs(request_response::Event::OutboundFailure { peer, request_id, error, .. })) => {
+                                 if let Some(hash) = blob_fetches.remove(&request_id) {
+                                     warn!(%peer, "Blob fetch {} failed: {:?}", hash, error);
+                                     fail_pending_tasks(&mut pending_tasks, &hash, &event_sender_clone);
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Blobs(_)) => {},
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Ledger(request_response::Event::Message { peer, message, .. })) => {
+                                 match message {
+                                     request_response::Message::Request { request, channel, .. } => {
+                                         let entries = local_ledger.ledger.entries().iter()
+                                             .skip(request.from_seq as usize)
+                                             .cloned()
+                                             .collect();
+                                         if swarm.behaviour_mut().ledger.send_response(channel, LedgerResponse { entries }).is_err() {
+                                             warn!(%peer, "Failed to send ledger");
+                                         }
+                                     }
+                                     request_response::Message::Response { request_id, response } => {
+                                         if let Some((owner, reply)) = ledger_fetches.remove(&request_id) {
+                                             // Only trust the chain if it verifies against the owner's own key
+                                             let verified = xnet_core::peer_public_key(&owner)
+                                                 .and_then(|key| Ledger::from_entries(response.entries, &key))
+                                                 .map_err(NetworkError::Ledger);
+                                             let _ = reply.send(verified);
+                                         }
+                                     }
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Ledger(request_response::Event::OutboundFailure { request_id, error, .. })) => {
+                                 if let Some((owner, reply)) = ledger_fetches.remove(&request_id) {
+                                     let _ = reply.send(Err(NetworkError::LedgerFetch { peer: owner, message: format!("{:?}", error) }));
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Ledger(_)) => {},
+                             libp2p::swarm::SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. } => {
+                                 // Remember where we reached the peer (only dialled addresses are reachable ones)
+                                 let address = endpoint.is_dialer().then(|| endpoint.get_remote_address().to_string());
+                                 update_peer(&store, &peer_id, address, 1);
+
+                                 let peer = peer_table.connected(peer_id, endpoint.get_remote_address(), num_established.get());
+                                 let _ = event_sender_clone.send(NetworkEvent::PeerUpdated(peer.clone()));
+                                 if num_established.get() == 1 {
+                                     let _ = event_sender_clone.send(NetworkEvent::PeerConnected(peer_id.to_string()));
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::ConnectionClosed { peer_id, num_established, .. } => {
+                                 if let Some(peer) = peer_table.disconnected(peer_id, num_established) {
+                                     let _ = event_sender_clone.send(NetworkEvent::PeerUpdated(peer.clone()));
+                                 }
+                                 if num_established == 0 {
+                                     peer_capabilities.remove(&peer_id.to_string());
+                                     let _ = event_sender_clone.send(NetworkEvent::PeerDisconnected(peer_id.to_string()));
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), .. } => {
+                                 update_peer(&store, &peer_id, None, -1);
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Identify(identify::Event::Received { peer_id, info, .. })) => {
+                                 let peer = peer_table.identified(peer_id, &info);
+                                 if peer.xnet_version().is_none() {
+                                     debug!(peer = %peer_id, "Not an xNet node ({} {})", info.protocol_version, info.agent_version);
+                                 }
+                                 let _ = event_sender_clone.send(NetworkEvent::PeerUpdated(peer.clone()));
+                                 for addr in &info.listen_addrs {
+                                     swarm.behaviour_mut().kad.add_address(&peer_id, addr.clone());
+                                 }
+                                 let is_relay = info.protocols.contains(&relay::HOP_PROTOCOL_NAME);
+                                 let direct_addr = info.listen_addrs.iter().find(|a| !a.iter().any(|p| p == Protocol::P2pCircuit));
+                                 if let (true, Some(addr)) = (is_relay, direct_addr) {
+                                     relay_candidates.insert(peer_id, addr.clone());
+                                     if publicly_reachable == Some(false) {
+                                         reserve_relays(&mut swarm, &relay_candidates, &mut relay_reservations, config.nat.max_relay_reservations);
+                                     }
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Ping(ping::Event { peer, result: Ok(rtt), .. })) => {
+                                 if let Some(peer) = peer_table.pinged(peer, rtt) {
+                                     let _ = event_sender_clone.send(NetworkEvent::PeerUpdated(peer.clone()));
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Autonat(autonat::Event::StatusChanged { old, new })) => {
+                                 info!("NAT status changed from {:?} to {:?}", old, new);
+                                 let _ = event_sender_clone.send(NetworkEvent::Message(format!("NAT status: {:?}", new)));
+                                 match new {
+                                     autonat::NatStatus::Public(_) => {
+                                         // Reachable directly, relay slots are no longer needed
+                                         publicly_reachable = Some(true);
+                                         for (_, listener) in relay_reservations.drain() {
+                                             swarm.remove_listener(listener);
+                                         }
+                                     }
+                                     autonat::NatStatus::Private => {
+                                         publicly_reachable = Some(false);
+                                         reserve_relays(&mut swarm, &relay_candidates, &mut relay_reservations, config.nat.max_relay_reservations);
+                                     }
+                                     autonat::NatStatus::Unknown => {}
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::RelayClient(relay::client::Event::ReservationReqAccepted { relay_peer_id, renewal: false, .. })) => {
+                                 info!("Reserved a relay slot at {}", relay_peer_id);
+                                 let _ = event_sender_clone.send(NetworkEvent::Message(format!("Reachable through relay {}", relay_peer_id)));
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Relay(relay::Event::ReservationReqAccepted { src_peer_id, .. })) => {
+                                 debug!("Relaying for {}", src_peer_id);
+                             },
+                             libp2p::swarm::SwarmEvent::Behaviour(RhizomeBehaviourEvent::Dcutr(dcutr::Event { remote_peer_id, result })) => {
+                                 match result {
+                                     Ok(_) => info!(peer = %remote_peer_id, "Hole punch succeeded, connection is now direct"),
+                                     Err(e) => debug!(peer = %remote_peer_id, "Hole punch failed: {}", e),
+                                 }
+                             },
+                             libp2p::swarm::SwarmEvent::ExternalAddrConfirmed { address } => {
+                                 info!("Confirmed external address {}", address);
+                             },
+                             libp2p::swarm::SwarmEvent::ListenerClosed { listener_id, addresses, reason } => {
+                                 // Every listener gave up before it had an address
+                                 if ready.is_some() && addresses.is_empty()
+                                     && let Some(addr) = listeners.remove(&listener_id)
+                                     && listeners.is_empty()
+                                     && let Some(ready) = ready.take()
+                                 {
+                                     let message = reason.err().map_or("listener closed".to_string(), |e| e.to_string());
+                                     let _ = ready.send(Err(NetworkError::Listen { addr, message }));
+                                 }
+                                 // A lost relay slot can be taken again elsewhere
+                                 relay_reservations.retain(|_, id| *id != listener_id);
+                                 if publicly_reachable == Some(false) {
+                                     reserve_relays(&mut swarm, &relay_candidates, &mut relay_reservations, config.nat.max_relay_reservations);
+                                 }
+                             },
                              _ => {}
                         }
                     }
                     command = receiver.recv() => {
                         match command {
-                            Some(Command::PublishTask(task)) => {
-                                if let Ok(data) = serde_json::to_vec(&task) {
-                                     let topic = gossipsub::IdentTopic::new("xnet/tasks/v1");
-                                     if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, data) {
-                                         println!("Publish error: {:?}", e);
-                                     }
+                            Some(Command::PublishTask { mut task, reply, result }) => {
+                                // Don't broadcast embedding or vision jobs nobody can serve
+                                let required = task.required_capabilities();
+                                if required.iter().any(|c| *c != ModelCapability::Completion) {
+                                    let served = peer_capabilities.values().flat_map(|(models, _)| models).any(|m| {
+                                        m.name == task.model_name && required.iter().all(|c| m.supports(*c))
+                                    });
+                                    if !served {
+                                        let _ = reply.send(Err(NetworkError::NoProvider { model: task.model_name.clone(), required }));
+                                        continue;
+                                    }
+                                }
+                                if let Some(blob) = task.images.iter().find(|b| !blob_store.contains(&b.hash)) {
+                                    let _ = reply.send(Err(NetworkError::BlobMissing(blob.hash.clone())));
+                                    continue;
+                                }
+
+                                // The bid is reserved until the task settles or expires
+                                let bid = task.max_price.unwrap_or(0.0);
+                                let available = local_ledger.ledger.balance() - reserved_credits;
+                                if !bid.is_finite() || bid < 0.0 {
+                                    let _ = reply.send(Err(NetworkError::InvalidPrice(bid)));
+                                    continue;
+                                }
+                                if bid > available {
+                                    let _ = reply.send(Err(NetworkError::Ledger(LedgerError::InsufficientCredits { balance: available, required: bid })));
+                                    continue;
+                                }
+                                task.requester = Some(local_id.clone());
+                                task.created_at = xnet_core::unix_timestamp();
+
+                                // Workers drop the task once we stop waiting for it
+                                let timeout_at = task.created_at + RESULT_TIMEOUT.as_secs();
+                                let deadline = task.deadline.map_or(timeout_at, |d| d.min(timeout_at));
+                                if deadline <= task.created_at {
+                                    let _ = reply.send(Err(NetworkError::DeadlinePassed));
+                                    continue;
+                                }
+                                task.deadline = Some(deadline);
+
+                                let published = match serde_json::to_vec(&task) {
+                                    Ok(data) => {
+                                        let topic = gossipsub::IdentTopic::new(&topics.tasks);
+                                        swarm.behaviour_mut().gossipsub.publish(topic, data)
+                                            .map(|_| ())
+                                            .map_err(|e| NetworkError::Publish(e.to_string()))
+                                    }
+                                    Err(e) => Err(NetworkError::Publish(e.to_string())),
+                                };
+                                match &published {
+                                    Ok(()) => {
+                                        reserved_credits += bid;
+                                        let record = TaskRecord::new(task.clone(), TaskRole::Requester);
+                                        save_task(&store, &record);
+                                        task_records.insert(task.id.clone(), record);
+                                        open_tasks.insert(task.id.clone(), OpenTask {
+                                            max_price: bid,
+                                            published_at: std::time::Instant::now(),
+                                            waiter: result,
+                                        });
+                                    }
+                                    Err(e) => warn!("Publish error: {:?}", e),
                                 }
+                                let _ = reply.send(published);
                             }
                             Some(Command::PublishPipeline(event)) => {
                                 // 1. Publish to Network
                                 if let Ok(data) = serde_json::to_vec(&event) {
-                                    let topic = gossipsub::IdentTopic::new("xnet/pipeline/v1");
+                                    let topic = gossipsub::IdentTopic::new(&topics.pipeline);
                                     if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, data) {
-                                        println!("Publish pipeline error: {:?}", e);
+                                        warn!("Publish pipeline error: {:?}", e);
                                     }
                                 }
                                 
@@ -356,50 +984,71 @@ impl P2PNode {
                                              let _ = swarm_sender.send(Command::PublishPipeline(next_event)).await;
                                          });
                                      },
-                                     PipelineEvent::ForwardPass { session_id, layer_start, tensor: _ } => {
-                                         if layer_start < 30 {
-                                             // Continue the chain
-                                             let next_layer = layer_start + 10;
-                                             let next_event = if next_layer >= 30 {
-                                                 PipelineEvent::Result { 
-                                                     session_id, 
-                                                     token: "Hello xNet!".to_string() 
-                                                 }
-                                             } else {
-                                                 PipelineEvent::ForwardPass {
-                                                     session_id,
-                                                     layer_start: next_layer,
-                                                     tensor: xnet_core::Tensor { shape: vec![1, 4096], data: vec![0.2; 10] }
-                                                 }
-                                             };
-                                             
-                                             // Broadcast after a delay
-                                             let swarm_sender = sender.clone();
-                                             tokio::spawn(async move {
-                                                 tokio::time::sleep(Duration::from_secs(2)).await;
-                                                 let _ = swarm_sender.send(Command::PublishPipeline(next_event)).await;
-                                             });
-                                         }
+                                     PipelineEvent::ForwardPass { session_id, layer_start, tensor: _ } if layer_start < 30 => {
+                                         // Continue the chain
+                                         let next_layer = layer_start + 10;
+                                         let next_event = if next_layer >= 30 {
+                                             PipelineEvent::Result { 
+                                                 session_id, 
+                                                 token: "Hello xNet!".to_string() 
+                                             }
+                                         } else {
+                                             PipelineEvent::ForwardPass {
+                                                 session_id,
+                                                 layer_start: next_layer,
+                                                 tensor: xnet_core::Tensor { shape: vec![1, 4096], data: vec![0.2; 10] }
+                                             }
+                                         };
+                                         
+                                         // Broadcast after a delay
+                                         let swarm_sender = sender.clone();
+                                         tokio::spawn(async move {
+                                             tokio::time::sleep(Duration::from_secs(2)).await;
+                                             let _ = swarm_sender.send(Command::PublishPipeline(next_event)).await;
+                                         });
                                      },
                                      _ => {}
                                  }
                             }
                             Some(Command::PublishVerification(event)) => {
                                 if let Ok(data) = serde_json::to_vec(&event) {
-                                     let topic = gossipsub::IdentTopic::new("xnet/verification/v1");
+                                     let topic = gossipsub::IdentTopic::new(&topics.verification);
                                      if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, data) {
-                                         println!("Publish verification error: {:?}", e);
+                                         warn!("Publish verification error: {:?}", e);
                                      }
                                 }
                                 // Loopback
                                 let _ = event_sender_clone.send(NetworkEvent::VerificationEvent(event.clone()));
+
+                                // A vote checked against our own record of the task is verification work done by this node
+                                if let VerificationEvent::VoteCast(vote) = &event {
+                                    let checked = match &vote.target {
+                                        ChallengeTarget::Receipt { task_id, receipt_hash } => check_receipt(&task_records, task_id, receipt_hash).is_some(),
+                                        ChallengeTarget::Session { .. } => false,
+                                    };
+                                    if vote.voter_id == peer_id.to_string() && checked {
+                                        let target = vote.target.to_string();
+                                        record_credit(&mut local_ledger, &ledger_keys, EntryKind::Earn, accountant.verification(), LedgerEvent::VerificationPerformed { target }, &event_sender_clone);
+                                    }
+                                }
                                 
                                 // Simulation: Respond to Challenge
                                 if let VerificationEvent::ChallengeIssued(challenge) = event {
+                                    // Receipts are only judged when we hold the task and output; otherwise we abstain
+                                    let valid = match &challenge.target {
+                                        ChallengeTarget::Receipt { task_id, receipt_hash } => match check_receipt(&task_records, task_id, receipt_hash) {
+                                            Some(valid) => valid,
+                                            None => {
+                                                debug!(task_id = %task_id, "No local record of the challenged receipt, abstaining");
+                                                continue;
+                                            }
+                                        },
+                                        ChallengeTarget::Session { .. } => true,
+                                    };
                                     let vote = xnet_core::Vote {
-                                        session_id: challenge.target_session_id,
+                                        target: challenge.target,
                                         voter_id: peer_id.to_string(),
-                                        vote: xnet_core::VoteType::Valid,
+                                        vote: if valid { xnet_core::VoteType::Valid } else { xnet_core::VoteType::Invalid },
                                     };
                                     let next_event = VerificationEvent::VoteCast(vote);
                                     let swarm_sender = sender.clone();
@@ -411,61 +1060,716 @@ impl P2PNode {
                             }
                             Some(Command::PublishFL(event)) => {
                                 if let Ok(data) = serde_json::to_vec(&event) {
-                                     let topic = gossipsub::IdentTopic::new("xnet/fl/v1");
+                                     let topic = gossipsub::IdentTopic::new(&topics.fl);
                                      if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, data) {
-                                         println!("Publish FL error: {:?}", e);
+                                         warn!("Publish FL error: {:?}", e);
                                      }
                                 }
                                 // Loopback
                                 let _ = event_sender_clone.send(NetworkEvent::FLEvent(event));
                             }
                             Some(Command::StartProviding) => {
+                                accepting_tasks = true;
                                 let key = kad::RecordKey::new(&b"xnet-provider-v1".to_vec());
-                                println!("Announcing provider capability for xnet-provider-v1");
+                                info!("Announcing provider capability for xnet-provider-v1");
                                 if let Err(e) = swarm.behaviour_mut().kad.start_providing(key) {
-                                    println!("Failed to start providing: {:?}", e);
+                                    warn!("Failed to start providing: {:?}", e);
                                 }
+
+                                // Look up what we can serve, then advertise it from the loop
+                                let runtime_for_models = runtime.clone();
+                                let swarm_sender = sender.clone();
+                                tokio::spawn(async move {
+                                    match runtime_for_models.describe_models().await {
+                                        Ok(models) => {
+                                            let _ = swarm_sender.send(Command::AdvertiseCapabilities(models)).await;
+                                        }
+                                        Err(e) => warn!("Failed to describe local models: {}", e),
+                                    }
+                                });
+                            }
+                            Some(Command::AdvertiseCapabilities(models)) => {
+                                // Stopped providing while the models were being looked up
+                                if !accepting_tasks {
+                                    continue;
+                                }
+                                for model in &models {
+                                    let key = kad::RecordKey::new(&format!("xnet-model-v1/{}", model.name));
+                                    if let Err(e) = swarm.behaviour_mut().kad.start_providing(key) {
+                                        warn!("Failed to provide model {}: {:?}", model.name, e);
+                                    }
+                                }
+                                publish_capabilities(&mut swarm, &topics, &local_id, models.clone());
+                                last_capability_advert = std::time::Instant::now();
+                                local_models = models;
+                            }
+                            Some(Command::StopProviding) => {
+                                accepting_tasks = false;
+                                info!("No longer providing compute");
+                                withdraw_provider(&mut swarm, &mut local_models, &local_id, &topics);
+                            }
+                            Some(Command::Shutdown(done)) => {
+                                if !shutdown.is_empty() {
+                                    shutdown.push(done);
+                                    continue;
+                                }
+                                info!("Shutting down, waiting for {} running tasks", work_queue.running());
+                                accepting_tasks = false;
+                                withdraw_provider(&mut swarm, &mut local_models, &local_id, &topics);
+
+                                // Tasks still waiting on images or a slot won't run anymore
+                                for job in work_queue.drain_queued() {
+                                    reject_job(job, RuntimeError::Overloaded("node is shutting down".to_string()), &mut task_records, &store, &event_sender_clone);
+                                }
+                                for (task_id, _) in pending_tasks.drain() {
+                                    if let Some(record) = task_records.get_mut(&task_id) {
+                                        record.status = TaskStatus::Failed("Node shut down".to_string());
+                                        record.updated_at = xnet_core::unix_timestamp();
+                                        save_task(&store, record);
+                                    }
+                                }
+                                drain_deadline = tokio::time::Instant::now() + SHUTDOWN_DRAIN_TIMEOUT;
+                                shutdown.push(done);
+                            }
+                            Some(Command::TaskFinished { task, requester, output, execution }) => {
+                                // A cancelled task's slot is already free and its record updated
+                                if !work_queue.finished(&task.id) {
+                                    continue;
+                                }
+                                start_jobs(&mut work_queue, &runtime, &local_runtime, &event_sender_clone, &sender);
+                                let output = match output {
+                                    Ok(output) => output,
+                                    Err(e) => {
+                                        if let Some(record) = task_records.get_mut(&task.id) {
+                                            record.status = TaskStatus::Failed(e);
+                                            record.updated_at = xnet_core::unix_timestamp();
+                                            save_task(&store, record);
+                                        }
+                                        continue;
+                                    }
+                                };
+                                metrics.tasks_processed += 1;
+
+                                // Send the result back with a receipt the requester can countersign
+                                let receipt = TaskReceipt {
+                                    task_id: task.id.clone(),
+                                    worker: local_id.clone(),
+                                    requester,
+                                    price: task.max_price.unwrap_or(0.0),
+                                    task_hash: task.hash(),
+                                    prompt_hash: task.input_hash(),
+                                    output_hash: output.hash(),
+                                    model: task.model_name.clone(),
+                                    model_digest: execution.model_digest,
+                                    options: execution.options,
+                                    started_at: execution.started_at,
+                                    finished_at: execution.finished_at,
+                                };
+                                match SignedReceipt::issue(receipt, &ledger_keys) {
+                                    Ok(receipt) => {
+                                        let record = task_records.entry(task.id.clone())
+                                            .or_insert_with(|| TaskRecord::new(task.clone(), TaskRole::Worker));
+                                        record.status = TaskStatus::Completed;
+                                        record.output = Some(output.clone());
+                                        record.receipt = Some(receipt.clone());
+                                        record.updated_at = xnet_core::unix_timestamp();
+                                        save_task(&store, record);
+
+                                        let result = TaskResult { task_id: task.id.clone(), worker: local_id.clone(), output, receipt: receipt.clone() };
+                                        if let Ok(data) = serde_json::to_vec(&result) {
+                                            let topic = gossipsub::IdentTopic::new(&topics.results);
+                                            if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, data) {
+                                                warn!("Publish result error: {:?}", e);
+                                            }
+                                        }
+                                        issued_receipts.insert(task.id, receipt);
+                                    }
+                                    Err(e) => error!(task_id = %task.id, "Failed to issue receipt: {}", e),
+                                }
+                            }
+                            Some(Command::RunLocal(task, images, reply)) => {
+                                if !shutdown.is_empty() {
+                                    let _ = reply.send(Err(NetworkError::Runtime(RuntimeError::Overloaded("node is shutting down".to_string()).into())));
+                                    continue;
+                                }
+                                let deadline = job_deadline(&task, config.limits.task_timeout());
+                                let job = Job { task, images, origin: Origin::Local(reply), deadline };
+                                if work_queue.has_room(pending_tasks.len()) {
+                                    work_queue.submit(job);
+                                } else {
+                                    reject_job(job, RuntimeError::Overloaded("worker queue is full".to_string()), &mut task_records, &store, &event_sender_clone);
+                                }
+                                start_jobs(&mut work_queue, &runtime, &local_runtime, &event_sender_clone, &sender);
+                            }
+                            Some(Command::LocalTaskFinished(task_id)) => {
+                                work_queue.finished(&task_id);
+                                start_jobs(&mut work_queue, &runtime, &local_runtime, &event_sender_clone, &sender);
+                            }
+                            Some(Command::CancelTask(task_id, reply)) => {
+                                if let Some(open) = open_tasks.remove(&task_id) {
+                                    // Dropping the waiter ends `submit_task` for this task
+                                    info!(task_id = %task_id, "Cancelled task");
+                                    reserved_credits -= open.max_price;
+                                    publish_cancel(&mut swarm, &topics, &task_id, &local_id);
+                                    if let Some(record) = task_records.get_mut(&task_id) {
+                                        record.status = TaskStatus::Failed("Cancelled".to_string());
+                                        record.updated_at = xnet_core::unix_timestamp();
+                                        save_task(&store, record);
+                                    }
+                                    let _ = reply.send(Ok(()));
+                                } else if work_queue.cancel(&task_id) {
+                                    // A local API request
+                                    info!(task_id = %task_id, "Cancelled task");
+                                    start_jobs(&mut work_queue, &runtime, &local_runtime, &event_sender_clone, &sender);
+                                    let _ = reply.send(Ok(()));
+                                } else {
+                                    let _ = reply.send(Err(NetworkError::NoOpenTask(task_id)));
+                                }
+                            }
+                            Some(Command::GetLedger(reply)) => {
+                                let _ = reply.send(local_ledger.ledger.entries().to_vec());
+                            }
+                            Some(Command::GetMetrics(reply)) => {
+                                metrics.uptime_seconds = start_time.elapsed().as_secs();
+                                metrics.running_tasks = work_queue.running() as u64;
+                                metrics.queued_tasks = work_queue.queued() as u64;
+                                let _ = reply.send(metrics.clone());
+                            }
+                            Some(Command::GetPeers(reply)) => {
+                                let _ = reply.send(peer_table.list());
+                            }
+                            Some(Command::GetTasks(reply)) => {
+                                let mut records: Vec<TaskRecord> = task_records.values().cloned().collect();
+                                records.sort_by_key(|r| std::cmp::Reverse(r.updated_at));
+                                let _ = reply.send(records);
+                            }
+                            Some(Command::FetchLedger(peer, reply)) => {
+                                let request_id = swarm.behaviour_mut().ledger.send_request(&peer, LedgerRequest { from_seq: 0 });
+                                ledger_fetches.insert(request_id, (peer, reply));
+                            }
+                            Some(Command::PutBlob(data, mime_type, reply)) => {
+                                let result = blob_store.insert(data, mime_type);
+                                if let Ok(blob) = &result {
+                                    let key = kad::RecordKey::new(&format!("xnet-blob-v1/{}", blob.hash));
+                                    if let Err(e) = swarm.behaviour_mut().kad.start_providing(key) {
+                                        warn!("Failed to provide blob {}: {:?}", blob.hash, e);
+                                    }
+                                }
+                                let _ = reply.send(result);
                             }
                             None => break,
                         }
                     }
+                    _ = tokio::time::sleep_until(drain_deadline), if !shutdown.is_empty() => {
+                        warn!("Gave up waiting for {} running tasks", work_queue.running());
+                        break;
+                    }
                 }
             }
-            Ok::<(), anyhow::Error>(())
+
+            // Leave the topics and close connections so peers see us go instead of timing out
+            for topic in topics.all() {
+                swarm.behaviour_mut().gossipsub.unsubscribe(&gossipsub::IdentTopic::new(topic));
+            }
+            let peers: Vec<libp2p::PeerId> = swarm.connected_peers().copied().collect();
+            for peer in peers {
+                let _ = swarm.disconnect_peer_id(peer);
+            }
+            let _ = tokio::time::timeout(SHUTDOWN_CLOSE_TIMEOUT, async {
+                while swarm.connected_peers().next().is_some() {
+                    swarm.select_next_some().await;
+                }
+            }).await;
+            if let Err(e) = store.flush() {
+                error!("Failed to flush the node store: {}", e);
+            }
+            info!("P2P node stopped");
+
+            for done in shutdown {
+                let _ = done.send(());
+            }
         });
 
-        Ok(Self { sender, event_sender })
+        let node = Self { sender, event_sender, task_stamp_bits, peer_id };
+        let failure = match tokio::time::timeout(STARTUP_TIMEOUT, started).await {
+            Ok(Ok(Ok(()))) => return Ok(node),
+            Ok(Ok(Err(e))) => e,
+            Ok(Err(_)) => NetworkError::Stopped,
+            Err(_) => NetworkError::NotListening(STARTUP_TIMEOUT),
+        };
+        // The event loop keeps its own command sender, so it has to be told to stop
+        let _ = node.shutdown().await;
+        Err(failure)
     }
 
     pub fn subscribe(&self) -> broadcast::Receiver<NetworkEvent> {
         self.event_sender.subscribe()
     }
+
+    /// This node's own peer id, the identity it signs receipts and verification events with.
+    pub fn peer_id(&self) -> libp2p::PeerId {
+        self.peer_id
+    }
+
+    /// Mints the proof-of-work stamp the network asks for, off the async runtime.
+    async fn stamp(&self, mut task: InferenceTask) -> Result<InferenceTask, DynError> {
+        let bits = self.task_stamp_bits;
+        if bits == 0 {
+            return Ok(task);
+        }
+        tokio::task::spawn_blocking(move || {
+            task.mint_stamp(bits);
+            task
+        }).await.map_err(|e| Box::new(e) as DynError)
+    }
+
+    /// Stores a blob (e.g. an image) locally so tasks can reference it by hash.
+    /// Workers fetch it from this node when they pick up the task.
+    pub async fn put_blob(&self, data: Vec<u8>, mime_type: Option<String>) -> Result<BlobRef, NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Command::PutBlob(data, mime_type, reply)).await
+            .map_err(stopped)?;
+        response.await.map_err(stopped)?
+    }
+
+    /// This node's own credit ledger entries.
+    pub async fn ledger(&self) -> Result<Vec<SignedEntry>, NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Command::GetLedger(reply)).await
+            .map_err(stopped)?;
+        response.await.map_err(stopped)
+    }
+
+    /// Withdraws the provider and model announcements and stops taking network tasks,
+    /// until `announce_provider` is called again.
+    pub async fn stop_providing(&self) -> Result<(), NetworkError> {
+        self.sender.send(Command::StopProviding).await
+            .map_err(stopped)
+    }
+
+    /// Runs a task on this node's own model, ahead of queued network tasks. Fails when the
+    /// work queue is full or the deadline (the task's, or `limits.task_timeout_secs`) passes.
+    pub async fn run_local(&self, task: InferenceTask, images: Vec<Vec<u8>>) -> Result<TaskOutput, NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Command::RunLocal(task, images, reply)).await
+            .map_err(stopped)?;
+        // The reply is dropped when the task is cancelled or the node stops
+        response.await.map_err(|_| NetworkError::Cancelled)?
+    }
+
+    /// Cancels a task this node published (workers are told to stop) or a local request.
+    pub async fn cancel_task(&self, task_id: &str) -> Result<(), NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Command::CancelTask(task_id.to_string(), reply)).await
+            .map_err(stopped)?;
+        response.await.map_err(stopped)?
+    }
+
+    pub async fn metrics(&self) -> Result<NodeMetrics, NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Command::GetMetrics(reply)).await
+            .map_err(stopped)?;
+        response.await.map_err(stopped)
+    }
+
+    /// The peer table: every peer we have been connected to, connected ones first, with its
+    /// addresses, identify info and last ping RTT.
+    pub async fn peers(&self) -> Result<Vec<PeerInfo>, NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Command::GetPeers(reply)).await
+            .map_err(stopped)?;
+        response.await.map_err(stopped)
+    }
+
+    /// Tasks this node published or worked on, most recently updated first. Each completed
+    /// record carries the signed receipt, checkable offline with `SignedReceipt::verify_against`.
+    pub async fn tasks(&self) -> Result<Vec<TaskRecord>, NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Command::GetTasks(reply)).await
+            .map_err(stopped)?;
+        response.await.map_err(stopped)
+    }
+
+    /// Stops taking work, waits for running tasks (up to a minute), withdraws the provider
+    /// announcement, leaves all topics and closes connections. Resolves once the node has stopped.
+    pub async fn shutdown(&self) -> Result<(), NetworkError> {
+        let (reply, done) = oneshot::channel();
+        if self.sender.send(Command::Shutdown(reply)).await.is_err() {
+            return Ok(()); // Already stopped
+        }
+        done.await.map_err(stopped)
+    }
+
+    /// Fetches a peer's ledger and verifies the whole chain against its peer id.
+    pub async fn fetch_ledger(&self, peer: libp2p::PeerId) -> Result<Ledger, NetworkError> {
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Command::FetchLedger(peer, reply)).await
+            .map_err(stopped)?;
+        response.await.map_err(stopped)?
+    }
+}
+
+/// All of the node's protocols. `relay_client` comes from the swarm builder, which wires it into the transport.
+fn new_behaviour(key: &identity::Keypair, relay_client: relay::client::Behaviour, config: &NodeConfig, store: &Store) -> Result<RhizomeBehaviour, Box<dyn std::error::Error + Send + Sync>> {
+    let peer_id = key.public().to_peer_id();
+
+    // Gossipsub config
+    let message_id_fn = |message: &gossipsub::Message| {
+        let mut s = DefaultHasher::new();
+        message.data.hash(&mut s);
+        gossipsub::MessageId::from(s.finish().to_string())
+    };
+    let gossipsub_config = gossipsub::ConfigBuilder::default()
+        .heartbeat_interval(config.gossipsub_heartbeat())
+        .validation_mode(gossipsub::ValidationMode::Strict)
+        .validate_messages() // Forward only what `validation` accepts
+        .message_id_fn(message_id_fn)
+        .max_transmit_size(256 * 1024) // Increase max size for Tensors
+        .build()
+        .map_err(std::io::Error::other)?;
+
+    let mut gossipsub = gossipsub::Behaviour::new(
+        gossipsub::MessageAuthenticity::Signed(key.clone()),
+        gossipsub_config,
+    )?;
+
+    // Peer scoring, only to punish invalid messages: our topics are too quiet to score delivery rates
+    let mut score_params = gossipsub::PeerScoreParams::default();
+    for topic in config.network_topics().all() {
+        let params = gossipsub::TopicScoreParams {
+            mesh_message_deliveries_weight: 0.0,
+            mesh_failure_penalty_weight: 0.0,
+            invalid_message_deliveries_weight: -10.0,
+            ..Default::default()
+        };
+        score_params.topics.insert(gossipsub::IdentTopic::new(topic).hash(), params);
+    }
+    gossipsub.with_peer_score(score_params, gossipsub::PeerScoreThresholds::default())?;
+
+    // mDNS config
+    let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), peer_id)?;
+
+    // Kademlia (DHT) config, with records and provider announcements kept on disk
+    let kad_store = PersistentStore::open(peer_id, store.clone());
+    let mut kad_config = kad::Config::default();
+    kad_config
+        .set_record_ttl(Some(dht::RECORD_TTL))
+        .set_publication_interval(Some(dht::RECORD_PUBLICATION_INTERVAL))
+        .set_provider_record_ttl(Some(dht::PROVIDER_RECORD_TTL))
+        .set_provider_publication_interval(Some(dht::PROVIDER_PUBLICATION_INTERVAL));
+    let kad = kad::Behaviour::with_config(peer_id, kad_store, kad_config);
+
+    // Blob exchange for task attachments
+    let blobs = blobs::new_behaviour();
+
+    // Ledger sync so peers can audit each other's credits
+    let ledger = ledger::new_behaviour();
+
+    // NAT traversal
+    let identify = identify::Behaviour::new(
+        identify::Config::new(IDENTIFY_PROTOCOL_VERSION.to_string(), key.public())
+            .with_agent_version(format!("xnet/{}", env!("CARGO_PKG_VERSION"))),
+    );
+    let autonat = autonat::Behaviour::new(peer_id, autonat::Config {
+        only_global_ips: config.nat.only_global_ips,
+        ..Default::default()
+    });
+    let relay = config.nat.relay_server
+        .then(|| relay::Behaviour::new(peer_id, relay::Config::default()))
+        .into();
+    let dcutr = dcutr::Behaviour::new(peer_id);
+
+    // Latency for the peer table
+    let ping = ping::Behaviour::new(ping::Config::new());
+
+    Ok(RhizomeBehaviour { gossipsub, mdns, kad, blobs, ledger, ping, identify, autonat, relay, relay_client, dcutr })
+}
+
+fn record_credit(local_ledger: &mut LocalLedger, keys: &identity::Keypair, kind: EntryKind, amount: f64, event: LedgerEvent, event_sender: &broadcast::Sender<NetworkEvent>) {
+    match local_ledger.record(keys, kind, amount, event) {
+        Ok(entry) => {
+            let _ = event_sender.send(NetworkEvent::LedgerEntryAdded(entry));
+        }
+        Err(e) => error!("Failed to record ledger entry: {}", e),
+    }
+}
+
+fn spawn_inference(runtime: OllamaRuntime, task: InferenceTask, requester: libp2p::PeerId, images: Vec<Vec<u8>>, deadline: tokio::time::Instant, event_sender: broadcast::Sender<NetworkEvent>, command_sender: mpsc::Sender<Command>) -> AbortHandle {
+    // Perform real inference using Ollama
+    let span = info_span!("task", task_id = %task.id, model = %task.model_name, requester = %requester);
+    tokio::spawn(async move {
+        // The receipt names the exact model build that ran, not just its tag
+        let model_digest = match runtime.list_model_info().await {
+            Ok(models) => models.into_iter()
+                .find(|m| m.name == task.model_name || m.name == format!("{}:latest", task.model_name))
+                .map(|m| m.digest)
+                .unwrap_or_default(),
+            Err(_) => String::new(),
+        };
+        let options = runtime.effective_options(&task.options);
+
+        let started_at = xnet_core::unix_timestamp_millis();
+        let result = tokio::time::timeout_at(deadline, runtime.run_task(&task, &images)).await
+            .unwrap_or_else(|_| Err(RuntimeError::Timeout("task deadline passed".to_string()).into()));
+        let execution = Execution { model_digest, options, started_at, finished_at: xnet_core::unix_timestamp_millis() };
+        let _ = command_sender.send(Command::TaskFinished {
+            task: task.clone(),
+            requester: requester.to_string(),
+            output: result.as_ref().cloned().map_err(|e| e.to_string()),
+            execution,
+        }).await;
+        match result {
+            Ok(output) => {
+                let response = output.to_string();
+                let preview = if response.chars().count() > 50 {
+                    format!("{}...", response.chars().take(50).collect::<String>())
+                } else {
+                    response.clone()
+                };
+                info!("Task completed: {}", preview);
+                let msg = format!("[AI Response] {}", response);
+                let _ = event_sender.send(NetworkEvent::Message(msg));
+            },
+            Err(e) => {
+                warn!("Task failed: {}", e);
+                let msg = format!("[AI Error] {}", e);
+                let _ = event_sender.send(NetworkEvent::Message(msg));
+            }
+        }
+    }.instrument(span)).abort_handle()
+}
+
+fn spawn_local_inference(runtime: OllamaRuntime, task: InferenceTask, images: Vec<Vec<u8>>, deadline: tokio::time::Instant, mut reply: oneshot::Sender<Result<TaskOutput, NetworkError>>, command_sender: mpsc::Sender<Command>) -> AbortHandle {
+    let span = info_span!("task", task_id = %task.id, model = %task.model_name, local = true);
+    tokio::spawn(async move {
+        let result = tokio::select! {
+            result = tokio::time::timeout_at(deadline, runtime.run_task(&task, &images)) => {
+                Some(result.unwrap_or_else(|_| Err(RuntimeError::Timeout("task deadline passed".to_string()).into())))
+            }
+            // The API client went away
+            _ = reply.closed() => None,
+        };
+        match result {
+            Some(result) => {
+                let _ = reply.send(result.map_err(NetworkError::Runtime));
+            }
+            None => info!("Task cancelled by the client"),
+        }
+        let _ = command_sender.send(Command::LocalTaskFinished(task.id)).await;
+    }.instrument(span)).abort_handle()
+}
+
+/// Checks a challenged receipt against our own record of the task. `None` when we don't hold
+/// both the receipt and the output, so have nothing to judge it by.
+fn check_receipt(task_records: &HashMap<String, TaskRecord>, task_id: &str, receipt_hash: &str) -> Option<bool> {
+    let record = task_records.get(task_id)?;
+    match (&record.receipt, &record.output) {
+        (Some(receipt), Some(output)) => Some(receipt.hash() == receipt_hash && receipt.verify_against(&record.task, output).is_ok()),
+        _ => None,
+    }
+}
+
+/// Starts queued jobs while inference slots are free.
+fn start_jobs(work_queue: &mut WorkQueue, runtime: &OllamaRuntime, local_runtime: &OllamaRuntime, event_sender: &broadcast::Sender<NetworkEvent>, command_sender: &mpsc::Sender<Command>) {
+    while let Some(job) = work_queue.next() {
+        let task_id = job.task.id.clone();
+        let handle = match job.origin {
+            Origin::Network(author) => spawn_inference(runtime.clone(), job.task, author, job.images, job.deadline, event_sender.clone(), command_sender.clone()),
+            Origin::Local(reply) => spawn_local_inference(local_runtime.clone(), job.task, job.images, job.deadline, reply, command_sender.clone()),
+        };
+        work_queue.started(task_id, handle);
+    }
+}
+
+/// Turns a job away: network tasks are marked failed, local requests get the reason back.
+fn reject_job(job: Job, reason: RuntimeError, task_records: &mut HashMap<String, TaskRecord>, store: &Store, event_sender: &broadcast::Sender<NetworkEvent>) {
+    match job.origin {
+        Origin::Network(_) => fail_task(&job.task.id, reason, task_records, store, event_sender),
+        Origin::Local(reply) => {
+            let _ = reply.send(Err(NetworkError::Runtime(reason.into())));
+        }
+    }
+}
+
+/// Marks a network task this node took on as failed, without running it.
+fn fail_task(task_id: &str, reason: impl std::fmt::Display, task_records: &mut HashMap<String, TaskRecord>, store: &Store, event_sender: &broadcast::Sender<NetworkEvent>) {
+    warn!(task_id = %task_id, "Task rejected: {}", reason);
+    if let Some(record) = task_records.get_mut(task_id) {
+        record.status = TaskStatus::Failed(reason.to_string());
+        record.updated_at = xnet_core::unix_timestamp();
+        save_task(store, record);
+    }
+    let _ = event_sender.send(NetworkEvent::Message(format!("[AI Error] Task {} rejected: {}", task_id, reason)));
+}
+
+/// When a job must be done by: the node's task timeout, or the task's own deadline if sooner.
+fn job_deadline(task: &InferenceTask, timeout: Duration) -> tokio::time::Instant {
+    let now = tokio::time::Instant::now();
+    let remaining = task.deadline
+        .map(|at| Duration::from_secs(at.saturating_sub(xnet_core::unix_timestamp())))
+        .map_or(timeout, |left| left.min(timeout));
+    now + remaining
+}
+
+fn publish_cancel(swarm: &mut libp2p::Swarm<RhizomeBehaviour>, topics: &Topics, task_id: &str, local_id: &str) {
+    let cancel = TaskCancel { task_id: task_id.to_string(), requester: local_id.to_string() };
+    if let Ok(data) = serde_json::to_vec(&cancel) {
+        let topic = gossipsub::IdentTopic::new(&topics.cancellations);
+        if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, data) {
+            warn!("Publish cancellation error: {:?}", e);
+        }
+    }
+}
+
+/// Whether `requester` has countersigned a receipt for work this node did.
+fn has_reputation(task_records: &HashMap<String, TaskRecord>, requester: Option<&str>) -> bool {
+    let Some(requester) = requester else { return false };
+    task_records.values().any(|r| {
+        r.role == TaskRole::Worker
+            && r.receipt.as_ref().is_some_and(|receipt| receipt.receipt.requester == requester && receipt.requester_signature.is_some())
+    })
+}
+
+/// Listens through known relays until `max` slots are reserved.
+fn reserve_relays(swarm: &mut libp2p::Swarm<RhizomeBehaviour>, candidates: &HashMap<libp2p::PeerId, Multiaddr>, reservations: &mut HashMap<libp2p::PeerId, ListenerId>, max: usize) {
+    for (relay_id, addr) in candidates {
+        if reservations.len() >= max {
+            break;
+        }
+        if reservations.contains_key(relay_id) {
+            continue;
+        }
+        let mut circuit = addr.clone();
+        if !matches!(circuit.iter().last(), Some(Protocol::P2p(_))) {
+            circuit.push(Protocol::P2p(*relay_id));
+        }
+        circuit.push(Protocol::P2pCircuit);
+        match swarm.listen_on(circuit.clone()) {
+            Ok(listener) => {
+                info!("Listening through relay {}", circuit);
+                reservations.insert(*relay_id, listener);
+            }
+            Err(e) => warn!("Failed to listen through relay {}: {:?}", relay_id, e),
+        }
+    }
+}
+
+/// Withdraws the provider and model announcements. An empty capability advertisement tells
+/// requesters to stop routing work here.
+fn withdraw_provider(swarm: &mut libp2p::Swarm<RhizomeBehaviour>, local_models: &mut Vec<ModelInfo>, local_id: &str, topics: &Topics) {
+    swarm.behaviour_mut().kad.stop_providing(&kad::RecordKey::new(&b"xnet-provider-v1".to_vec()));
+    for model in local_models.drain(..) {
+        swarm.behaviour_mut().kad.stop_providing(&kad::RecordKey::new(&format!("xnet-model-v1/{}", model.name)));
+    }
+    publish_capabilities(swarm, topics, local_id, Vec::new());
+}
+
+fn publish_capabilities(swarm: &mut libp2p::Swarm<RhizomeBehaviour>, topics: &Topics, local_id: &str, models: Vec<ModelInfo>) {
+    let advert = CapabilityAdvertisement { peer_id: local_id.to_string(), models, issued_at: xnet_core::unix_timestamp_millis() };
+    if let Ok(data) = serde_json::to_vec(&advert) {
+        let topic = gossipsub::IdentTopic::new(&topics.capabilities);
+        if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, data) {
+            warn!("Publish capabilities error: {:?}", e);
+        }
+    }
+}
+
+/// Persists a task record and its receipt; storage errors are logged, never fatal to the node.
+fn save_task(store: &Store, record: &TaskRecord) {
+    if let Err(e) = store.put_task(record) {
+        error!(task_id = %record.task.id, "Failed to store task: {}", e);
+    }
+    if let Some(receipt) = &record.receipt
+        && let Err(e) = store.put_receipt(receipt)
+    {
+        error!(task_id = %record.task.id, "Failed to store receipt: {}", e);
+    }
+}
+
+// Addresses kept per peer, most recent first
+const MAX_PEER_ADDRESSES: usize = 8;
+
+fn update_peer(store: &Store, peer_id: &libp2p::PeerId, address: Option<String>, score_delta: i64) {
+    let peer_id = peer_id.to_string();
+    let mut peer = match store.peer(&peer_id) {
+        Ok(Some(peer)) => peer,
+        Ok(None) => PeerRecord { peer_id: peer_id.clone(), addresses: Vec::new(), score: 0, last_seen: 0 },
+        Err(e) => {
+            warn!("Failed to load peer {}: {}", peer_id, e);
+            return;
+        }
+    };
+    if let Some(address) = address {
+        peer.addresses.retain(|a| *a != address);
+        peer.addresses.insert(0, address);
+        peer.addresses.truncate(MAX_PEER_ADDRESSES);
+    }
+    peer.score += score_delta;
+    if score_delta > 0 {
+        peer.last_seen = xnet_core::unix_timestamp();
+    }
+    if let Err(e) = store.put_peer(&peer) {
+        warn!("Failed to store peer {}: {}", peer_id, e);
+    }
+}
+
+/// Drops every pending task that needed a blob we couldn't get.
+fn fail_pending_tasks(pending_tasks: &mut HashMap<String, PendingTask>, hash: &str, event_sender: &broadcast::Sender<NetworkEvent>) {
+    pending_tasks.retain(|id, pending| {
+        if pending.missing.contains(hash) {
+            warn!(task_id = %id, "Task failed: image {} unavailable", hash);
+            let _ = event_sender.send(NetworkEvent::Message(format!("[AI Error] Image {} unavailable", hash)));
+            false
+        } else {
+            true
+        }
+    });
 }
 
 #[async_trait]
 impl NetworkInterface for P2PNode {
     async fn publish_task(&self, task: InferenceTask) -> Result<(), DynError> {
-        self.sender.send(Command::PublishTask(task)).await
-            .map_err(|e| Box::new(e) as DynError)
+        let task = self.stamp(task).await?;
+        let (reply, response) = oneshot::channel();
+        self.sender.send(Command::PublishTask { task, reply, result: None }).await
+            .map_err(stopped)?;
+        response.await.map_err(stopped)??;
+        Ok(())
+    }
+
+    async fn submit_task(&self, task: InferenceTask) -> Result<TaskResult, DynError> {
+        let task = self.stamp(task).await?;
+        let (reply, response) = oneshot::channel();
+        let (result_sender, result) = oneshot::channel();
+        self.sender.send(Command::PublishTask { task, reply, result: Some(result_sender) }).await
+            .map_err(stopped)?;
+        response.await.map_err(stopped)??;
+
+        // The waiter is dropped when the task expires, which ends this wait too
+        result.await.map_err(|_| NetworkError::Cancelled.into())
     }
 
     async fn announce_provider(&self) -> Result<(), DynError> {
         self.sender.send(Command::StartProviding).await
-            .map_err(|e| Box::new(e) as DynError)
+            .map_err(stopped)?;
+        Ok(())
     }
 
     async fn publish_pipeline_event(&self, event: PipelineEvent) -> Result<(), DynError> {
          self.sender.send(Command::PublishPipeline(event)).await
-             .map_err(|e| Box::new(e) as DynError)
+             .map_err(stopped)?;
+        Ok(())
     }
 
     async fn publish_verification_event(&self, event: VerificationEvent) -> Result<(), DynError> {
         self.sender.send(Command::PublishVerification(event)).await
-            .map_err(|e| Box::new(e) as DynError)
+            .map_err(stopped)?;
+        Ok(())
     }
 
     async fn publish_fl_event(&self, event: FLEvent) -> Result<(), DynError> {
         self.sender.send(Command::PublishFL(event)).await
-            .map_err(|e| Box::new(e) as DynError)
+            .map_err(stopped)?;
+        Ok(())
     }
 }
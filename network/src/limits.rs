@@ -0,0 +1,292 @@
+use crate::config::Limits;
+use crate::NetworkError;
+use libp2p::{gossipsub::TopicHash, PeerId};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tokio::task::AbortHandle;
+use xnet_core::{InferenceTask, TaskOutput};
+
+// Spam Protection
+//
+// Gossip is rate limited before validation: every (peer, topic) pair and every topic has a token
+// bucket, and messages over the limit are ignored (not forwarded, not penalised). Local inference
+// runs at most `max_concurrent_tasks` at once; further tasks wait in a bounded queue, local API
+// requests ahead of network tasks, and are turned away once it is full. Every job has a deadline
+// and can be cancelled while it waits or runs.
+
+// Buckets untouched for this long are full again and can be dropped
+const IDLE_BUCKET: Duration = Duration::from_secs(5 * 60);
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: u32, now: Instant) -> Self {
+        Self { tokens: burst as f64, updated: now }
+    }
+
+    fn take(&mut self, rate: f64, burst: u32, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst as f64);
+        self.updated = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+pub struct RateLimiter {
+    limits: Limits,
+    peers: HashMap<(PeerId, TopicHash), TokenBucket>,
+    topics: HashMap<TopicHash, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: Limits) -> Self {
+        Self { limits, peers: HashMap::new(), topics: HashMap::new() }
+    }
+
+    /// Takes a token for a message from `peer` on `topic`. A peer over its own limit doesn't
+    /// use up the topic's budget.
+    pub fn allow(&mut self, peer: PeerId, topic: &TopicHash, now: Instant) -> bool {
+        let limits = &self.limits;
+        let peer_bucket = self.peers.entry((peer, topic.clone()))
+            .or_insert_with(|| TokenBucket::new(limits.peer_burst, now));
+        if !peer_bucket.take(limits.peer_messages_per_sec, limits.peer_burst, now) {
+            return false;
+        }
+        self.topics.entry(topic.clone())
+            .or_insert_with(|| TokenBucket::new(limits.topic_burst, now))
+            .take(limits.topic_messages_per_sec, limits.topic_burst, now)
+    }
+
+    pub fn prune(&mut self, now: Instant) {
+        self.peers.retain(|_, b| now.saturating_duration_since(b.updated) < IDLE_BUCKET);
+        self.topics.retain(|_, b| now.saturating_duration_since(b.updated) < IDLE_BUCKET);
+    }
+}
+
+/// Where a job came from. Local API requests run before network tasks.
+pub enum Origin {
+    /// Gossiped by this peer; the result goes back over the network
+    Network(PeerId),
+    /// From this node's API, answered directly
+    Local(oneshot::Sender<Result<TaskOutput, NetworkError>>),
+}
+
+/// A task accepted for local inference.
+pub struct Job {
+    pub task: InferenceTask,
+    pub images: Vec<Vec<u8>>,
+    pub origin: Origin,
+    pub deadline: tokio::time::Instant,
+}
+
+/// Caps concurrent inference and the number of tasks waiting for a slot.
+pub struct WorkQueue {
+    max_running: usize,
+    max_queued: usize,
+    running: HashMap<String, AbortHandle>,
+    local: VecDeque<Job>,
+    network: VecDeque<Job>,
+}
+
+impl WorkQueue {
+    pub fn new(limits: &Limits) -> Self {
+        Self {
+            max_running: limits.max_concurrent_tasks,
+            max_queued: limits.max_queued_tasks,
+            running: HashMap::new(),
+            local: VecDeque::new(),
+            network: VecDeque::new(),
+        }
+    }
+
+    /// Queues a job; check `has_room` first.
+    pub fn submit(&mut self, job: Job) {
+        match job.origin {
+            Origin::Local(_) => self.local.push_back(job),
+            Origin::Network(_) => self.network.push_back(job),
+        }
+    }
+
+    /// Whether a task arriving now would be taken. `reserved` counts accepted tasks that aren't
+    /// submitted yet (still fetching images), so they can't be crowded out by later arrivals.
+    pub fn has_room(&self, reserved: usize) -> bool {
+        self.running.len() + self.queued() + reserved < self.max_running + self.max_queued
+    }
+
+    /// The next job to start if a slot is free; pass its handle to `started`.
+    pub fn next(&mut self) -> Option<Job> {
+        if self.running.len() >= self.max_running {
+            return None;
+        }
+        self.local.pop_front().or_else(|| self.network.pop_front())
+    }
+
+    pub fn started(&mut self, task_id: String, handle: AbortHandle) {
+        self.running.insert(task_id, handle);
+    }
+
+    /// Frees the task's slot; false if it was cancelled in the meantime.
+    pub fn finished(&mut self, task_id: &str) -> bool {
+        self.running.remove(task_id).is_some()
+    }
+
+    /// Aborts a running task or takes a waiting one out of the queue. Returns false if the task
+    /// isn't here.
+    pub fn cancel(&mut self, task_id: &str) -> bool {
+        if let Some(handle) = self.running.remove(task_id) {
+            handle.abort();
+            return true;
+        }
+        let before = self.queued();
+        self.local.retain(|job| job.task.id != task_id);
+        self.network.retain(|job| job.task.id != task_id);
+        self.queued() < before
+    }
+
+    /// Drops waiting jobs whose deadline has passed.
+    pub fn expire(&mut self, now: tokio::time::Instant) -> Vec<Job> {
+        let mut expired = Vec::new();
+        for queue in [&mut self.local, &mut self.network] {
+            let (gone, kept): (VecDeque<Job>, VecDeque<Job>) = queue.drain(..).partition(|job| job.deadline <= now);
+            *queue = kept;
+            expired.extend(gone);
+        }
+        expired
+    }
+
+    pub fn running(&self) -> usize {
+        self.running.len()
+    }
+
+    pub fn queued(&self) -> usize {
+        self.local.len() + self.network.len()
+    }
+
+    /// Removes the waiting jobs, e.g. on shutdown.
+    pub fn drain_queued(&mut self) -> Vec<Job> {
+        self.local.drain(..).chain(self.network.drain(..)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(peer_burst: u32, topic_burst: u32) -> Limits {
+        Limits {
+            peer_messages_per_sec: 2.0,
+            peer_burst,
+            topic_messages_per_sec: 10.0,
+            topic_burst,
+            max_concurrent_tasks: 1,
+            max_queued_tasks: 2,
+            ..Limits::default()
+        }
+    }
+
+    #[test]
+    fn bucket_refills_at_its_rate() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2, now);
+        assert!(bucket.take(2.0, 2, now));
+        assert!(bucket.take(2.0, 2, now));
+        assert!(!bucket.take(2.0, 2, now));
+
+        // Half a second at 2/s is one token, and the bucket never holds more than its burst
+        assert!(bucket.take(2.0, 2, now + Duration::from_millis(500)));
+        assert!(!bucket.take(2.0, 2, now + Duration::from_millis(500)));
+        let later = now + Duration::from_secs(60);
+        assert!(bucket.take(2.0, 2, later));
+        assert!(bucket.take(2.0, 2, later));
+        assert!(!bucket.take(2.0, 2, later));
+    }
+
+    #[test]
+    fn limits_each_peer_and_the_topic() {
+        let now = Instant::now();
+        let topic = TopicHash::from_raw("xnet/tasks/v1");
+        let mut limiter = RateLimiter::new(limits(2, 3));
+        let (flooder, other, third) = (PeerId::random(), PeerId::random(), PeerId::random());
+
+        assert!(limiter.allow(flooder, &topic, now));
+        assert!(limiter.allow(flooder, &topic, now));
+        assert!(!limiter.allow(flooder, &topic, now));
+        // The flooder's rejected messages didn't use up the topic
+        assert!(limiter.allow(other, &topic, now));
+        assert!(!limiter.allow(third, &topic, now));
+        // Other topics have their own buckets
+        assert!(limiter.allow(flooder, &TopicHash::from_raw("xnet/results/v1"), now));
+
+        limiter.prune(now + IDLE_BUCKET);
+        assert!(limiter.peers.is_empty() && limiter.topics.is_empty());
+    }
+
+    fn job(id: &str, origin: Origin, deadline: tokio::time::Instant) -> Job {
+        Job { task: InferenceTask::new(id, "llama3", "Hello"), images: Vec::new(), origin, deadline }
+    }
+
+    fn idle_handle() -> AbortHandle {
+        tokio::spawn(std::future::pending::<()>()).abort_handle()
+    }
+
+    #[tokio::test]
+    async fn runs_local_jobs_first_within_capacity() {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(60);
+        let mut queue = WorkQueue::new(&limits(1, 1));
+        queue.submit(job("net-1", Origin::Network(PeerId::random()), deadline));
+        queue.submit(job("net-2", Origin::Network(PeerId::random()), deadline));
+        assert!(queue.has_room(0));
+        assert!(!queue.has_room(1));
+        let (reply, _rx) = oneshot::channel();
+        queue.submit(job("local", Origin::Local(reply), deadline));
+
+        let first = queue.next().unwrap();
+        assert_eq!(first.task.id, "local");
+        queue.started(first.task.id, idle_handle());
+        // One slot, already taken
+        assert!(queue.next().is_none());
+
+        assert!(queue.finished("local"));
+        assert_eq!(queue.next().unwrap().task.id, "net-1");
+    }
+
+    #[tokio::test]
+    async fn cancels_running_and_queued_jobs() {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(60);
+        let mut queue = WorkQueue::new(&limits(1, 1));
+        queue.submit(job("running", Origin::Network(PeerId::random()), deadline));
+        queue.submit(job("waiting", Origin::Network(PeerId::random()), deadline));
+        let running = queue.next().unwrap();
+        let handle = tokio::spawn(std::future::pending::<()>());
+        queue.started(running.task.id, handle.abort_handle());
+
+        assert!(queue.cancel("running"));
+        assert!(handle.await.unwrap_err().is_cancelled());
+        // Its slot is free and a late finish is not counted
+        assert!(!queue.finished("running"));
+
+        assert!(queue.cancel("waiting"));
+        assert!(!queue.cancel("unknown"));
+        assert_eq!((queue.running(), queue.queued()), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn expires_jobs_past_their_deadline() {
+        let now = tokio::time::Instant::now();
+        let mut queue = WorkQueue::new(&limits(1, 1));
+        queue.submit(job("late", Origin::Network(PeerId::random()), now));
+        queue.submit(job("on-time", Origin::Network(PeerId::random()), now + Duration::from_secs(60)));
+
+        let expired: Vec<String> = queue.expire(now).into_iter().map(|j| j.task.id).collect();
+        assert_eq!(expired, ["late"]);
+        assert_eq!(queue.next().unwrap().task.id, "on-time");
+    }
+}
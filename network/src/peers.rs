@@ -0,0 +1,178 @@
+use libp2p::{identify, Multiaddr, PeerId};
+use std::collections::HashMap;
+use std::time::Duration;
+use xnet_core::{ConnectionState, PeerInfo};
+
+// Disconnected peers are kept for display until the table grows past this
+const MAX_DISCONNECTED_PEERS: usize = 200;
+
+// Addresses remembered per peer
+const MAX_ADDRESSES: usize = 8;
+
+/// Live view of the peers this node has been connected to. Every update returns the changed
+/// entry so the event loop can publish it.
+#[derive(Default)]
+pub struct PeerTable {
+    peers: HashMap<PeerId, PeerInfo>,
+}
+
+impl PeerTable {
+    /// Records a new connection; `connections` is the number now open to the peer.
+    pub fn connected(&mut self, peer_id: PeerId, address: &Multiaddr, connections: u32) -> &PeerInfo {
+        let peer = self.entry(peer_id);
+        peer.state = ConnectionState::Connected;
+        peer.connections = connections;
+        add_address(peer, address);
+        peer
+    }
+
+    /// Records a closed connection; `remaining` is the number still open to the peer.
+    pub fn disconnected(&mut self, peer_id: PeerId, remaining: u32) -> Option<&PeerInfo> {
+        let peer = self.peers.get_mut(&peer_id)?;
+        peer.connections = remaining;
+        if remaining == 0 {
+            peer.state = ConnectionState::Disconnected;
+            peer.rtt_ms = None;
+        }
+        peer.last_seen = xnet_core::unix_timestamp();
+        self.prune();
+        self.peers.get(&peer_id)
+    }
+
+    pub fn identified(&mut self, peer_id: PeerId, info: &identify::Info) -> &PeerInfo {
+        let peer = self.entry(peer_id);
+        peer.agent_version = Some(info.agent_version.clone());
+        peer.protocol_version = Some(info.protocol_version.clone());
+        peer.protocols = info.protocols.iter().map(|p| p.to_string()).collect();
+        for addr in &info.listen_addrs {
+            add_address(peer, addr);
+        }
+        peer
+    }
+
+    pub fn pinged(&mut self, peer_id: PeerId, rtt: Duration) -> Option<&PeerInfo> {
+        let peer = self.peers.get_mut(&peer_id)?;
+        peer.rtt_ms = Some(rtt.as_millis() as u64);
+        peer.last_seen = xnet_core::unix_timestamp();
+        Some(peer)
+    }
+
+    /// Connected peers first, then the most recently seen.
+    pub fn list(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self.peers.values().cloned().collect();
+        peers.sort_by_key(|p| (p.state != ConnectionState::Connected, std::cmp::Reverse(p.last_seen)));
+        peers
+    }
+
+    fn entry(&mut self, peer_id: PeerId) -> &mut PeerInfo {
+        let peer = self.peers.entry(peer_id).or_insert_with(|| PeerInfo {
+            peer_id: peer_id.to_string(),
+            addresses: Vec::new(),
+            agent_version: None,
+            protocol_version: None,
+            protocols: Vec::new(),
+            rtt_ms: None,
+            state: ConnectionState::Disconnected,
+            connections: 0,
+            last_seen: 0,
+        });
+        peer.last_seen = xnet_core::unix_timestamp();
+        peer
+    }
+
+    fn prune(&mut self) {
+        let mut disconnected: Vec<(u64, PeerId)> = self.peers.iter()
+            .filter(|(_, p)| p.state == ConnectionState::Disconnected)
+            .map(|(id, p)| (p.last_seen, *id))
+            .collect();
+        if disconnected.len() <= MAX_DISCONNECTED_PEERS {
+            return;
+        }
+        disconnected.sort();
+        for (_, peer_id) in &disconnected[..disconnected.len() - MAX_DISCONNECTED_PEERS] {
+            self.peers.remove(peer_id);
+        }
+    }
+}
+
+fn add_address(peer: &mut PeerInfo, address: &Multiaddr) {
+    let address = address.to_string();
+    peer.addresses.retain(|a| *a != address);
+    peer.addresses.insert(0, address);
+    peer.addresses.truncate(MAX_ADDRESSES);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> Multiaddr {
+        format!("/ip4/10.0.0.1/tcp/{}", port).parse().unwrap()
+    }
+
+    #[test]
+    fn tracks_rtt_while_connected() {
+        let mut table = PeerTable::default();
+        let peer = PeerId::random();
+        assert!(table.pinged(peer, Duration::from_millis(5)).is_none());
+
+        table.connected(peer, &addr(1), 2);
+        assert_eq!(table.pinged(peer, Duration::from_millis(42)).unwrap().rtt_ms, Some(42));
+        let info = table.disconnected(peer, 1).unwrap();
+        assert_eq!((info.state, info.rtt_ms), (ConnectionState::Connected, Some(42)));
+        let info = table.disconnected(peer, 0).unwrap();
+        assert_eq!((info.state, info.rtt_ms), (ConnectionState::Disconnected, None));
+    }
+
+    #[test]
+    fn lists_connected_then_most_recently_seen() {
+        let mut table = PeerTable::default();
+        let (old, recent, live) = (PeerId::random(), PeerId::random(), PeerId::random());
+        for peer in [old, recent, live] {
+            table.connected(peer, &addr(1), 1);
+        }
+        table.disconnected(old, 0);
+        table.disconnected(recent, 0);
+        table.peers.get_mut(&old).unwrap().last_seen = 10;
+        table.peers.get_mut(&recent).unwrap().last_seen = 20;
+        table.peers.get_mut(&live).unwrap().last_seen = 1;
+
+        let order: Vec<String> = table.list().into_iter().map(|p| p.peer_id).collect();
+        assert_eq!(order, [live.to_string(), recent.to_string(), old.to_string()]);
+    }
+
+    #[test]
+    fn prunes_the_longest_disconnected_peers() {
+        let mut table = PeerTable::default();
+        let live = PeerId::random();
+        table.connected(live, &addr(1), 1);
+        let gone: Vec<PeerId> = (0..=MAX_DISCONNECTED_PEERS).map(|_| PeerId::random()).collect();
+        for (i, peer) in gone.iter().enumerate() {
+            table.connected(*peer, &addr(1), 1);
+            table.peers.get_mut(peer).unwrap().state = ConnectionState::Disconnected;
+            table.peers.get_mut(peer).unwrap().last_seen = i as u64;
+        }
+        // The next disconnect goes over the limit and drops the one seen longest ago
+        let last = PeerId::random();
+        table.connected(last, &addr(1), 1);
+        table.disconnected(last, 0);
+
+        assert_eq!(table.peers.len(), MAX_DISCONNECTED_PEERS + 1);
+        assert!(table.peers.contains_key(&live) && table.peers.contains_key(&last));
+        assert!(!table.peers.contains_key(&gone[0]) && !table.peers.contains_key(&gone[1]));
+        assert!(table.peers.contains_key(&gone[2]));
+    }
+
+    #[test]
+    fn keeps_recent_addresses_first() {
+        let mut table = PeerTable::default();
+        let peer = PeerId::random();
+        for port in 0..=MAX_ADDRESSES as u16 {
+            table.connected(peer, &addr(port), 1);
+        }
+        let info = table.connected(peer, &addr(3), 1);
+        assert_eq!(info.addresses.len(), MAX_ADDRESSES);
+        assert_eq!(info.addresses[0], addr(3).to_string());
+        assert!(!info.addresses.contains(&addr(0).to_string()));
+    }
+}
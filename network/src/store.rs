@@ -0,0 +1,476 @@
+use redb::{Database, ReadableDatabase, ReadableTable, TableDefinition, WriteTransaction};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use xnet_core::{SignedEntry, SignedReceipt, TaskRecord};
+
+// Node Storage
+//
+// One embedded redb database per node (`node.redb` in the data dir) holding everything that
+// should survive a restart. Values are JSON so records stay readable and can gain serde-default
+// fields without a migration; structural changes go through `MIGRATIONS`.
+//
+// Tasks, receipts, peers and DHT records change on nearly every network event, so their writes
+// are buffered and committed together by `flush`, which the node calls on its metrics tick and
+// at shutdown. A crash loses at most that window. Ledger entries are committed immediately.
+
+/// File name of the store inside a node's data dir.
+pub const STORE_FILE: &str = "node.redb";
+
+const META: TableDefinition<&str, u64> = TableDefinition::new("meta");
+const TASKS: TableDefinition<&str, &[u8]> = TableDefinition::new("tasks");
+const RECEIPTS: TableDefinition<&str, &[u8]> = TableDefinition::new("receipts");
+const PEERS: TableDefinition<&str, &[u8]> = TableDefinition::new("peers");
+const LEDGER: TableDefinition<u64, &[u8]> = TableDefinition::new("ledger");
+const DHT_RECORDS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("dht_records");
+const DHT_PROVIDERS: TableDefinition<(&[u8], &str), &[u8]> = TableDefinition::new("dht_providers"); // (key, provider peer id)
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+type Migration = fn(&WriteTransaction) -> Result<(), StoreError>;
+
+/// Schema migrations, applied in order. Entry `i` upgrades the schema from version `i` to `i + 1`.
+const MIGRATIONS: &[Migration] = &[
+    // 1: initial tables
+    |tx| {
+        tx.open_table(TASKS)?;
+        tx.open_table(RECEIPTS)?;
+        tx.open_table(PEERS)?;
+        tx.open_table(LEDGER)?;
+        tx.open_table(DHT_RECORDS)?;
+        Ok(())
+    },
+    // 2: provider records, kept apart from value records since one key has many providers
+    |tx| {
+        tx.open_table(DHT_PROVIDERS)?;
+        Ok(())
+    },
+];
+
+/// A peer we have connected to, kept so we can find it again after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+    /// Successful connections minus failed dials; low scores are dialled last.
+    pub score: i64,
+    pub last_seen: u64, // Unix seconds
+}
+
+/// A Kademlia value record as stored on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DhtRecord {
+    #[serde(with = "serde_bytes")]
+    pub value: Vec<u8>,
+    pub publisher: Option<String>,
+    pub expires_at: Option<u64>, // Unix seconds
+}
+
+/// A Kademlia provider announcement as stored on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DhtProvider {
+    pub provider: String, // Peer id
+    pub addresses: Vec<String>,
+    pub expires_at: Option<u64>, // Unix seconds
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Database(redb::Error),
+    /// A stored value isn't valid JSON for its type
+    Encoding(serde_json::Error),
+    SchemaTooNew { found: u64, supported: u64 },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Database(e) => write!(f, "{}", e),
+            StoreError::Encoding(e) => write!(f, "Corrupt record: {}", e),
+            StoreError::SchemaTooNew { found, supported } => {
+                write!(f, "Store schema version {} is newer than this build supports ({})", found, supported)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+// redb has an error type per operation; they all end up in `redb::Error`
+impl From<redb::DatabaseError> for StoreError {
+    fn from(e: redb::DatabaseError) -> Self {
+        StoreError::Database(e.into())
+    }
+}
+
+impl From<redb::TransactionError> for StoreError {
+    fn from(e: redb::TransactionError) -> Self {
+        StoreError::Database(e.into())
+    }
+}
+
+impl From<redb::TableError> for StoreError {
+    fn from(e: redb::TableError) -> Self {
+        StoreError::Database(e.into())
+    }
+}
+
+impl From<redb::StorageError> for StoreError {
+    fn from(e: redb::StorageError) -> Self {
+        StoreError::Database(e.into())
+    }
+}
+
+impl From<redb::CommitError> for StoreError {
+    fn from(e: redb::CommitError) -> Self {
+        StoreError::Database(e.into())
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Encoding(e)
+    }
+}
+
+/// A buffered write's row; the latest value per row wins.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Row {
+    Task(String),
+    Receipt(String),
+    Peer(String),
+    DhtRecord(Vec<u8>),
+    DhtProvider(Vec<u8>, String),
+}
+
+#[derive(Clone)]
+pub struct Store {
+    db: Arc<Database>,
+    pending: Arc<Mutex<BTreeMap<Row, Option<Vec<u8>>>>>, // `None` removes the row
+}
+
+impl Store {
+    /// Opens (or creates) the database at `path` and brings its schema up to date.
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        Self::init(Database::create(path)?)
+    }
+
+    /// A store that lives only as long as the node, for running without a data dir.
+    pub fn in_memory() -> Result<Self, StoreError> {
+        Self::init(Database::builder().create_with_backend(redb::backends::InMemoryBackend::new())?)
+    }
+
+    fn init(db: Database) -> Result<Self, StoreError> {
+        let store = Self { db: Arc::new(db), pending: Arc::default() };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    pub fn schema_version(&self) -> Result<u64, StoreError> {
+        let tx = self.db.begin_read()?;
+        let version = match tx.open_table(META) {
+            Ok(meta) => meta.get(SCHEMA_VERSION_KEY)?.map(|v| v.value()).unwrap_or(0),
+            Err(redb::TableError::TableDoesNotExist(_)) => 0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(version)
+    }
+
+    fn migrate(&self) -> Result<(), StoreError> {
+        let current = self.schema_version()?;
+        if current > MIGRATIONS.len() as u64 {
+            return Err(StoreError::SchemaTooNew { found: current, supported: MIGRATIONS.len() as u64 });
+        }
+        // Each step commits on its own, so an interrupted upgrade resumes where it stopped
+        for (version, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+            let tx = self.db.begin_write()?;
+            migration(&tx)?;
+            tx.open_table(META)?.insert(SCHEMA_VERSION_KEY, version as u64 + 1)?;
+            tx.commit()?;
+            tracing::info!("Store schema migrated to version {}", version + 1);
+        }
+        Ok(())
+    }
+
+    // Tasks
+
+    pub fn put_task(&self, record: &TaskRecord) -> Result<(), StoreError> {
+        self.buffer(Row::Task(record.task.id.clone()), Some(serde_json::to_vec(record)?));
+        Ok(())
+    }
+
+    pub fn task(&self, task_id: &str) -> Result<Option<TaskRecord>, StoreError> {
+        self.get(TASKS, Row::Task, task_id)
+    }
+
+    pub fn tasks(&self) -> Result<Vec<TaskRecord>, StoreError> {
+        self.all(TASKS)
+    }
+
+    // Receipts, keyed by receipt hash (which a countersignature doesn't change)
+
+    pub fn put_receipt(&self, receipt: &SignedReceipt) -> Result<(), StoreError> {
+        self.buffer(Row::Receipt(receipt.hash()), Some(serde_json::to_vec(receipt)?));
+        Ok(())
+    }
+
+    pub fn receipt(&self, receipt_hash: &str) -> Result<Option<SignedReceipt>, StoreError> {
+        self.get(RECEIPTS, Row::Receipt, receipt_hash)
+    }
+
+    pub fn receipts(&self) -> Result<Vec<SignedReceipt>, StoreError> {
+        self.all(RECEIPTS)
+    }
+
+    // Peers
+
+    pub fn put_peer(&self, peer: &PeerRecord) -> Result<(), StoreError> {
+        self.buffer(Row::Peer(peer.peer_id.clone()), Some(serde_json::to_vec(peer)?));
+        Ok(())
+    }
+
+    pub fn peer(&self, peer_id: &str) -> Result<Option<PeerRecord>, StoreError> {
+        self.get(PEERS, Row::Peer, peer_id)
+    }
+
+    /// Known peers, best score first.
+    pub fn peers(&self) -> Result<Vec<PeerRecord>, StoreError> {
+        let mut peers: Vec<PeerRecord> = self.all(PEERS)?;
+        peers.sort_by(|a, b| b.score.cmp(&a.score).then(b.last_seen.cmp(&a.last_seen)));
+        Ok(peers)
+    }
+
+    // Ledger
+
+    pub fn append_ledger_entry(&self, entry: &SignedEntry) -> Result<(), StoreError> {
+        let tx = self.db.begin_write()?;
+        tx.open_table(LEDGER)?.insert(entry.entry.seq, serde_json::to_vec(entry)?.as_slice())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// All ledger entries, in sequence order.
+    pub fn ledger_entries(&self) -> Result<Vec<SignedEntry>, StoreError> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(LEDGER)?;
+        table.iter()?
+            .map(|row| Ok(serde_json::from_slice(row?.1.value())?))
+            .collect()
+    }
+
+    // DHT records
+
+    pub fn put_dht_record(&self, key: &[u8], record: &DhtRecord) -> Result<(), StoreError> {
+        self.buffer(Row::DhtRecord(key.to_vec()), Some(serde_json::to_vec(record)?));
+        Ok(())
+    }
+
+    pub fn remove_dht_record(&self, key: &[u8]) -> Result<(), StoreError> {
+        self.buffer(Row::DhtRecord(key.to_vec()), None);
+        Ok(())
+    }
+
+    pub fn dht_records(&self) -> Result<Vec<(Vec<u8>, DhtRecord)>, StoreError> {
+        self.flush()?;
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(DHT_RECORDS)?;
+        table.iter()?
+            .map(|row| {
+                let (key, value) = row?;
+                Ok((key.value().to_vec(), serde_json::from_slice(value.value())?))
+            })
+            .collect()
+    }
+
+    pub fn put_dht_provider(&self, key: &[u8], record: &DhtProvider) -> Result<(), StoreError> {
+        self.buffer(Row::DhtProvider(key.to_vec(), record.provider.clone()), Some(serde_json::to_vec(record)?));
+        Ok(())
+    }
+
+    pub fn remove_dht_provider(&self, key: &[u8], provider: &str) -> Result<(), StoreError> {
+        self.buffer(Row::DhtProvider(key.to_vec(), provider.to_string()), None);
+        Ok(())
+    }
+
+    pub fn dht_providers(&self) -> Result<Vec<(Vec<u8>, DhtProvider)>, StoreError> {
+        self.flush()?;
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(DHT_PROVIDERS)?;
+        table.iter()?
+            .map(|row| {
+                let (key, value) = row?;
+                Ok((key.value().0.to_vec(), serde_json::from_slice(value.value())?))
+            })
+            .collect()
+    }
+
+    /// Commits all buffered writes in one transaction. On failure they stay buffered for the next try.
+    pub fn flush(&self) -> Result<(), StoreError> {
+        let mut pending = self.pending.lock().expect("store buffer poisoned");
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let tx = self.db.begin_write()?;
+        {
+            let mut tasks = tx.open_table(TASKS)?;
+            let mut receipts = tx.open_table(RECEIPTS)?;
+            let mut peers = tx.open_table(PEERS)?;
+            let mut records = tx.open_table(DHT_RECORDS)?;
+            let mut providers = tx.open_table(DHT_PROVIDERS)?;
+            for (row, value) in pending.iter() {
+                let value = value.as_deref();
+                match row {
+                    Row::Task(key) => apply(&mut tasks, key.as_str(), value)?,
+                    Row::Receipt(key) => apply(&mut receipts, key.as_str(), value)?,
+                    Row::Peer(key) => apply(&mut peers, key.as_str(), value)?,
+                    Row::DhtRecord(key) => apply(&mut records, key.as_slice(), value)?,
+                    Row::DhtProvider(key, provider) => apply(&mut providers, (key.as_slice(), provider.as_str()), value)?,
+                }
+            }
+        }
+        tx.commit()?;
+        pending.clear();
+        Ok(())
+    }
+
+    fn buffer(&self, row: Row, value: Option<Vec<u8>>) {
+        self.pending.lock().expect("store buffer poisoned").insert(row, value);
+    }
+
+    /// Reads a row, seeing buffered writes before they are flushed.
+    fn get<T: DeserializeOwned>(&self, table: TableDefinition<&str, &[u8]>, row: fn(String) -> Row, key: &str) -> Result<Option<T>, StoreError> {
+        if let Some(value) = self.pending.lock().expect("store buffer poisoned").get(&row(key.to_string())) {
+            return Ok(value.as_deref().map(serde_json::from_slice).transpose()?);
+        }
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(table)?;
+        let value = table.get(key)?;
+        Ok(value.map(|v| serde_json::from_slice(v.value())).transpose()?)
+    }
+
+    fn all<T: DeserializeOwned>(&self, table: TableDefinition<&str, &[u8]>) -> Result<Vec<T>, StoreError> {
+        self.flush()?;
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(table)?;
+        table.iter()?
+            .map(|row| Ok(serde_json::from_slice(row?.1.value())?))
+            .collect()
+    }
+}
+
+fn apply<K: redb::Key + 'static>(table: &mut redb::Table<K, &'static [u8]>, key: K::SelfType<'_>, value: Option<&[u8]>) -> Result<(), StoreError> {
+    match value {
+        Some(value) => table.insert(key, value)?,
+        None => table.remove(key)?,
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use xnet_core::{InferenceTask, TaskRole};
+
+    /// A fresh directory under the system temp dir, removed when dropped.
+    pub(crate) struct TempDir(pub PathBuf);
+
+    impl TempDir {
+        pub(crate) fn new(name: &str) -> Self {
+            static NEXT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+            let n = NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let dir = std::env::temp_dir().join(format!("xnet-{}-{}-{}", name, std::process::id(), n));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn creates_current_schema_and_keeps_data() {
+        let dir = TempDir::new("store");
+        let path = dir.0.join(STORE_FILE);
+        {
+            let store = Store::open(&path).unwrap();
+            assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len() as u64);
+            store.put_task(&TaskRecord::new(InferenceTask::new("t1", "llama3", "Hello"), TaskRole::Requester)).unwrap();
+            store.put_dht_record(b"key", &DhtRecord { value: vec![1, 2], publisher: None, expires_at: None }).unwrap();
+            store.flush().unwrap();
+        }
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.task("t1").unwrap().unwrap().task.model_name, "llama3");
+        assert_eq!(store.dht_records().unwrap()[0].1.value, [1, 2]);
+        assert!(store.task("t2").unwrap().is_none());
+    }
+
+    #[test]
+    fn buffers_writes_until_flushed() {
+        let dir = TempDir::new("store");
+        let path = dir.0.join(STORE_FILE);
+        let peer = |score| PeerRecord { peer_id: "a".to_string(), addresses: Vec::new(), score, last_seen: 0 };
+        {
+            let store = Store::open(&path).unwrap();
+            store.put_peer(&peer(1)).unwrap();
+            store.flush().unwrap();
+            store.put_peer(&peer(2)).unwrap();
+            store.put_dht_record(b"key", &DhtRecord { value: vec![1], publisher: None, expires_at: None }).unwrap();
+            store.remove_dht_record(b"key").unwrap();
+            // Reads see the buffer
+            assert_eq!(store.peer("a").unwrap().unwrap().score, 2);
+        }
+        // Unflushed writes are gone after a restart
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.peer("a").unwrap().unwrap().score, 1);
+        assert!(store.dht_records().unwrap().is_empty());
+    }
+
+    #[test]
+    fn resumes_an_interrupted_migration() {
+        let dir = TempDir::new("store");
+        let path = dir.0.join(STORE_FILE);
+        {
+            // A store left at version 1, before provider records existed
+            let db = Database::create(&path).unwrap();
+            let tx = db.begin_write().unwrap();
+            MIGRATIONS[0](&tx).unwrap();
+            tx.open_table(META).unwrap().insert(SCHEMA_VERSION_KEY, 1).unwrap();
+            tx.commit().unwrap();
+        }
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len() as u64);
+        let provider = DhtProvider { provider: "peer".to_string(), addresses: Vec::new(), expires_at: None };
+        store.put_dht_provider(b"key", &provider).unwrap();
+        assert_eq!(store.dht_providers().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn refuses_a_newer_schema() {
+        let dir = TempDir::new("store");
+        let path = dir.0.join(STORE_FILE);
+        {
+            let store = Store::open(&path).unwrap();
+            let tx = store.db.begin_write().unwrap();
+            tx.open_table(META).unwrap().insert(SCHEMA_VERSION_KEY, 99).unwrap();
+            tx.commit().unwrap();
+        }
+        assert!(matches!(Store::open(&path), Err(StoreError::SchemaTooNew { found: 99, .. })));
+    }
+
+    #[test]
+    fn orders_peers_by_score() {
+        let store = Store::in_memory().unwrap();
+        for (peer_id, score, last_seen) in [("a", 1, 10), ("b", 5, 1), ("c", 1, 20)] {
+            store.put_peer(&PeerRecord { peer_id: peer_id.to_string(), addresses: Vec::new(), score, last_seen }).unwrap();
+        }
+        let order: Vec<String> = store.peers().unwrap().into_iter().map(|p| p.peer_id).collect();
+        assert_eq!(order, ["b", "c", "a"]);
+    }
+}
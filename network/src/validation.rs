@@ -0,0 +1,355 @@
+use crate::config::Topics;
+use libp2p::gossipsub::{self, MessageAcceptance};
+use serde::de::DeserializeOwned;
+use xnet_core::{CapabilityAdvertisement, ChatRequest, FLEvent, InferenceTask, PipelineEvent, SignedReceipt, TaskCancel, TaskKind, TaskOutput, TaskResult, Tensor, VerificationEvent};
+
+// Gossip Validation
+//
+// Gossipsub runs in manual validation mode: nothing is forwarded until the node has decoded the
+// message and checked it here. Malformed or oversized messages are rejected, which counts
+// against the sender's gossipsub score; stale ones are ignored, since an honest peer with a
+// slow clock or a late delivery shouldn't be penalised for them.
+
+// Largest message per topic (gossipsub's own limit is 256 KiB)
+pub(crate) const MAX_TASK_BYTES: usize = 96 * 1024; // Also caps task bodies on the HTTP API
+const MAX_CONTROL_BYTES: usize = 64 * 1024; // Verification, receipts, capabilities
+const MAX_DATA_BYTES: usize = 256 * 1024; // Pipeline tensors, FL gradients, results
+
+// Task limits
+const MAX_PROMPT_BYTES: usize = 32 * 1024;
+const MAX_CHAT_MESSAGES: usize = 256;
+const MAX_STOP_SEQUENCES: usize = 16;
+const MAX_EMBED_INPUTS: usize = 256;
+const MAX_IMAGES: usize = 8;
+const MAX_ID_LEN: usize = 128;
+const MAX_NAME_LEN: usize = 256;
+
+// Pipeline and FL limits
+const MAX_TENSOR_RANK: usize = 4;
+const MAX_TENSOR_ELEMENTS: usize = 1 << 20;
+const MAX_LAYER: usize = 1024;
+const MAX_ADVERTISED_MODELS: usize = 256;
+
+// Messages older than this, or further than the skew in the future, are ignored
+const MAX_MESSAGE_AGE_MS: u64 = 10 * 60 * 1000;
+const MAX_CLOCK_SKEW_MS: u64 = 60 * 1000;
+
+/// A gossip message that passed validation.
+pub enum GossipMessage {
+    Task(InferenceTask),
+    Pipeline(PipelineEvent),
+    Verification(VerificationEvent),
+    FL(FLEvent),
+    Result(TaskResult),
+    Receipt(SignedReceipt),
+    Capabilities(CapabilityAdvertisement),
+    Cancel(TaskCancel),
+}
+
+/// Why a message was dropped, and whether the sender is to blame.
+pub struct Invalid {
+    pub reject: bool,
+    pub reason: String,
+}
+
+impl Invalid {
+    fn reject(reason: impl Into<String>) -> Self {
+        Self { reject: true, reason: reason.into() }
+    }
+
+    fn ignore(reason: impl Into<String>) -> Self {
+        Self { reject: false, reason: reason.into() }
+    }
+
+    pub fn acceptance(&self) -> MessageAcceptance {
+        if self.reject { MessageAcceptance::Reject } else { MessageAcceptance::Ignore }
+    }
+}
+
+/// Decodes `message` for its topic and checks sizes, field limits and timestamps.
+pub fn validate(topics: &Topics, message: &gossipsub::Message, now_ms: u64) -> Result<GossipMessage, Invalid> {
+    let topic = message.topic.as_str();
+    let data = &message.data;
+
+    if topic == topics.tasks {
+        let task: InferenceTask = decode(data, MAX_TASK_BYTES)?;
+        // Reputation and stamps are judged by requester, so it can't be borrowed
+        if task.requester.is_none() || task.requester != message.source.map(|p| p.to_string()) {
+            return Err(Invalid::reject("task requester is not the sender"));
+        }
+        check_task(&task)?;
+        check_age(task.created_at.saturating_mul(1000), now_ms)?;
+        if task.deadline.is_some_and(|d| d.saturating_mul(1000) <= now_ms) {
+            return Err(Invalid::ignore("deadline passed"));
+        }
+        Ok(GossipMessage::Task(task))
+    } else if topic == topics.pipeline {
+        let event: PipelineEvent = decode(data, MAX_DATA_BYTES)?;
+        check_pipeline(&event)?;
+        Ok(GossipMessage::Pipeline(event))
+    } else if topic == topics.verification {
+        let event: VerificationEvent = decode(data, MAX_CONTROL_BYTES)?;
+        // Challenges and votes count for whoever signs them, so only the sender can cast them
+        let claimed = match &event {
+            VerificationEvent::ChallengeIssued(challenge) => Some(&challenge.challenger_id),
+            VerificationEvent::VoteCast(vote) => Some(&vote.voter_id),
+            VerificationEvent::SlashingEnforced { .. } => None,
+        };
+        if let Some(claimed) = claimed
+            && message.source.map(|p| p.to_string()).as_deref() != Some(claimed.as_str())
+        {
+            return Err(Invalid::reject("verification event for another peer"));
+        }
+        Ok(GossipMessage::Verification(event))
+    } else if topic == topics.fl {
+        let event: FLEvent = decode(data, MAX_DATA_BYTES)?;
+        if let FLEvent::LocalUpdate(update) = &event {
+            check_len("gradients", update.gradients.len(), MAX_TENSOR_ELEMENTS)?;
+            if update.gradients.iter().any(|g| !g.is_finite()) {
+                return Err(Invalid::reject("gradients must be finite"));
+            }
+        }
+        Ok(GossipMessage::FL(event))
+    } else if topic == topics.results {
+        let result: TaskResult = decode(data, MAX_DATA_BYTES)?;
+        let receipt = &result.receipt.receipt;
+        if receipt.task_id != result.task_id || receipt.worker != result.worker {
+            return Err(Invalid::reject("receipt does not match the result"));
+        }
+        if let TaskOutput::Embeddings(vectors) = &result.output {
+            check_len("embeddings", vectors.iter().map(Vec::len).sum(), MAX_TENSOR_ELEMENTS)?;
+        }
+        result.receipt.verify_worker().map_err(|e| Invalid::reject(e.to_string()))?;
+        check_age(receipt.finished_at, now_ms)?;
+        Ok(GossipMessage::Result(result))
+    } else if topic == topics.receipts {
+        let receipt: SignedReceipt = decode(data, MAX_CONTROL_BYTES)?;
+        receipt.verify().map_err(|e| Invalid::reject(e.to_string()))?;
+        check_age(receipt.receipt.finished_at, now_ms)?;
+        Ok(GossipMessage::Receipt(receipt))
+    } else if topic == topics.capabilities {
+        let advert: CapabilityAdvertisement = decode(data, MAX_CONTROL_BYTES)?;
+        // Only a provider speaks for itself
+        if message.source.map(|p| p.to_string()).as_deref() != Some(advert.peer_id.as_str()) {
+            return Err(Invalid::reject("advertisement for another peer"));
+        }
+        check_len("models", advert.models.len(), MAX_ADVERTISED_MODELS)?;
+        check_age(advert.issued_at, now_ms)?;
+        Ok(GossipMessage::Capabilities(advert))
+    } else if topic == topics.cancellations {
+        let cancel: TaskCancel = decode(data, MAX_CONTROL_BYTES)?;
+        // Only the requester can call off its task
+        if message.source.map(|p| p.to_string()).as_deref() != Some(cancel.requester.as_str()) {
+            return Err(Invalid::reject("cancellation for another requester"));
+        }
+        check_len("task id", cancel.task_id.len(), MAX_ID_LEN)?;
+        Ok(GossipMessage::Cancel(cancel))
+    } else {
+        Err(Invalid::ignore(format!("unknown topic {}", topic)))
+    }
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8], max_bytes: usize) -> Result<T, Invalid> {
+    if data.len() > max_bytes {
+        return Err(Invalid::reject(format!("{} bytes, at most {} allowed", data.len(), max_bytes)));
+    }
+    serde_json::from_slice(data).map_err(|e| Invalid::reject(format!("malformed: {}", e)))
+}
+
+fn check_task(task: &InferenceTask) -> Result<(), Invalid> {
+    check_len("task id", task.id.len(), MAX_ID_LEN)?;
+    check_len("model name", task.model_name.len(), MAX_NAME_LEN)?;
+    check_len("images", task.images.len(), MAX_IMAGES)?;
+    if task.id.is_empty() || task.model_name.is_empty() {
+        return Err(Invalid::reject("task id and model are required"));
+    }
+    if task.max_price.is_some_and(|p| !p.is_finite() || p < 0.0) {
+        return Err(Invalid::reject("max price must be finite and non-negative"));
+    }
+    let input_bytes = match &task.kind {
+        TaskKind::Generate => task.prompt.len(),
+        TaskKind::Chat(ChatRequest { messages, system, stop }) => {
+            check_len("chat messages", messages.len(), MAX_CHAT_MESSAGES)?;
+            check_len("stop sequences", stop.len(), MAX_STOP_SEQUENCES)?;
+            messages.iter().map(|m| m.content.len()).sum::<usize>() + system.as_ref().map_or(0, String::len)
+        }
+        TaskKind::Embed { input } => {
+            check_len("embed inputs", input.len(), MAX_EMBED_INPUTS)?;
+            input.iter().map(String::len).sum()
+        }
+    };
+    check_len("prompt bytes", input_bytes, MAX_PROMPT_BYTES)
+}
+
+fn check_pipeline(event: &PipelineEvent) -> Result<(), Invalid> {
+    let session_id = match event {
+        PipelineEvent::InitSession { session_id, model } => {
+            check_len("model name", model.len(), MAX_NAME_LEN)?;
+            session_id
+        }
+        PipelineEvent::ForwardPass { session_id, layer_start, tensor } => {
+            check_len("layer", *layer_start, MAX_LAYER)?;
+            check_tensor(tensor)?;
+            session_id
+        }
+        PipelineEvent::Result { session_id, .. } | PipelineEvent::Error { session_id, .. } => session_id,
+    };
+    check_len("session id", session_id.len(), MAX_ID_LEN)
+}
+
+/// The shape must be plausible and hold at least the data that was sent.
+fn check_tensor(tensor: &Tensor) -> Result<(), Invalid> {
+    if tensor.shape.is_empty() || tensor.shape.len() > MAX_TENSOR_RANK || tensor.shape.contains(&0) {
+        return Err(Invalid::reject(format!("bad tensor shape {:?}", tensor.shape)));
+    }
+    let elements = tensor.shape.iter().try_fold(1usize, |n, d| n.checked_mul(*d))
+        .filter(|n| *n <= MAX_TENSOR_ELEMENTS)
+        .ok_or_else(|| Invalid::reject(format!("tensor shape {:?} too large", tensor.shape)))?;
+    if tensor.data.len() > elements {
+        return Err(Invalid::reject(format!("{} values for shape {:?}", tensor.data.len(), tensor.shape)));
+    }
+    if tensor.data.iter().any(|v| !v.is_finite()) {
+        return Err(Invalid::reject("tensor values must be finite"));
+    }
+    Ok(())
+}
+
+fn check_len(field: &str, len: usize, max: usize) -> Result<(), Invalid> {
+    if len > max {
+        return Err(Invalid::reject(format!("{} is {}, at most {} allowed", field, len, max)));
+    }
+    Ok(())
+}
+
+fn check_age(timestamp_ms: u64, now_ms: u64) -> Result<(), Invalid> {
+    if timestamp_ms.saturating_add(MAX_MESSAGE_AGE_MS) < now_ms {
+        return Err(Invalid::ignore(format!("stale, sent {}s ago", (now_ms - timestamp_ms) / 1000)));
+    }
+    if timestamp_ms > now_ms + MAX_CLOCK_SKEW_MS {
+        return Err(Invalid::ignore("timestamp in the future"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::PeerId;
+    use xnet_core::{unix_timestamp, unix_timestamp_millis, Challenge, ChallengeTarget, Tensor, Vote, VoteType};
+
+    fn message(topic: &str, source: PeerId, data: Vec<u8>) -> gossipsub::Message {
+        gossipsub::Message { source: Some(source), data, sequence_number: None, topic: gossipsub::TopicHash::from_raw(topic) }
+    }
+
+    fn invalid(result: Result<GossipMessage, Invalid>) -> Invalid {
+        match result {
+            Ok(_) => panic!("message was accepted"),
+            Err(invalid) => invalid,
+        }
+    }
+
+    fn task_from(sender: PeerId) -> InferenceTask {
+        let mut task = InferenceTask::new("t1", "llama3", "Hello");
+        task.requester = Some(sender.to_string());
+        task
+    }
+
+    fn check_task_message(task: &InferenceTask, sender: PeerId) -> Result<GossipMessage, Invalid> {
+        let topics = Topics::default();
+        validate(&topics, &message(&topics.tasks, sender, serde_json::to_vec(task).unwrap()), unix_timestamp_millis())
+    }
+
+    #[test]
+    fn accepts_valid_task() {
+        let sender = PeerId::random();
+        assert!(matches!(check_task_message(&task_from(sender), sender), Ok(GossipMessage::Task(_))));
+    }
+
+    #[test]
+    fn rejects_malformed_and_oversized() {
+        let topics = Topics::default();
+        let sender = PeerId::random();
+        let now = unix_timestamp_millis();
+
+        let garbage = invalid(validate(&topics, &message(&topics.tasks, sender, b"not json".to_vec()), now));
+        assert!(garbage.reject);
+        let huge = invalid(validate(&topics, &message(&topics.tasks, sender, vec![b' '; MAX_TASK_BYTES + 1]), now));
+        assert!(huge.reject);
+
+        let mut long_prompt = task_from(sender);
+        long_prompt.prompt = "x".repeat(MAX_PROMPT_BYTES + 1);
+        assert!(invalid(check_task_message(&long_prompt, sender)).reject);
+
+        let mut bad_price = task_from(sender);
+        bad_price.max_price = Some(-1.0);
+        assert!(invalid(check_task_message(&bad_price, sender)).reject);
+    }
+
+    #[test]
+    fn rejects_borrowed_identity() {
+        let sender = PeerId::random();
+        let task = task_from(PeerId::random());
+        assert!(invalid(check_task_message(&task, sender)).reject);
+
+        let topics = Topics::default();
+        let cancel = TaskCancel { task_id: "t1".to_string(), requester: PeerId::random().to_string() };
+        let cancelled = invalid(validate(&topics, &message(&topics.cancellations, sender, serde_json::to_vec(&cancel).unwrap()), unix_timestamp_millis()));
+        assert!(cancelled.reject);
+
+        let advert = CapabilityAdvertisement { peer_id: PeerId::random().to_string(), models: Vec::new(), issued_at: unix_timestamp_millis() };
+        let advertised = invalid(validate(&topics, &message(&topics.capabilities, sender, serde_json::to_vec(&advert).unwrap()), unix_timestamp_millis()));
+        assert!(advertised.reject);
+
+        let target = ChallengeTarget::Receipt { task_id: "t1".to_string(), receipt_hash: "abc".to_string() };
+        let verification = |event: &VerificationEvent| {
+            validate(&topics, &message(&topics.verification, sender, serde_json::to_vec(event).unwrap()), unix_timestamp_millis())
+        };
+        let vote = |voter: PeerId| VerificationEvent::VoteCast(Vote { target: target.clone(), voter_id: voter.to_string(), vote: VoteType::Invalid });
+        let challenge = |challenger: PeerId| VerificationEvent::ChallengeIssued(Challenge { target: target.clone(), challenger_id: challenger.to_string() });
+        assert!(invalid(verification(&vote(PeerId::random()))).reject);
+        assert!(invalid(verification(&challenge(PeerId::random()))).reject);
+        assert!(matches!(verification(&vote(sender)), Ok(GossipMessage::Verification(_))));
+        assert!(matches!(verification(&challenge(sender)), Ok(GossipMessage::Verification(_))));
+    }
+
+    #[test]
+    fn ignores_stale_and_expired() {
+        let sender = PeerId::random();
+
+        let mut stale = task_from(sender);
+        stale.created_at = unix_timestamp() - 3600;
+        let ignored = invalid(check_task_message(&stale, sender));
+        assert!(!ignored.reject);
+        assert!(matches!(ignored.acceptance(), MessageAcceptance::Ignore));
+
+        let mut future = task_from(sender);
+        future.created_at = unix_timestamp() + 3600;
+        assert!(!invalid(check_task_message(&future, sender)).reject);
+
+        let expired = task_from(sender).with_deadline(unix_timestamp() - 1);
+        assert!(!invalid(check_task_message(&expired, sender)).reject);
+
+        let topics = Topics::default();
+        let replayed = CapabilityAdvertisement { peer_id: sender.to_string(), models: Vec::new(), issued_at: unix_timestamp_millis() - 3_600_000 };
+        let replayed = invalid(validate(&topics, &message(&topics.capabilities, sender, serde_json::to_vec(&replayed).unwrap()), unix_timestamp_millis()));
+        assert!(!replayed.reject);
+
+        let unknown = invalid(validate(&topics, &message("xnet/other/v1", sender, b"{}".to_vec()), unix_timestamp_millis()));
+        assert!(!unknown.reject);
+    }
+
+    #[test]
+    fn checks_tensors() {
+        let topics = Topics::default();
+        let sender = PeerId::random();
+        let pass = |shape: Vec<usize>, data: Vec<f32>| {
+            let event = PipelineEvent::ForwardPass { session_id: "s1".to_string(), layer_start: 0, tensor: Tensor { shape, data } };
+            validate(&topics, &message(&topics.pipeline, sender, serde_json::to_vec(&event).unwrap()), unix_timestamp_millis())
+        };
+
+        assert!(pass(vec![1, 4], vec![0.1; 4]).is_ok());
+        assert!(invalid(pass(vec![1, 4], vec![0.1; 5])).reject);
+        assert!(invalid(pass(vec![0], Vec::new())).reject);
+        assert!(invalid(pass(vec![usize::MAX, 2], Vec::new())).reject);
+    }
+}
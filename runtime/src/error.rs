@@ -0,0 +1,105 @@
+use std::fmt;
+
+/// Why a runtime call failed. `RuntimeInterface` returns these boxed in a `DynError`; use
+/// `downcast_ref::<RuntimeError>()` to tell them apart.
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// The model isn't installed
+    ModelNotFound(String),
+    /// Ollama (or this node's work queue) is busy; worth trying again later
+    Overloaded(String),
+    /// No answer in time: connecting, generating, or the task's deadline
+    Timeout(String),
+    /// Ollama can't be reached
+    Unavailable(String),
+    /// The request broke off after it was sent; not retried, Ollama may already be working on it
+    Request(String),
+    /// Ollama answered with something we can't use
+    BadResponse(String),
+    /// Any other error status from Ollama
+    Api { status: u16, message: String },
+    /// The request itself is wrong, retrying won't help
+    InvalidRequest(String),
+}
+
+impl RuntimeError {
+    /// Errors that may go away on their own: a busy or (re)starting Ollama.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, RuntimeError::Overloaded(_) | RuntimeError::Unavailable(_))
+    }
+
+    /// Classifies an error status; `model` names the model the request was for.
+    pub(crate) fn from_status(status: reqwest::StatusCode, message: String, model: Option<&str>) -> Self {
+        match status.as_u16() {
+            404 => RuntimeError::ModelNotFound(model.map_or(message, String::from)),
+            429 | 503 => RuntimeError::Overloaded(message),
+            400 => RuntimeError::InvalidRequest(message),
+            status => RuntimeError::Api { status, message },
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::ModelNotFound(model) => write!(f, "Model {} not found", model),
+            RuntimeError::Overloaded(e) => write!(f, "Overloaded: {}", e),
+            RuntimeError::Timeout(e) => write!(f, "Timed out: {}", e),
+            RuntimeError::Unavailable(e) => write!(f, "Ollama unavailable: {}", e),
+            RuntimeError::Request(e) => write!(f, "Request to Ollama failed: {}", e),
+            RuntimeError::BadResponse(e) => write!(f, "Bad response from Ollama: {}", e),
+            RuntimeError::Api { status, message } => write!(f, "Ollama API error {}: {}", status, message),
+            RuntimeError::InvalidRequest(e) => write!(f, "Invalid request: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<reqwest::Error> for RuntimeError {
+    fn from(e: reqwest::Error) -> Self {
+        // Connection refused, connect timeout, DNS failure, ...
+        if e.is_connect() {
+            RuntimeError::Unavailable(e.to_string())
+        } else if e.is_timeout() {
+            RuntimeError::Timeout(e.to_string())
+        } else if e.is_decode() {
+            RuntimeError::BadResponse(e.to_string())
+        } else {
+            RuntimeError::Request(e.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for RuntimeError {
+    fn from(e: serde_json::Error) -> Self {
+        RuntimeError::BadResponse(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn classifies_error_statuses() {
+        let status = |code| RuntimeError::from_status(StatusCode::from_u16(code).unwrap(), "nope".to_string(), Some("llama3"));
+        assert!(matches!(status(404), RuntimeError::ModelNotFound(model) if model == "llama3"));
+        assert!(matches!(status(429), RuntimeError::Overloaded(_)));
+        assert!(matches!(status(503), RuntimeError::Overloaded(_)));
+        assert!(matches!(status(400), RuntimeError::InvalidRequest(_)));
+        assert!(matches!(status(500), RuntimeError::Api { status: 500, .. }));
+        let not_found = RuntimeError::from_status(StatusCode::NOT_FOUND, "no such model".to_string(), None);
+        assert!(matches!(not_found, RuntimeError::ModelNotFound(message) if message == "no such model"));
+    }
+
+    #[test]
+    fn retries_only_transient_errors() {
+        assert!(RuntimeError::Overloaded(String::new()).is_transient());
+        assert!(RuntimeError::Unavailable(String::new()).is_transient());
+        assert!(!RuntimeError::Request(String::new()).is_transient());
+        assert!(!RuntimeError::Timeout(String::new()).is_transient());
+        assert!(!RuntimeError::InvalidRequest(String::new()).is_transient());
+    }
+}
@@ -1,66 +1,424 @@
-use reqwest::Client;
+mod error;
+
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use xnet_core::{DynError, RuntimeInterface};
+use std::time::Duration;
+use xnet_core::{ChatMessage, ChatRequest, ChatRole, DynError, GenerationLimits, GenerationOptions, ModelCapability, ModelDetails, ModelInfo, RuntimeInterface};
 use async_trait::async_trait;
+use futures::stream::{self, Stream};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+
+pub use crate::error::RuntimeError;
+
+// Defaults for `OllamaRuntime::new`
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_GENERATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Listing, showing and deleting models should never take long
+const METADATA_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Clone)]
 pub struct OllamaRuntime {
     client: Client,
     base_url: String,
+    limits: GenerationLimits,
+    generation_timeout: Duration,
+    retry: RetryPolicy,
+}
+
+/// How transient failures (Ollama busy, restarting or not up yet) are retried: up to
+/// `max_retries` more attempts, waiting twice as long after each, up to `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_retries: 3, initial_backoff: Duration::from_millis(500), max_backoff: Duration::from_secs(8) }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff.saturating_mul(1 << attempt.min(16)).min(self.max_backoff)
+    }
+}
+
+/// One line of the `/api/pull` progress stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullProgress {
+    pub status: String,
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+}
+
+/// A model currently loaded into memory (`/api/ps`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunningModel {
+    pub name: String,
+    pub digest: String,
+    pub size: u64,
+    pub size_vram: u64,
+    pub expires_at: Option<String>,
 }
 
 impl OllamaRuntime {
     pub fn new(base_url: impl Into<String>) -> Self {
         Self {
-            client: Client::new(),
+            client: build_client(DEFAULT_CONNECT_TIMEOUT),
             base_url: base_url.into(),
+            limits: GenerationLimits::default(),
+            generation_timeout: DEFAULT_GENERATION_TIMEOUT,
+            retry: RetryPolicy::default(),
         }
     }
 
-    pub async fn list_models(&self) -> Result<Vec<String>, DynError> {
-        let url = format!("{}/api/tags", self.base_url);
-        let res = self.client.get(&url).send().await?;
-        
-        if !res.status().is_success() {
-            return Err(format!("Ollama API error: {}", res.status()).into());
+    /// `connect` bounds reaching Ollama, `generation` a whole generate, chat or embed call.
+    pub fn with_timeouts(mut self, connect: Duration, generation: Duration) -> Self {
+        self.client = build_client(connect);
+        self.generation_timeout = generation;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Caps the options of every request sent through this runtime.
+    pub fn with_limits(mut self, limits: GenerationLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The options actually used for a request, after the limits are applied.
+    pub fn effective_options(&self, options: &GenerationOptions) -> GenerationOptions {
+        options.clamp(&self.limits)
+    }
+
+    /// Maps our options onto Ollama's `options` object, after applying the worker limits.
+    fn ollama_options(&self, options: &GenerationOptions, stop: &[String]) -> serde_json::Value {
+        let options = options.clamp(&self.limits);
+        let mut mapped = serde_json::Map::new();
+        if let Some(v) = options.temperature { mapped.insert("temperature".into(), json!(v)); }
+        if let Some(v) = options.top_p { mapped.insert("top_p".into(), json!(v)); }
+        if let Some(v) = options.top_k { mapped.insert("top_k".into(), json!(v)); }
+        if let Some(v) = options.seed { mapped.insert("seed".into(), json!(v)); }
+        if let Some(v) = options.max_tokens { mapped.insert("num_predict".into(), json!(v)); }
+        if let Some(v) = options.repeat_penalty { mapped.insert("repeat_penalty".into(), json!(v)); }
+        if let Some(v) = options.context_window { mapped.insert("num_ctx".into(), json!(v)); }
+        if !stop.is_empty() { mapped.insert("stop".into(), json!(stop)); }
+        serde_json::Value::Object(mapped)
+    }
+
+    /// Sends the request `build` makes, retrying transient failures with backoff. Error
+    /// statuses become `RuntimeError`s; `model` names the model for not-found errors.
+    async fn send(&self, build: impl Fn() -> RequestBuilder, model: Option<&str>) -> Result<Response, RuntimeError> {
+        let mut attempt = 0;
+        loop {
+            let error = match build().send().await {
+                Ok(res) if res.status().is_success() => return Ok(res),
+                Ok(res) => {
+                    let status = res.status();
+                    RuntimeError::from_status(status, error_message(res).await, model)
+                }
+                Err(e) => RuntimeError::from(e),
+            };
+            if !error.is_transient() || attempt >= self.retry.max_retries {
+                return Err(error);
+            }
+            let backoff = self.retry.backoff(attempt);
+            tracing::warn!(attempt = attempt + 1, ?backoff, "{}, retrying", error);
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
         }
+    }
+
+    async fn get_json(&self, path: &str) -> Result<serde_json::Value, RuntimeError> {
+        let url = format!("{}{}", self.base_url, path);
+        let res = self.send(|| self.client.get(&url).timeout(METADATA_TIMEOUT), None).await?;
+        Ok(res.json().await?)
+    }
+
+    async fn post_json(&self, path: &str, body: &serde_json::Value, timeout: Duration, model: Option<&str>) -> Result<serde_json::Value, RuntimeError> {
+        let url = format!("{}{}", self.base_url, path);
+        let res = self.send(|| self.client.post(&url).json(body).timeout(timeout), model).await?;
+        Ok(res.json().await?)
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<String>, RuntimeError> {
+        let models = self.list_model_info().await?
+            .into_iter()
+            .map(|m| m.name)
+            .collect();
+
+        Ok(models)
+    }
+
+    /// Like `list_models`, but keeps the digest, size and details Ollama reports.
+    pub async fn list_model_info(&self) -> Result<Vec<ModelInfo>, RuntimeError> {
+        let payload = self.get_json("/api/tags").await?;
+        let models = payload["models"]
+            .as_array()
+            .ok_or_else(|| RuntimeError::BadResponse("no models in /api/tags".to_string()))?
+            .iter()
+            .filter_map(|m| {
+                Some(ModelInfo {
+                    name: m["name"].as_str()?.to_string(),
+                    digest: m["digest"].as_str().unwrap_or_default().to_string(),
+                    size: m["size"].as_u64().unwrap_or(0),
+                    details: parse_details(&m["details"]),
+                    capabilities: Vec::new(), // Only reported by /api/show
+                })
+            })
+            .collect();
+
+        Ok(models)
+    }
+
+    /// Full metadata for one installed model (`/api/show`).
+    /// The digest is not part of the show response, so it is looked up from `/api/tags`.
+    pub async fn show_model(&self, name: &str) -> Result<ModelInfo, RuntimeError> {
+        let (details, capabilities) = self.show_details(name).await?;
+
+        let listed = self.list_model_info().await?
+            .into_iter()
+            .find(|m| m.name == name || m.name == format!("{}:latest", name));
+
+        Ok(ModelInfo {
+            name: name.to_string(),
+            digest: listed.as_ref().map(|m| m.digest.clone()).unwrap_or_default(),
+            size: listed.map(|m| m.size).unwrap_or(0),
+            details,
+            capabilities,
+        })
+    }
+
+    /// Every installed model with full details and capabilities, as advertised to the network.
+    pub async fn describe_models(&self) -> Result<Vec<ModelInfo>, RuntimeError> {
+        let mut models = self.list_model_info().await?;
+        for model in models.iter_mut() {
+            let (details, capabilities) = self.show_details(&model.name).await?;
+            model.details = details;
+            model.capabilities = capabilities;
+        }
+        Ok(models)
+    }
+
+    async fn show_details(&self, name: &str) -> Result<(ModelDetails, Vec<ModelCapability>), RuntimeError> {
+        let payload = self.post_json("/api/show", &json!({ "model": name }), METADATA_TIMEOUT, Some(name)).await?;
+        let mut details = parse_details(&payload["details"]);
+        details.parameters = payload["parameters"].as_str().map(String::from);
+
+        // model_info keys are prefixed by architecture, e.g. "llama.context_length"
+        details.context_length = payload["model_info"]
+            .as_object()
+            .and_then(|info| {
+                info.iter()
+                    .find(|(k, _)| k.ends_with(".context_length"))
+                    .and_then(|(_, v)| v.as_u64())
+            });
+
+        // Older Ollama versions don't report capabilities; assume a plain completion model
+        let capabilities = match payload["capabilities"].as_array() {
+            Some(list) => list.iter()
+                .filter_map(|c| match c.as_str()? {
+                    "completion" => Some(ModelCapability::Completion),
+                    "embedding" => Some(ModelCapability::Embedding),
+                    "vision" => Some(ModelCapability::Vision),
+                    _ => None,
+                })
+                .collect(),
+            None => vec![ModelCapability::Completion],
+        };
+
+        Ok((details, capabilities))
+    }
+
+    /// Starts downloading a model and yields progress updates until Ollama reports "success".
+    /// Downloads take as long as they take, so only connecting is bounded.
+    pub async fn pull_model(&self, name: &str) -> Result<impl Stream<Item = Result<PullProgress, RuntimeError>> + use<>, RuntimeError> {
+        let url = format!("{}/api/pull", self.base_url);
+        let body = json!({ "model": name, "stream": true });
+        let res = self.send(|| self.client.post(&url).json(&body), Some(name)).await?;
+
+        // The body is newline-delimited JSON; chunks don't necessarily align with lines.
+        let progress = stream::unfold((res, Vec::<u8>::new(), false), |(mut res, mut buf, done)| async move {
+            if done {
+                return None;
+            }
+            loop {
+                if let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buf.drain(..=pos).collect();
+                    if line.iter().all(|b| b.is_ascii_whitespace()) {
+                        continue;
+                    }
+                    return Some((parse_progress(&line), (res, buf, false)));
+                }
+                match res.chunk().await {
+                    Ok(Some(chunk)) => buf.extend_from_slice(&chunk),
+                    Ok(None) if buf.iter().all(|b| b.is_ascii_whitespace()) => return None,
+                    Ok(None) => {
+                        let line = std::mem::take(&mut buf);
+                        return Some((parse_progress(&line), (res, buf, true)));
+                    }
+                    Err(e) => return Some((Err(RuntimeError::from(e)), (res, buf, true))),
+                }
+            }
+        });
+
+        Ok(progress)
+    }
+
+    pub async fn delete_model(&self, name: &str) -> Result<(), RuntimeError> {
+        let url = format!("{}/api/delete", self.base_url);
+        let body = json!({ "model": name });
+        self.send(|| self.client.delete(&url).json(&body).timeout(METADATA_TIMEOUT), Some(name)).await?;
+        Ok(())
+    }
 
-        let payload: serde_json::Value = res.json().await?;
+    pub async fn running_models(&self) -> Result<Vec<RunningModel>, RuntimeError> {
+        let payload = self.get_json("/api/ps").await?;
         let models = payload["models"]
             .as_array()
-            .ok_or("Invalid models response")?
+            .ok_or_else(|| RuntimeError::BadResponse("no models in /api/ps".to_string()))?
             .iter()
-            .filter_map(|m| m["name"].as_str().map(String::from))
+            .filter_map(|m| {
+                Some(RunningModel {
+                    name: m["name"].as_str()?.to_string(),
+                    digest: m["digest"].as_str().unwrap_or_default().to_string(),
+                    size: m["size"].as_u64().unwrap_or(0),
+                    size_vram: m["size_vram"].as_u64().unwrap_or(0),
+                    expires_at: m["expires_at"].as_str().map(String::from),
+                })
+            })
             .collect();
 
         Ok(models)
     }
 }
 
+fn parse_details(details: &serde_json::Value) -> ModelDetails {
+    ModelDetails {
+        format: details["format"].as_str().map(String::from),
+        family: details["family"].as_str().map(String::from),
+        parameter_size: details["parameter_size"].as_str().map(String::from),
+        quantization_level: details["quantization_level"].as_str().map(String::from),
+        ..Default::default()
+    }
+}
+
+fn build_client(connect_timeout: Duration) -> Client {
+    Client::builder()
+        .connect_timeout(connect_timeout)
+        .build()
+        .expect("HTTP client builds")
+}
+
+/// Ollama puts the reason for an error status in `{"error": "..."}`.
+async fn error_message(res: Response) -> String {
+    let status = res.status();
+    match res.json::<serde_json::Value>().await {
+        Ok(body) => body["error"].as_str().map_or_else(|| status.to_string(), String::from),
+        Err(_) => status.to_string(),
+    }
+}
+
+fn encode_images(images: &[Vec<u8>]) -> Vec<String> {
+    images.iter().map(|data| BASE64.encode(data)).collect()
+}
+
+fn parse_progress(line: &[u8]) -> Result<PullProgress, RuntimeError> {
+    let payload: serde_json::Value = serde_json::from_slice(line)?;
+    // Failures after the download started arrive in the stream, behind a 200
+    if let Some(err) = payload["error"].as_str() {
+        return Err(RuntimeError::Api { status: 200, message: format!("pull failed: {}", err) });
+    }
+    Ok(serde_json::from_value(payload)?)
+}
+
 #[async_trait]
 impl RuntimeInterface for OllamaRuntime {
-    async fn generate(&self, model: &str, prompt: &str) -> Result<String, DynError> {
-        let url = format!("{}/api/generate", self.base_url);
-        let body = json!({
+    async fn generate(&self, model: &str, prompt: &str, images: &[Vec<u8>], options: &GenerationOptions) -> Result<String, DynError> {
+        let mut body = json!({
             "model": model,
             "prompt": prompt,
-            "stream": false
+            "stream": false,
+            "options": self.ollama_options(options, &[])
         });
-
-        let res = self.client.post(&url)
-            .json(&body)
-            .send()
-            .await?;
-
-        if !res.status().is_success() {
-             return Err(format!("Ollama API error: {}", res.status()).into());
+        if !images.is_empty() {
+            body["images"] = json!(encode_images(images));
         }
 
-        let payload: serde_json::Value = res.json().await?;
+        let payload = self.post_json("/api/generate", &body, self.generation_timeout, Some(model)).await?;
         let response_text = payload["response"].as_str()
-            .ok_or("Invalid response format")?
+            .ok_or_else(|| RuntimeError::BadResponse("no response text".to_string()))?
             .to_string();
 
         Ok(response_text)
     }
+
+    async fn chat(&self, model: &str, request: &ChatRequest, images: &[Vec<u8>], options: &GenerationOptions) -> Result<ChatMessage, DynError> {
+        let mut messages = Vec::with_capacity(request.messages.len() + 1);
+        if let Some(system) = &request.system {
+            messages.push(json!(ChatMessage::new(ChatRole::System, system.clone())));
+        }
+        messages.extend(request.messages.iter().map(|m| json!(m)));
+
+        // Ollama takes images per message; attach them to the latest user turn
+        if !images.is_empty() {
+            let last_user = request.messages.iter().rposition(|m| m.role == ChatRole::User)
+                .ok_or_else(|| RuntimeError::InvalidRequest("chat images need a user message to attach to".to_string()))?;
+            let index = last_user + usize::from(request.system.is_some());
+            messages[index]["images"] = json!(encode_images(images));
+        }
+
+        let body = json!({
+            "model": model,
+            "messages": messages,
+            "stream": false,
+            "options": self.ollama_options(options, &request.stop)
+        });
+
+        let payload = self.post_json("/api/chat", &body, self.generation_timeout, Some(model)).await?;
+        let message: ChatMessage = serde_json::from_value(payload["message"].clone())
+            .map_err(RuntimeError::from)?;
+
+        Ok(message)
+    }
+
+    async fn embed(&self, model: &str, input: &[String], options: &GenerationOptions) -> Result<Vec<Vec<f32>>, DynError> {
+        let body = json!({
+            "model": model,
+            "input": input,
+            "options": self.ollama_options(options, &[])
+        });
+
+        let payload = self.post_json("/api/embed", &body, self.generation_timeout, Some(model)).await?;
+        let embeddings: Vec<Vec<f32>> = serde_json::from_value(payload["embeddings"].clone())
+            .map_err(RuntimeError::from)?;
+
+        Ok(embeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+        let waits: Vec<u64> = (0..6).map(|attempt| policy.backoff(attempt).as_millis() as u64).collect();
+        assert_eq!(waits, [500, 1000, 2000, 4000, 8000, 8000]);
+        assert_eq!(policy.backoff(u32::MAX), policy.max_backoff);
+    }
 }
@@ -0,0 +1,136 @@
+#!/bin/bash
+# NAT Traversal Verification Script
+#
+# Builds a small network out of Linux network namespaces (needs root, iproute2 and iptables):
+#
+#   xnet-relay 10.99.0.10  ──┐
+#                            ├── bridge in xnet-wan (10.99.0.0/24, the "internet")
+#   xnet-nat1  10.99.0.11  ──┤        xnet-nat2  10.99.0.12
+#      │ MASQUERADE          │           │ MASQUERADE
+#   xnet-a  192.168.1.2      └────    xnet-b  192.168.2.2
+#
+# The relay is publicly reachable. Nodes A and B sit behind their own NAT, should find out
+# they are private (AutoNAT), reserve a slot on the relay and hole punch to each other (DCUtR).
+#
+# Usage: sudo ./verify_nat.sh [path/to/xnet]   (default: target/release/xnet)
+
+set -e
+
+XNET=${1:-target/release/xnet}
+# Relayed circuits are only logged at debug
+export XNET_LOG=info,xnet_network=debug
+WORK=$(mktemp -d)
+NAMESPACES="xnet-wan xnet-relay xnet-nat1 xnet-nat2 xnet-a xnet-b"
+
+cleanup() {
+    for pid in $(jobs -p); do kill "$pid" 2>/dev/null || true; done
+    for ns in $NAMESPACES; do ip netns del "$ns" 2>/dev/null || true; done
+    echo "Logs kept in $WORK"
+}
+trap cleanup EXIT
+
+if [ ! -x "$XNET" ]; then
+    echo "❌ $XNET not found, build it with: cargo build --release -p cli"
+    exit 1
+fi
+
+echo "=== NAT Traversal Verification ==="
+echo ""
+echo "1. Creating namespaces..."
+for ns in $NAMESPACES; do
+    ip netns add "$ns"
+    ip -n "$ns" link set lo up
+done
+
+ip -n xnet-wan link add br0 type bridge
+ip -n xnet-wan link set br0 up
+
+# Attach a namespace to the WAN bridge with the given address
+wan_link() {
+    local ns=$1 addr=$2
+    ip link add "$ns-wan" type veth peer name "$ns-br"
+    ip link set "$ns-wan" netns "$ns"
+    ip link set "$ns-br" netns xnet-wan
+    ip -n xnet-wan link set "$ns-br" master br0 up
+    ip -n "$ns" addr add "$addr/24" dev "$ns-wan"
+    ip -n "$ns" link set "$ns-wan" up
+}
+
+# Put a node behind a NAT router
+nat_link() {
+    local router=$1 node=$2 subnet=$3
+    ip link add "$node-lan" type veth peer name "$router-lan"
+    ip link set "$node-lan" netns "$node"
+    ip link set "$router-lan" netns "$router"
+    ip -n "$router" addr add "$subnet.1/24" dev "$router-lan"
+    ip -n "$router" link set "$router-lan" up
+    ip -n "$node" addr add "$subnet.2/24" dev "$node-lan"
+    ip -n "$node" link set "$node-lan" up
+    ip -n "$node" route add default via "$subnet.1"
+    ip netns exec "$router" sysctl -qw net.ipv4.ip_forward=1
+    ip netns exec "$router" iptables -t nat -A POSTROUTING -o "$router-wan" -j MASQUERADE
+}
+
+wan_link xnet-relay 10.99.0.10
+wan_link xnet-nat1 10.99.0.11
+wan_link xnet-nat2 10.99.0.12
+nat_link xnet-nat1 xnet-a 192.168.1
+nat_link xnet-nat2 xnet-b 192.168.2
+echo "✅ Network ready"
+
+# Private test addresses count as reachable here
+for node in relay a b a2; do
+    mkdir -p "$WORK/$node"
+    cat > "$WORK/$node/config.toml" <<EOF
+listen_addrs = ["/ip4/0.0.0.0/tcp/0", "/ip4/0.0.0.0/udp/0/quic-v1"]
+
+[nat]
+only_global_ips = false
+EOF
+done
+
+# The relay needs a fixed port for the bootnode address
+sed -i 's|/tcp/0"|/tcp/4001"|; s|/udp/0/|/udp/4001/|' "$WORK/relay/config.toml"
+
+echo ""
+echo "2. Starting relay..."
+"$XNET" key generate --data-dir "$WORK/relay" > /dev/null
+RELAY_ID=$("$XNET" --json key show --data-dir "$WORK/relay" | sed -n 's/.*"peer_id": "\(.*\)".*/\1/p')
+ip netns exec xnet-relay "$XNET" daemon --data-dir "$WORK/relay" > "$WORK/relay.log" 2>&1 &
+sleep 2
+BOOTNODE="/ip4/10.99.0.10/tcp/4001/p2p/$RELAY_ID"
+echo "✅ Relay $BOOTNODE"
+
+echo ""
+echo "3. Starting NATed nodes A and B..."
+ip netns exec xnet-a "$XNET" daemon --data-dir "$WORK/a" --bootnode "$BOOTNODE" > "$WORK/a.log" 2>&1 &
+ip netns exec xnet-b "$XNET" daemon --data-dir "$WORK/b" --bootnode "$BOOTNODE" > "$WORK/b.log" 2>&1 &
+
+echo ""
+echo "4. Waiting for AutoNAT and relay reservations (up to 60s)..."
+for _ in $(seq 60); do
+    if grep -q "Reserved a relay slot" "$WORK/a.log" && grep -q "Reserved a relay slot" "$WORK/b.log"; then
+        break
+    fi
+    sleep 1
+done
+
+check() {
+    if grep -q "$2" "$WORK/$1.log"; then echo "✅ $1: $3"; else echo "❌ $1: $3"; fi
+}
+check a "to Private" "detected it is behind NAT"
+check b "to Private" "detected it is behind NAT"
+check a "Reserved a relay slot" "reserved a relay slot"
+check b "Reserved a relay slot" "reserved a relay slot"
+check relay "Relaying for" "relaying for NATed peers"
+
+echo ""
+echo "5. Connecting A to B through the relay..."
+B_ID=$("$XNET" --json key show --data-dir "$WORK/b" | sed -n 's/.*"peer_id": "\(.*\)".*/\1/p')
+ip netns exec xnet-a "$XNET" daemon --data-dir "$WORK/a2" --api-addr 127.0.0.1:3031 --bootnode "$BOOTNODE/p2p-circuit/p2p/$B_ID" \
+    > "$WORK/a2.log" 2>&1 &
+sleep 20
+check a2 "Hole punch succeeded.*peer=$B_ID" "upgraded the relayed connection to a direct one"
+
+echo ""
+echo "=== Verification Complete ==="